@@ -0,0 +1,192 @@
+#[macro_use]
+extern crate criterion;
+extern crate nep;
+
+use std::fs;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use criterion::{BenchmarkId, Criterion};
+
+use nep::buffer::Buffer;
+
+const SIZES: [usize; 2] = [200_000, 1_000_000];
+const LINE: &str = "the quick brown fox jumps over the lazy dog, again";
+
+/// The storage `Buffer` used before the rope: one `Vec<char>` per line, kept
+/// in a single `Vec`.
+struct VecBuffer {
+    lines: Vec<Vec<char>>,
+}
+
+/// The operations being measured, each paired with a way to take it back
+/// again between iterations.
+trait Storage {
+    fn insert(&mut self, line: usize, column: usize, c: char);
+    fn undo_insert(&mut self, line: usize, column: usize);
+    fn split_line(&mut self, line: usize, column: usize);
+    fn undo_split(&mut self, line: usize, column: usize);
+    fn merge_line(&mut self, line: usize) -> usize;
+    fn undo_merge(&mut self, line: usize, column: usize);
+    fn len(&self) -> usize;
+}
+
+impl Storage for VecBuffer {
+    fn insert(&mut self, line: usize, column: usize, c: char) {
+        self.lines[line].insert(column, c);
+    }
+
+    fn undo_insert(&mut self, line: usize, column: usize) {
+        self.lines[line].remove(column);
+    }
+
+    fn split_line(&mut self, line: usize, column: usize) {
+        let len = self.lines[line].len();
+        let new_line = self.lines[line].drain(column..len).collect();
+        self.lines.insert(line + 1, new_line);
+    }
+
+    fn undo_split(&mut self, line: usize, _: usize) {
+        self.merge_line(line + 1);
+    }
+
+    fn merge_line(&mut self, line: usize) -> usize {
+        let mut to_merge = self.lines.remove(line);
+        let merging = &mut self.lines[line - 1];
+        let len = merging.len();
+        merging.append(&mut to_merge);
+        len
+    }
+
+    fn undo_merge(&mut self, line: usize, column: usize) {
+        self.split_line(line - 1, column);
+    }
+
+    fn len(&self) -> usize {
+        self.lines.len()
+    }
+}
+
+impl Storage for Buffer {
+    fn insert(&mut self, line: usize, column: usize, c: char) {
+        Buffer::insert(self, line, column, c);
+    }
+
+    fn undo_insert(&mut self, _: usize, _: usize) {
+        self.undo();
+    }
+
+    fn split_line(&mut self, line: usize, column: usize) {
+        Buffer::split_line(self, line, column);
+    }
+
+    fn undo_split(&mut self, _: usize, _: usize) {
+        self.undo();
+    }
+
+    fn merge_line(&mut self, line: usize) -> usize {
+        Buffer::merge_line(self, line).unwrap()
+    }
+
+    fn undo_merge(&mut self, _: usize, _: usize) {
+        self.undo();
+    }
+
+    fn len(&self) -> usize {
+        Buffer::len(self)
+    }
+}
+
+fn vec_buffer(size: usize) -> VecBuffer {
+    VecBuffer {
+        lines: (0..size).map(|_| LINE.chars().collect()).collect(),
+    }
+}
+
+fn rope_buffer(size: usize) -> Buffer {
+    let path = std::env::temp_dir().join(format!("nep-bench-{}-{}", std::process::id(), size));
+    let mut contents = String::with_capacity(size * (LINE.len() + 1));
+    for _ in 0..size {
+        contents.push_str(LINE);
+        contents.push('\n');
+    }
+    fs::write(&path, contents).unwrap();
+    let buffer = Buffer::from(path.to_string_lossy().into_owned()).unwrap();
+    fs::remove_file(&path).unwrap();
+    buffer
+}
+
+fn positions(len: usize) -> [(&'static str, usize); 3] {
+    [("start", 1), ("middle", len / 2), ("end", len - 2)]
+}
+
+/// Times `op` alone, running `undo` between iterations (untimed) so the
+/// buffer keeps the same shape, and history stays small, for the whole run.
+fn time<S, F, G>(storage: &mut S, iters: u64, mut op: F, mut undo: G) -> Duration
+where
+    F: FnMut(&mut S),
+    G: FnMut(&mut S),
+{
+    let mut total = Duration::new(0, 0);
+    for _ in 0..iters {
+        let start = Instant::now();
+        op(black_box(&mut *storage));
+        total += start.elapsed();
+        undo(storage);
+    }
+    total
+}
+
+fn bench_storage<S: Storage>(c: &mut Criterion, name: &str, build: fn(usize) -> S) {
+    for &size in SIZES.iter() {
+        let mut storage = build(size);
+        let column = LINE.len() / 2;
+
+        for &(at, line) in positions(storage.len()).iter() {
+            let id = BenchmarkId::new(format!("{}/{}", name, at), size);
+
+            c.benchmark_group("insert").bench_function(id.clone(), |b| {
+                b.iter_custom(|iters| {
+                    time(
+                        &mut storage,
+                        iters,
+                        |s| s.insert(line, column, 'x'),
+                        |s| s.undo_insert(line, column),
+                    )
+                })
+            });
+
+            c.benchmark_group("split").bench_function(id.clone(), |b| {
+                b.iter_custom(|iters| {
+                    time(
+                        &mut storage,
+                        iters,
+                        |s| s.split_line(line, column),
+                        |s| s.undo_split(line, column),
+                    )
+                })
+            });
+
+            c.benchmark_group("merge").bench_function(id, |b| {
+                b.iter_custom(|iters| {
+                    time(
+                        &mut storage,
+                        iters,
+                        |s| {
+                            black_box(s.merge_line(line));
+                        },
+                        |s| s.undo_merge(line, LINE.len()),
+                    )
+                })
+            });
+        }
+    }
+}
+
+fn storage(c: &mut Criterion) {
+    bench_storage(c, "vec", vec_buffer);
+    bench_storage(c, "rope", rope_buffer);
+}
+
+criterion_group!(benches, storage);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+//! Runs the operations read from each input against `Buffer` and `Cursor`
+//! and the string model the property tests use, so any panic or any
+//! disagreement with the model is a crash.
+//!
+//! Run with `cargo fuzz run edits`.
+
+#![no_main]
+
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate nep;
+
+#[path = "../../tests/model/mod.rs"]
+mod model;
+
+fuzz_target!(|bytes: &[u8]| {
+    model::check(&model::decode(bytes));
+});
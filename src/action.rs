@@ -0,0 +1,236 @@
+use super::buffer::LineEnding;
+use super::mode::{Motion, Operator};
+use super::window::Direction;
+
+/// The largest count an action can be given, however many digits are
+/// typed or written.
+pub const MAX_COUNT: usize = 99_999;
+
+/// Something the editor can be asked to do, whether by a key, a script, a
+/// plugin or the command line. Counts say how many times to do it.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Action {
+    /// Quits, unless a buffer has unsaved changes.
+    Quit,
+    /// Quits, throwing away any unsaved changes.
+    ForceQuit,
+    /// Saves the buffer, asking for a file name if it has none.
+    Save,
+    /// Saves the buffer to a file, as its file from now on, or else asks
+    /// for the name of one.
+    SaveAs(Option<String>),
+    /// Writes the buffer to a file, naming the buffer after it if it had no
+    /// file, or else leaving its file as it was.
+    Write(String),
+    /// Saves the buffer, then quits once it is saved.
+    SaveQuit,
+    /// Opens a file, or else asks for the name of one.
+    Open(Option<String>),
+    Close,
+    Undo,
+    Redo,
+    /// Runs a command provided by a script or a plugin.
+    Run(String),
+    /// Opens the command line, to type a command such as `w` or `set wrap`.
+    Command,
+    /// Converts the buffer to end its lines as given, or else shows how
+    /// its file is laid out.
+    LineEndings(Option<LineEnding>),
+    /// Changes or shows a setting, written as for `Config::option`.
+    Set(String),
+
+    /// Goes back to normal mode from insert or visual mode.
+    Normal,
+    Insert,
+    Append,
+    InsertStart,
+    AppendEnd,
+    OpenBelow,
+    OpenAbove,
+    Visual,
+
+    /// Moves the main cursor.
+    Move(Motion, usize),
+    /// Moves every cursor one step, as the arrow keys do in insert mode.
+    MoveEach(Motion),
+    /// Asks for text to search forward for, moving to the first match as
+    /// it is typed.
+    Search,
+    SearchBackward,
+    /// Moves to a later match of the last search, in the direction it went.
+    NextMatch(usize),
+    /// Moves to an earlier match of the last search, against its direction.
+    PreviousMatch(usize),
+    /// Replaces what a regular expression matches in the selected lines, or
+    /// else the whole buffer, given a substitution written as `/a/b/` or
+    /// else asking for one.
+    Substitute(Option<String>),
+    /// Replaces what a regular expression matches in the cursor's line, or
+    /// in every line, given a substitution, or else makes the last one
+    /// again.
+    SubstituteLines {
+        every_line: bool,
+        written: Option<String>,
+    },
+
+    /// Types a character at every cursor.
+    InsertChar(char),
+    /// Types an indent at every cursor.
+    Indent,
+    SplitLine,
+    /// Deletes the character before every cursor, joining lines at the
+    /// start of one.
+    Backspace,
+    DeleteChar(usize),
+    Operate(Operator, Motion, usize),
+    /// An operator doubled, as in `dd`, acting on whole lines from the
+    /// cursor down.
+    OperateLines(Operator, usize),
+    /// An operator acting on the visual selection.
+    OperateSelection(Operator),
+    Paste(usize),
+    PasteBefore(usize),
+
+    /// Adds cursors on the lines above or below, moving the main cursor to
+    /// the furthest one added.
+    CursorAbove(usize),
+    CursorBelow(usize),
+    /// Adds a cursor at the next match of the word under the main cursor.
+    CursorAtMatch,
+    /// Drops every cursor but the main one, and stops highlighting the
+    /// matches of the last search.
+    SingleCursor,
+
+    NextBuffer(usize),
+    PreviousBuffer(usize),
+    /// Splits the focused window in two, one half above the other.
+    Split,
+    /// Splits the focused window in two, side by side.
+    VerticalSplit,
+    CloseWindow,
+    NextWindow,
+    Focus(Direction),
+}
+
+impl Action {
+    /// Reads an action written as its name, followed for some by an
+    /// argument: a count, which defaults to one; a line number for
+    /// `goto_line`, which defaults to the last line; a character for
+    /// `insert_char`, where a newline splits the line and other control
+    /// characters but tab are refused; a command for `run`; a file name
+    /// for `write`, and one which may be left out for `save_as` and `open`;
+    /// a setting for `set`; a substitution, which may be left out, for
+    /// `substitute`, `substitute_line` and `substitute_every_line`; `lf` or
+    /// `crlf`, which may be left out, for `line_endings`; or a motion for
+    /// `move_each`, such as `move_each move_up`.
+    pub fn parse(written: &str) -> Option<Action> {
+        let mut parts = written.splitn(2, ' ');
+        let name = parts.next()?;
+        let argument = parts.next();
+        let count = match argument.map(str::parse) {
+            None => Some(1),
+            Some(Ok(0)) | Some(Err(_)) => None,
+            Some(Ok(count)) => Some(std::cmp::min(count, MAX_COUNT)),
+        };
+        let none = argument.is_none();
+        let text = argument
+            .filter(|argument| !argument.is_empty())
+            .map(str::to_string);
+
+        let action = match name {
+            "quit" if none => Action::Quit,
+            "force_quit" if none => Action::ForceQuit,
+            "save" if none => Action::Save,
+            "save_as" => Action::SaveAs(text),
+            "write" => Action::Write(text?),
+            "save_quit" if none => Action::SaveQuit,
+            "open" => Action::Open(text),
+            "close" if none => Action::Close,
+            "undo" if none => Action::Undo,
+            "redo" if none => Action::Redo,
+            "command" if none => Action::Command,
+            "line_endings" => match argument {
+                None => Action::LineEndings(None),
+                Some(name) => Action::LineEndings(Some(LineEnding::parse(name)?)),
+            },
+            "run" => Action::Run(text?),
+            "set" => Action::Set(text?),
+            "normal" if none => Action::Normal,
+            "insert" if none => Action::Insert,
+            "append" if none => Action::Append,
+            "insert_start" if none => Action::InsertStart,
+            "append_end" if none => Action::AppendEnd,
+            "open_below" if none => Action::OpenBelow,
+            "open_above" if none => Action::OpenAbove,
+            "visual" if none => Action::Visual,
+            "search" if none => Action::Search,
+            "search_backward" if none => Action::SearchBackward,
+            "next_match" => Action::NextMatch(count?),
+            "previous_match" => Action::PreviousMatch(count?),
+            "substitute" => Action::Substitute(argument.map(str::to_string)),
+            "substitute_line" | "substitute_every_line" => Action::SubstituteLines {
+                every_line: name == "substitute_every_line",
+                written: argument.map(str::to_string),
+            },
+            "goto_line" => match argument {
+                None => Action::Move(Motion::Line(None), 1),
+                Some(_) => Action::Move(Motion::Line(Some(count?)), 1),
+            },
+            "insert_char" => {
+                let mut chars = argument?.chars();
+                match (chars.next(), chars.next()) {
+                    (Some('\n'), None) => Action::SplitLine,
+                    (Some(c), None) if c == '\t' || !c.is_control() => Action::InsertChar(c),
+                    _ => return None,
+                }
+            }
+            "indent" if none => Action::Indent,
+            "split_line" if none => Action::SplitLine,
+            "backspace" if none => Action::Backspace,
+            "delete_char" => Action::DeleteChar(count?),
+            "delete_line" => Action::OperateLines(Operator::Delete, count?),
+            "change_line" => Action::OperateLines(Operator::Change, count?),
+            "yank_line" => Action::OperateLines(Operator::Yank, count?),
+            "delete_selection" if none => Action::OperateSelection(Operator::Delete),
+            "change_selection" if none => Action::OperateSelection(Operator::Change),
+            "yank_selection" if none => Action::OperateSelection(Operator::Yank),
+            "paste" => Action::Paste(count?),
+            "paste_before" => Action::PasteBefore(count?),
+            "cursor_above" => Action::CursorAbove(count?),
+            "cursor_below" => Action::CursorBelow(count?),
+            "cursor_at_match" if none => Action::CursorAtMatch,
+            "single_cursor" if none => Action::SingleCursor,
+            "next_buffer" => Action::NextBuffer(count?),
+            "previous_buffer" => Action::PreviousBuffer(count?),
+            "split" if none => Action::Split,
+            "vertical_split" if none => Action::VerticalSplit,
+            "close_window" if none => Action::CloseWindow,
+            "next_window" if none => Action::NextWindow,
+            "focus_left" if none => Action::Focus(Direction::Left),
+            "focus_right" if none => Action::Focus(Direction::Right),
+            "focus_up" if none => Action::Focus(Direction::Up),
+            "focus_down" if none => Action::Focus(Direction::Down),
+            "move_each" => match Action::parse(argument?)? {
+                Action::Move(motion, 1) => Action::MoveEach(motion),
+                _ => return None,
+            },
+            _ => {
+                let motion = match name {
+                    "move_left" => Motion::Left,
+                    "move_right" => Motion::Right,
+                    "move_up" => Motion::Up,
+                    "move_down" => Motion::Down,
+                    "word_forward" => Motion::WordForward,
+                    "word_backward" => Motion::WordBackward,
+                    "word_end" => Motion::WordEnd,
+                    "line_start" if none => Motion::LineStart,
+                    "first_non_blank" if none => Motion::FirstNonBlank,
+                    "line_end" if none => Motion::LineEnd,
+                    _ => return None,
+                };
+                Action::Move(motion, count?)
+            }
+        };
+        Some(action)
+    }
+}
@@ -0,0 +1,181 @@
+//! Where the editor gets its events from and shows its frames: a real
+//! terminal, or a screen kept in memory for tests.
+
+use std::collections::VecDeque;
+use std::io;
+use std::io::Write;
+use std::os::unix::io::AsRawFd;
+
+use termion::event::Key;
+use termion::raw::{IntoRawMode, RawTerminal};
+use termion::screen::AlternateScreen;
+use termion::{clear, cursor, terminal_size};
+
+pub use super::event::Event;
+use super::event::Events;
+pub use super::grid::{Cell, Grid, Style};
+
+pub trait Backend {
+    /// The screen's size, in columns and rows.
+    fn size(&mut self) -> io::Result<(u16, u16)>;
+
+    /// Waits for the next event, or gives `None` once there will be no
+    /// more.
+    fn event(&mut self) -> Option<io::Result<Event>>;
+
+    /// Shows `frame`, which is the size last given by `size`, with the
+    /// cursor at `cursor`, a column and row counting from zero.
+    fn present(&mut self, frame: &Grid, cursor: (u16, u16)) -> io::Result<()>;
+
+    /// Whether saves should be finished before the next frame is drawn, so
+    /// that what is shown never depends on how long they take.
+    fn waits_for_saves(&self) -> bool {
+        false
+    }
+}
+
+/// The terminal the editor runs in, read through `input` and drawn on
+/// through `output`, which is switched to its alternate screen and raw mode
+/// until this is dropped.
+pub struct Terminal<R: AsRawFd, W: Write> {
+    events: Events<R>,
+    output: RawTerminal<AlternateScreen<W>>,
+    /// The frame the terminal is showing.
+    shown: Grid,
+}
+
+impl<R: AsRawFd, W: Write> Terminal<R, W> {
+    pub fn new(input: R, output: W) -> io::Result<Terminal<R, W>> {
+        Ok(Terminal {
+            // Resizes are listened for before anything is drawn, so none is
+            // missed.
+            events: Events::new(input)?,
+            output: AlternateScreen::from(output).into_raw_mode()?,
+            shown: Grid::new(0, 0),
+        })
+    }
+}
+
+impl<R: AsRawFd, W: Write> Backend for Terminal<R, W> {
+    fn size(&mut self) -> io::Result<(u16, u16)> {
+        terminal_size()
+    }
+
+    fn event(&mut self) -> Option<io::Result<Event>> {
+        self.events.next()
+    }
+
+    /// Writes only what differs from the last frame, with the cursor hidden
+    /// meanwhile so it is not seen jumping about. The terminal is cleared
+    /// first if its size has changed.
+    fn present(&mut self, frame: &Grid, (x, y): (u16, u16)) -> io::Result<()> {
+        if (frame.width(), frame.height()) != (self.shown.width(), self.shown.height()) {
+            write!(self.output, "{}", clear::All)?;
+            self.shown = Grid::new(frame.width() as u16, frame.height() as u16);
+        }
+
+        let mut output = vec![];
+        frame.diff(&self.shown, &mut output)?;
+        if !output.is_empty() {
+            write!(self.output, "{}", cursor::Hide)?;
+            self.output.write_all(&output)?;
+            self.shown.clone_from(frame);
+        }
+        write!(
+            self.output,
+            "{}{}",
+            cursor::Goto(x + 1, y + 1),
+            cursor::Show
+        )?;
+        self.output.flush()
+    }
+}
+
+/// A screen kept in memory, given its events by whoever made it, on which
+/// what the editor shows can be checked without a terminal.
+pub struct Headless {
+    size: (u16, u16),
+    /// Events still to be given, with the size each resize is to.
+    events: VecDeque<(Event, Option<(u16, u16)>)>,
+    screen: Grid,
+    cursor: (u16, u16),
+}
+
+impl Headless {
+    pub fn new(width: u16, height: u16) -> Headless {
+        Headless {
+            size: (width, height),
+            events: VecDeque::new(),
+            screen: Grid::new(width, height),
+            cursor: (0, 0),
+        }
+    }
+
+    /// Queues a key to be pressed.
+    pub fn press(&mut self, key: Key) {
+        self.events.push_back((Event::Key(key), None));
+    }
+
+    /// Queues every character of `text` to be typed.
+    pub fn type_text(&mut self, text: &str) {
+        for c in text.chars() {
+            self.press(Key::Char(c));
+        }
+    }
+
+    /// Queues the screen being resized.
+    pub fn resize(&mut self, width: u16, height: u16) {
+        self.events
+            .push_back((Event::Resize, Some((width, height))));
+    }
+
+    /// The last frame shown.
+    pub fn frame(&self) -> &Grid {
+        &self.screen
+    }
+
+    /// The text on the screen, one line per row with the spaces at the end
+    /// of each taken off.
+    pub fn screen(&self) -> String {
+        let mut screen = String::new();
+        for y in 0..self.screen.height() {
+            let row = (0..self.screen.width())
+                .map(|x| self.screen.cell(x, y))
+                .filter(|cell| !cell.is_continuation())
+                .map(|cell| cell.character)
+                .collect::<String>();
+            screen += row.trim_end();
+            screen.push('\n');
+        }
+        screen
+    }
+
+    /// Where the cursor is, as the column and row counting from zero.
+    pub fn cursor(&self) -> (u16, u16) {
+        self.cursor
+    }
+}
+
+impl Backend for Headless {
+    fn size(&mut self) -> io::Result<(u16, u16)> {
+        Ok(self.size)
+    }
+
+    fn event(&mut self) -> Option<io::Result<Event>> {
+        let (event, size) = self.events.pop_front()?;
+        if let Some(size) = size {
+            self.size = size;
+        }
+        Some(Ok(event))
+    }
+
+    fn present(&mut self, frame: &Grid, cursor: (u16, u16)) -> io::Result<()> {
+        self.screen.clone_from(frame);
+        self.cursor = cursor;
+        Ok(())
+    }
+
+    fn waits_for_saves(&self) -> bool {
+        true
+    }
+}
@@ -1,139 +1,619 @@
+use std::error;
+use std::fmt;
+use std::fs;
 use std::fs::{File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::path::PathBuf;
+use std::io;
+use std::io::{BufWriter, ErrorKind, Read, Write};
+use std::os::unix::fs::{fchown, MetadataExt};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::Chars;
 
+use super::history::{Edit, History};
+use super::rope::Rope;
+
+/// Why a buffer could not be opened or saved. Each variant carries the name
+/// of the file involved.
+#[derive(Debug)]
+pub enum Error {
+    IsDirectory(String),
+    /// The file is not UTF-8. Holds the line holding the first bad byte.
+    InvalidUtf8(String, usize),
+    Io(String, io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::IsDirectory(ref name) => write!(f, "{} is a directory", name),
+            Error::InvalidUtf8(ref name, line) => {
+                write!(f, "{} is not valid UTF-8 (line {})", name, line)
+            }
+            Error::Io(ref name, ref error) => write!(f, "{}: {}", name, error),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(_, ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// The byte order mark some files start with, as a character.
+const BOM: char = '\u{feff}';
+
+/// What each line of a file ends with.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineEnding {
+    /// `\n`, as on Unix.
+    Lf,
+    /// `\r\n`, as on Windows.
+    CrLf,
+}
+
+impl LineEnding {
+    /// Reads a line ending by its name, `lf` or `crlf`.
+    pub fn parse(name: &str) -> Option<LineEnding> {
+        match name {
+            "lf" => Some(LineEnding::Lf),
+            "crlf" => Some(LineEnding::CrLf),
+            _ => None,
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "lf",
+            LineEnding::CrLf => "crlf",
+        }
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+/// How a file is laid out besides the text of its lines, kept from when it
+/// was opened so that saving writes it back the same way.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Format {
+    pub line_ending: LineEnding,
+    /// Whether the last line has a line ending after it too.
+    pub final_newline: bool,
+    /// Whether the file starts with a byte order mark.
+    pub bom: bool,
+    /// Whether the file held no lines at all, so that a buffer left empty
+    /// is saved as an empty file rather than as a single empty line.
+    pub empty: bool,
+    /// Whether some lines but not all ended in `\r\n`, so that the `\r` of
+    /// those which did was kept in their text.
+    pub mixed: bool,
+}
+
+impl Default for Format {
+    fn default() -> Format {
+        Format {
+            line_ending: LineEnding::Lf,
+            final_newline: true,
+            bom: false,
+            empty: true,
+            mixed: false,
+        }
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} line endings", self.line_ending.name())?;
+        if !self.final_newline {
+            write!(f, ", no newline at the end")?;
+        }
+        if self.bom {
+            write!(f, ", byte order mark")?;
+        }
+        Ok(())
+    }
+}
+
+/// A line of text, stored as UTF-8. Positions are counted in characters.
+#[derive(Clone)]
 pub struct Line {
-    characters: Vec<char>,
+    text: String,
+    len: usize,
 }
 
 impl Line {
     fn new() -> Line {
-        Line { characters: vec![] }
+        Line {
+            text: String::new(),
+            len: 0,
+        }
     }
 
     fn from<T: Iterator<Item = char>>(t: T) -> Line {
+        let text = t.collect::<String>();
         Line {
-            characters: t.collect(),
+            len: text.chars().count(),
+            text,
         }
     }
 
     fn split(&mut self, index: usize) -> Line {
-        let len = self.len();
-        Line::from(self.characters.drain(index..len))
+        let offset = self.offset(index);
+        let text = self.text.split_off(offset);
+        let len = self.len - index;
+        self.len = index;
+        Line { text, len }
     }
 
-    pub fn borrow_chars(&self) -> &Vec<char> {
-        &self.characters
+    fn append(&mut self, other: Line) {
+        self.text.push_str(&other.text);
+        self.len += other.len;
+    }
+
+    /// Byte offset of the character at `index`.
+    fn offset(&self, index: usize) -> usize {
+        if self.len == self.text.len() {
+            return index;
+        }
+        self.text
+            .char_indices()
+            .nth(index)
+            .map_or(self.text.len(), |(offset, _)| offset)
     }
 
-    pub fn insert(&mut self, index: usize, c: char) {
-        self.characters.insert(index, c);
+    pub fn chars(&self) -> Chars<'_> {
+        self.text.chars()
     }
 
-    pub fn delete(&mut self, index: usize) {
-        self.characters.remove(index);
+    pub fn as_str(&self) -> &str {
+        &self.text
+    }
+
+    fn insert(&mut self, index: usize, text: &str) {
+        let offset = self.offset(index);
+        self.text.insert_str(offset, text);
+        self.len += text.chars().count();
+    }
+
+    fn delete(&mut self, index: usize, count: usize) -> String {
+        let start = self.offset(index);
+        let end = start
+            + self.text[start..]
+                .char_indices()
+                .nth(count)
+                .map_or(self.text.len() - start, |(offset, _)| offset);
+        self.len -= count;
+        self.text.drain(start..end).collect()
     }
 
     pub fn len(&self) -> usize {
-        self.characters.len()
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
     }
 }
 
 pub struct Buffer {
-    lines: Vec<Line>,
-    file: Option<File>,
+    lines: Rope<Line>,
     filename: Option<String>,
+    format: Format,
     dirty: bool,
+    history: History,
+    revision: usize,
+    dirty_before: bool,
+    changes: Option<Vec<Edit>>,
 }
 
 impl Buffer {
     pub fn new() -> Buffer {
+        let mut lines = Rope::new();
+        lines.push(Line::new());
+
         Buffer {
-            lines: vec![Line::new()],
-            file: None,
+            lines,
             filename: None,
+            format: Format::default(),
             dirty: false,
+            history: History::new(),
+            revision: 0,
+            dirty_before: false,
+            changes: None,
         }
     }
 
-    pub fn from(filename: String) -> Buffer {
-        let mut file = OpenOptions::new()
-            .create(true)
-            .read(true)
-            .write(true)
-            .open(PathBuf::from(filename.clone()))
-            .expect("Failed to open buffer");
-        let mut contents = String::new();
-        file.read_to_string(&mut contents)
-            .expect("Failed to read file contents");
+    /// Opens the named file. A file that does not exist yet gives an empty
+    /// buffer which will create it when saved. The file's line endings,
+    /// whether it ends in a newline and any byte order mark are kept, to be
+    /// written back the same way.
+    pub fn from(filename: String) -> Result<Buffer, Error> {
+        let mut bytes = vec![];
+        let mut format = Format::default();
+        match File::open(PathBuf::from(filename.clone())) {
+            Err(ref error) if error.kind() == ErrorKind::NotFound => (),
+            Err(error) => return Err(Error::Io(filename, error)),
+            Ok(mut file) => {
+                if file.metadata().map(|m| m.is_dir()).unwrap_or(false) {
+                    return Err(Error::IsDirectory(filename));
+                }
+                if let Err(error) = file.read_to_end(&mut bytes) {
+                    return Err(Error::Io(filename, error));
+                }
+            }
+        }
+
+        let contents = match String::from_utf8(bytes) {
+            Ok(contents) => contents,
+            Err(error) => {
+                let valid = error.utf8_error().valid_up_to();
+                let line = error.as_bytes()[..valid]
+                    .iter()
+                    .filter(|&&b| b == b'\n')
+                    .count();
+                return Err(Error::InvalidUtf8(filename, line + 1));
+            }
+        };
+
+        // Lines end in `\r\n` only if every one does, so that in a file
+        // which mixes the two, the odd `\r` stays in the text and is written
+        // back as it was.
+        let text = contents.strip_prefix(BOM).unwrap_or(&contents);
+        let breaks = text.matches('\n').count();
+        let crlf = text.matches("\r\n").count();
+        if breaks > 0 && crlf == breaks {
+            format.line_ending = LineEnding::CrLf;
+        }
+        format.mixed = crlf > 0 && crlf < breaks;
+        format.bom = text.len() < contents.len();
+        // An empty file is taken to want a newline after whatever is typed
+        // into it.
+        format.empty = text.is_empty();
+        format.final_newline = text.ends_with('\n') || format.empty;
+        let ending = format.line_ending.as_str().len() - 1;
 
-        let mut lines = contents
-            .lines()
-            .map(|x| Line::from(x.chars()))
-            .collect::<Vec<Line>>();
+        // With a newline at the end, there is no line after it.
+        let count = if text.ends_with('\n') {
+            breaks
+        } else {
+            breaks + 1
+        };
+        let mut lines = text
+            .split('\n')
+            .take(count)
+            .enumerate()
+            .map(|(i, line)| {
+                let line = if i < breaks {
+                    &line[..line.len() - ending]
+                } else {
+                    line
+                };
+                Line::from(line.chars())
+            })
+            .collect::<Rope<Line>>();
 
-        if lines.len() == 0 {
+        if lines.is_empty() {
             lines.push(Line::new());
         }
 
-        Buffer {
-            lines: lines,
-            file: Some(file),
+        Ok(Buffer {
+            lines,
             filename: Some(filename),
+            format,
             dirty: false,
+            history: History::new(),
+            revision: 0,
+            dirty_before: false,
+            changes: None,
+        })
+    }
+
+    /// Takes a copy of the buffer's contents to be written back to its file
+    /// without holding up further edits. Returns `None` if the buffer has no
+    /// file to write to.
+    pub fn snapshot(&self) -> Option<Snapshot> {
+        Some(self.snapshot_to(self.filename.clone()?))
+    }
+
+    /// Takes a copy of the buffer's contents to be written to the file
+    /// `name`, which need not be its own.
+    pub fn snapshot_to(&self, name: String) -> Snapshot {
+        Snapshot {
+            lines: self.lines.clone(),
+            name,
+            format: self.format,
+            revision: self.revision,
         }
     }
 
-    pub fn write_back(&mut self) {
-        match self.file {
-            Some(ref mut file) => {
-                file.seek(SeekFrom::Start(0)).unwrap();
-                let contents = self
-                    .lines
-                    .iter()
-                    .map(|x| x.characters.iter().collect::<String>() + "\n")
-                    .collect::<String>();
-                let contents = contents.as_bytes();
-                let len = contents.len();
-                file.write(contents).unwrap();
-                file.set_len(len as u64).unwrap();
-                self.dirty = false;
+    /// Marks the buffer clean after a snapshot taken at `revision` has been
+    /// written, unless it has been edited since.
+    pub fn mark_saved(&mut self, revision: usize) {
+        if self.revision == revision {
+            self.dirty = false;
+        }
+    }
+
+    /// Starts keeping a list of every edit made to the buffer, including
+    /// those made by undo and redo, to be collected with `take_changes`.
+    pub fn track_changes(&mut self) {
+        self.changes = Some(vec![]);
+    }
+
+    /// The edits made since the last call, oldest first.
+    pub fn take_changes(&mut self) -> Vec<Edit> {
+        match self.changes {
+            Some(ref mut changes) => changes.split_off(0),
+            None => vec![],
+        }
+    }
+
+    /// Makes an edit without recording it.
+    fn apply(&mut self, edit: &Edit) {
+        self.dirty = true;
+        if let Some(ref mut changes) = self.changes {
+            changes.push(edit.clone());
+        }
+        match *edit {
+            Edit::Insert {
+                line,
+                column,
+                ref text,
+            } => self.lines.get_mut(line).insert(column, text),
+            Edit::Delete {
+                line,
+                column,
+                ref text,
+            } => {
+                self.lines
+                    .get_mut(line)
+                    .delete(column, text.chars().count());
             }
-            None => return,
+            Edit::Split { line, column } => {
+                let new_line = self.lines.get_mut(line).split(column);
+                self.lines.insert(line + 1, new_line);
+            }
+            Edit::Join { line, .. } => {
+                let to_join = self.lines.remove(line + 1);
+                self.lines.get_mut(line).append(to_join);
+            }
+        }
+    }
+
+    fn record(&mut self, edit: Edit) {
+        self.apply(&edit);
+        self.history.record(edit);
+        if !self.history.in_transaction() {
+            self.revision += 1;
+        }
+    }
+
+    /// Starts a transaction. Edits made until the matching `commit` form a
+    /// single undo step and a single change to the buffer's revision.
+    /// Transactions may be nested.
+    pub fn begin(&mut self) {
+        if !self.history.in_transaction() {
+            self.dirty_before = self.dirty;
         }
+        self.history.begin();
+    }
+
+    pub fn commit(&mut self) {
+        if self.history.commit() {
+            self.revision += 1;
+        }
+    }
+
+    /// Ends a transaction made up of the same key typed at several places,
+    /// whose edits carry on the last undo step if they continue the typing
+    /// or backspacing at each place, as a single edit would.
+    pub fn commit_typing(&mut self) {
+        if self.history.commit_typing() {
+            self.revision += 1;
+        }
+    }
+
+    /// Ends a transaction, reverting every edit made since its `begin`.
+    pub fn abort(&mut self) {
+        for edit in self.history.abort().iter().rev() {
+            self.apply(&edit.invert());
+        }
+        if !self.history.in_transaction() {
+            self.dirty = self.dirty_before;
+        }
+    }
+
+    /// The nearest position to `(line, column)` which is in the buffer, as
+    /// positions past the end of a line or of the buffer are taken to mean
+    /// its end.
+    fn clamp(&self, (line, column): (usize, usize)) -> (usize, usize) {
+        let line = std::cmp::min(line, self.lines.len() - 1);
+        (line, std::cmp::min(column, self.lines.get(line).len()))
+    }
+
+    /// Inserts `c`, which must not be a newline.
+    pub fn insert(&mut self, line: usize, column: usize, c: char) {
+        debug_assert!(c != '\n' && c != '\r', "a line cannot hold a newline");
+        let (line, column) = self.clamp((line, column));
+        self.record(Edit::Insert {
+            line,
+            column,
+            text: c.to_string(),
+        });
+    }
+
+    /// Deletes the character at `(line, column)`, if there is one.
+    pub fn delete(&mut self, line: usize, column: usize) {
+        let (line, column) = self.clamp((line, column));
+        let text = match self.lines.get(line).chars().nth(column) {
+            Some(text) => text,
+            None => return,
+        };
+        self.record(Edit::Delete {
+            line,
+            column,
+            text: text.to_string(),
+        });
     }
 
     pub fn split_line(&mut self, line: usize, column: usize) {
-        self.dirty = true;
-        let new_line = self.lines[line].split(column);
-        self.lines.insert(line + 1, new_line);
+        let (line, column) = self.clamp((line, column));
+        self.record(Edit::Split { line, column });
+    }
+
+    /// Joins `line` onto the end of the line before it, returning the
+    /// column where they meet, or `None` if there is no line before it.
+    pub fn merge_line(&mut self, line: usize) -> Option<usize> {
+        if line == 0 || line >= self.lines.len() {
+            return None;
+        }
+        let column = self.lines.get(line - 1).len();
+        self.record(Edit::Join {
+            line: line - 1,
+            column,
+        });
+        Some(column)
+    }
+
+    /// The text between two positions, given either way round, with a
+    /// newline wherever it crosses from one line to the next.
+    pub fn text_range(&self, start: (usize, usize), end: (usize, usize)) -> String {
+        let (start, end) = self.order(start, end);
+        let mut text = String::new();
+        for line in start.0..=end.0 {
+            let from = if line == start.0 { start.1 } else { 0 };
+            let to = if line == end.0 {
+                end.1
+            } else {
+                self.lines.get(line).len()
+            };
+            if line > start.0 {
+                text.push('\n');
+            }
+            text.extend(
+                self.lines
+                    .get(line)
+                    .chars()
+                    .skip(from)
+                    .take(to.saturating_sub(from)),
+            );
+        }
+        text
+    }
+
+    /// Inserts `text`, which may run over several lines, returning the
+    /// position just after it.
+    pub fn insert_str(&mut self, line: usize, column: usize, text: &str) -> (usize, usize) {
+        let (mut line, mut column) = self.clamp((line, column));
+        self.begin();
+        for (i, part) in text.split('\n').enumerate() {
+            if i > 0 {
+                self.split_line(line, column);
+                line += 1;
+                column = 0;
+            }
+            if !part.is_empty() {
+                self.record(Edit::Insert {
+                    line,
+                    column,
+                    text: part.to_string(),
+                });
+                column += part.chars().count();
+            }
+        }
+        self.commit();
+        (line, column)
+    }
+
+    /// Removes the text between two positions, given either way round,
+    /// returning it.
+    pub fn delete_range(&mut self, start: (usize, usize), end: (usize, usize)) -> String {
+        let (start, end) = self.order(start, end);
+        let removed = self.text_range(start, end);
+        let (line, column) = start;
+
+        self.begin();
+        for (i, part) in removed.split('\n').enumerate() {
+            if i > 0 {
+                self.record(Edit::Join { line, column });
+            }
+            if !part.is_empty() {
+                self.record(Edit::Delete {
+                    line,
+                    column,
+                    text: part.to_string(),
+                });
+            }
+        }
+        self.commit();
+        removed
     }
 
-    pub fn merge_line(&mut self, line: usize) -> usize {
-        if line > 0 {
-            let mut to_merge = self.lines.remove(line);
-            let merging = &mut self.lines[line.saturating_sub(1)];
-            let len = merging.len();
-            merging.characters.append(&mut to_merge.characters);
-            len
+    /// Two positions brought into the buffer and put in order.
+    fn order(&self, a: (usize, usize), b: (usize, usize)) -> ((usize, usize), (usize, usize)) {
+        let (a, b) = (self.clamp(a), self.clamp(b));
+        if a <= b {
+            (a, b)
         } else {
-            0
+            (b, a)
         }
     }
 
-    pub fn borrow_line(&self, line: usize) -> &Line {
-        &self.lines[line]
+    /// Ends the current undo step, so the next edit starts a new one even
+    /// if it carries straight on from the last.
+    pub fn checkpoint(&mut self) {
+        self.history.checkpoint();
     }
 
-    pub fn borrow_line_mut(&mut self, line: usize) -> &mut Line {
-        self.dirty = true;
-        &mut self.lines[line]
+    /// Reverts the last undo step, returning where the cursor should go.
+    pub fn undo(&mut self) -> Option<(usize, usize)> {
+        let step = self.history.undo()?;
+        for edit in step.iter().rev() {
+            self.apply(&edit.invert());
+        }
+        self.revision += 1;
+        Some(step[0].before())
+    }
+
+    /// Reapplies the last undone step, returning where the cursor should go.
+    pub fn redo(&mut self) -> Option<(usize, usize)> {
+        let step = self.history.redo()?;
+        for edit in step.iter() {
+            self.apply(edit);
+        }
+        self.revision += 1;
+        Some(step[step.len() - 1].after())
+    }
+
+    pub fn borrow_line(&self, line: usize) -> &Line {
+        self.lines.get(line)
     }
 
     pub fn len(&self) -> usize {
         self.lines.len()
     }
 
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    pub fn filename(&self) -> Option<&str> {
+        self.filename.as_deref()
+    }
+
+    /// Gives the buffer a file to be saved to.
+    pub fn set_filename(&mut self, filename: String) {
+        self.filename = Some(filename);
+    }
+
     pub fn get_name(&self) -> String {
         (match self.filename {
             Some(ref filename) => filename.clone(),
@@ -144,4 +624,172 @@ impl Buffer {
     pub fn dirty(&self) -> bool {
         self.dirty
     }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Changes what the lines end with when the buffer is saved, which
+    /// counts as a change to it if it differs. In a file which mixed line
+    /// endings, the `\r` kept at the end of the lines which ended in `\r\n`
+    /// is taken off too, as a single undo step.
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if self.format.mixed {
+            self.begin();
+            for line in 0..self.lines.len() {
+                // The last line only ended in a line break if the file did.
+                let ended = line + 1 < self.lines.len() || self.format.final_newline;
+                if ended && self.lines.get(line).as_str().ends_with('\r') {
+                    let column = self.lines.get(line).len() - 1;
+                    self.delete(line, column);
+                }
+            }
+            self.commit();
+            self.format.mixed = false;
+        }
+
+        if self.format.line_ending != line_ending {
+            self.format.line_ending = line_ending;
+            self.dirty = true;
+            self.revision += 1;
+        }
+    }
+}
+
+/// The contents of a buffer at one revision, as handed to the writer.
+pub struct Snapshot {
+    lines: Rope<Line>,
+    name: String,
+    format: Format,
+    revision: usize,
+}
+
+impl Snapshot {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn revision(&self) -> usize {
+        self.revision
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// Saves the snapshot to its file, calling `progress` every so often
+    /// with the number of lines written so far.
+    ///
+    /// The contents go to a temporary file alongside the original, which is
+    /// synced and then renamed over it, so the original is never left half
+    /// written. The new file takes on the original's permissions, and its
+    /// owner as far as the user saving it may set that. If anything fails
+    /// the original is untouched.
+    pub fn write_back<F: FnMut(usize)>(&self, progress: F) -> Result<(), Error> {
+        // Write through symlinks rather than replacing them.
+        let path = fs::canonicalize(&self.name).unwrap_or_else(|_| PathBuf::from(&self.name));
+        let (temp, file) = create_temp(&path).map_err(|error| self.error(error))?;
+
+        let result = self.write_to(file, &path, progress).and_then(|()| {
+            fs::rename(&temp, &path)?;
+            // Make the rename itself durable. Not every filesystem lets a
+            // directory be synced, so this is only best effort.
+            if let Ok(dir) = File::open(parent(&path)) {
+                let _ = dir.sync_all();
+            }
+            Ok(())
+        });
+
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
+        }
+        result.map_err(|error| self.error(error))
+    }
+
+    fn error(&self, error: io::Error) -> Error {
+        Error::Io(self.name.clone(), error)
+    }
+
+    fn write_to<F: FnMut(usize)>(
+        &self,
+        file: File,
+        path: &Path,
+        mut progress: F,
+    ) -> io::Result<()> {
+        if let Ok(metadata) = fs::metadata(path) {
+            file.set_permissions(metadata.permissions())?;
+            let owner = file.metadata()?;
+            if owner.uid() != metadata.uid() || owner.gid() != metadata.gid() {
+                // Only root may give a file away, so someone saving a file
+                // they can write but do not own keeps at least its group if
+                // they can, and otherwise saves it as their own.
+                match fchown(&file, Some(metadata.uid()), Some(metadata.gid())) {
+                    Err(ref error) if error.kind() == ErrorKind::PermissionDenied => {
+                        let _ = fchown(&file, None, Some(metadata.gid()));
+                    }
+                    result => result?,
+                }
+            }
+        }
+
+        let mut writer = BufWriter::new(file);
+        if self.format.bom {
+            write!(writer, "{}", BOM)?;
+        }
+        // An empty file left empty is written back as it was, rather than
+        // as a single newline.
+        let empty = self.format.empty && self.lines.len() == 1 && self.lines.get(0).is_empty();
+        let ending = self.format.line_ending.as_str().as_bytes();
+        for (i, line) in self.lines.iter().enumerate() {
+            if i % 4096 == 0 {
+                progress(i);
+            }
+            writer.write_all(line.as_str().as_bytes())?;
+            if i + 1 < self.lines.len() || self.format.final_newline && !empty {
+                writer.write_all(ending)?;
+            }
+        }
+
+        let file = writer.into_inner().map_err(|error| error.into_error())?;
+        file.sync_all()?;
+        progress(self.lines.len());
+        Ok(())
+    }
+}
+
+fn parent(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if parent != Path::new("") => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Creates a new, empty file in the same directory as `path` to write a
+/// save into before renaming it into place.
+fn create_temp(path: &Path) -> io::Result<(PathBuf, File)> {
+    let name = path
+        .file_name()
+        .map_or("buffer".into(), |name| name.to_string_lossy());
+
+    let mut attempt = 0;
+    loop {
+        let temp = parent(path).join(format!(".{}.{}-{}.nep", name, process::id(), attempt));
+        match OpenOptions::new().write(true).create_new(true).open(&temp) {
+            Ok(file) => return Ok((temp, file)),
+            Err(ref error) if error.kind() == ErrorKind::AlreadyExists && attempt < 16 => {
+                attempt += 1
+            }
+            Err(error) => return Err(error),
+        }
+    }
+}
+
+impl Default for Buffer {
+    fn default() -> Buffer {
+        Buffer::new()
+    }
 }
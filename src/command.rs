@@ -0,0 +1,287 @@
+//! The command line opened with `:`, where commands are typed out as in vi.
+//! Each is read into an action, so that it does just what a key bound to
+//! that action would. Besides the commands here, anything `Action::parse`
+//! reads, or the name of a command a script or plugin provides, can be run
+//! from it.
+
+use std::fs;
+use std::path::Path;
+
+use termion::event::Key;
+
+use super::action::Action;
+use super::mode::Motion;
+
+/// The commands the command line knows by name, for completing.
+const NAMES: &[&str] = &[
+    "edit",
+    "quit",
+    "quit!",
+    "saveas",
+    "set",
+    "substitute",
+    "wq",
+    "write",
+];
+
+/// Reads a command as typed, such as `w notes.txt`, `12` or `%s/a/b/g`,
+/// into the action it asks for. Most names have a short form, as in vi.
+pub fn parse(written: &str) -> Result<Action, String> {
+    let written = written.trim();
+    if let Ok(line) = written.parse() {
+        return Ok(Action::Move(Motion::Line(Some(line)), 1));
+    }
+
+    let (every_line, rest) = match written.strip_prefix('%') {
+        Some(rest) => (true, rest),
+        None => (false, written),
+    };
+    let end = rest
+        .find(|c: char| !c.is_alphanumeric() && c != '_' && c != '!')
+        .unwrap_or(rest.len());
+    let (name, argument) = (&rest[..end], rest[end..].trim());
+    let argument = Some(argument).filter(|argument| !argument.is_empty());
+    if every_line && name != "s" && name != "substitute" {
+        return Err("% only goes before s".to_string());
+    }
+
+    let action = match (name, argument) {
+        ("w", None) | ("write", None) => Action::Save,
+        ("w", Some(name)) | ("write", Some(name)) => Action::Write(name.to_string()),
+        ("saveas", Some(name)) => Action::SaveAs(Some(name.to_string())),
+        ("wq", None) | ("x", None) => Action::SaveQuit,
+        ("e", Some(name)) | ("edit", Some(name)) => Action::Open(Some(name.to_string())),
+        ("e", None) | ("edit", None) | ("saveas", None) => {
+            return Err("expected a file name".to_string())
+        }
+        ("q", None) | ("quit", None) => Action::Quit,
+        ("q!", None) | ("quit!", None) => Action::ForceQuit,
+        ("set", Some(option)) => Action::Set(option.to_string()),
+        ("set", None) => return Err("expected a setting".to_string()),
+        ("s", _) | ("substitute", _) => Action::SubstituteLines {
+            every_line,
+            written: argument.map(str::to_string),
+        },
+        _ => Action::parse(written).unwrap_or_else(|| Action::Run(written.to_string())),
+    };
+    Ok(action)
+}
+
+/// What could finish the word before the cursor in `before`, the text of
+/// the command line up to it: a command name, taken from the ones here and
+/// from `commands`; a file name after `edit`, `write` or `saveas`; or a
+/// setting after `set`, taken from `settings`. Gives where the word starts
+/// along with each candidate, in order, as it would read finished.
+pub fn complete(before: &str, commands: &[String], settings: &[&str]) -> (usize, Vec<String>) {
+    let start = before.rfind(' ').map_or(0, |space| space + 1);
+    let word = &before[start..];
+    let name = before.split(' ').next().unwrap_or("");
+
+    let mut candidates = if start == 0 {
+        NAMES
+            .iter()
+            .map(|name| name.to_string())
+            .chain(commands.iter().cloned())
+            .filter(|name| name.starts_with(word))
+            .collect()
+    } else {
+        match name {
+            "e" | "edit" | "w" | "write" | "saveas" => files(word),
+            "set" => settings
+                .iter()
+                .filter(|setting| setting.starts_with(word))
+                .map(|setting| setting.to_string())
+                .collect(),
+            _ => vec![],
+        }
+    };
+    candidates.sort();
+    candidates.dedup();
+    (start, candidates)
+}
+
+/// The files whose paths start with `prefix`, directories ending in `/`.
+/// Hidden files are left out unless the prefix asks for them.
+fn files(prefix: &str) -> Vec<String> {
+    let (directory, start) = match prefix.rfind('/') {
+        Some(slash) => prefix.split_at(slash + 1),
+        None => ("", prefix),
+    };
+    let entries = match fs::read_dir(if directory.is_empty() {
+        Path::new(".")
+    } else {
+        Path::new(directory)
+    }) {
+        Ok(entries) => entries,
+        Err(_) => return vec![],
+    };
+
+    entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            if !name.starts_with(start) || name.starts_with('.') && !start.starts_with('.') {
+                return None;
+            }
+            let slash = if entry.path().is_dir() { "/" } else { "" };
+            Some(format!("{}{}{}", directory, name, slash))
+        })
+        .collect()
+}
+
+/// A command being typed, with the cursor somewhere in it.
+#[derive(Default)]
+pub struct CommandLine {
+    text: String,
+    /// The cursor's byte offset in the text.
+    cursor: usize,
+    /// While going back through the history: the entry shown, counting
+    /// back from the newest, and what had been typed before.
+    recalled: Option<(usize, String)>,
+    /// While going through completions: where the word being completed
+    /// starts, the candidates and the one shown.
+    completing: Option<(usize, Vec<String>, usize)>,
+}
+
+impl CommandLine {
+    pub fn new() -> CommandLine {
+        CommandLine::default()
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// How many characters are before the cursor.
+    pub fn cursor(&self) -> usize {
+        self.text[..self.cursor].chars().count()
+    }
+
+    fn set_text(&mut self, text: String) {
+        self.cursor = text.len();
+        self.text = text;
+    }
+
+    /// The byte offset of the character before the cursor.
+    fn previous(&self) -> usize {
+        self.text[..self.cursor]
+            .char_indices()
+            .last()
+            .map_or(0, |(offset, _)| offset)
+    }
+
+    /// The byte offset of the character after the cursor.
+    fn next(&self) -> usize {
+        self.text[self.cursor..]
+            .chars()
+            .next()
+            .map_or(self.cursor, |c| self.cursor + c.len_utf8())
+    }
+
+    /// Edits the command as `key` says. Up and down go through `history`,
+    /// oldest first, keeping to entries which start with what was typed,
+    /// and tab goes through what `complete` gives for the text before the
+    /// cursor, as `complete` in this module does.
+    pub fn key<F>(&mut self, key: Key, history: &[String], complete: F)
+    where
+        F: FnOnce(&str) -> (usize, Vec<String>),
+    {
+        if key != Key::Up && key != Key::Down {
+            self.recalled = None;
+        }
+        if key != Key::Char('\t') && key != Key::BackTab {
+            self.completing = None;
+        }
+
+        match key {
+            Key::Char('\t') | Key::BackTab => self.complete(key == Key::BackTab, complete),
+            Key::Up | Key::Down => self.recall(key == Key::Up, history),
+            Key::Char(c) => {
+                self.text.insert(self.cursor, c);
+                self.cursor += c.len_utf8();
+            }
+            Key::Backspace => {
+                let previous = self.previous();
+                self.text.replace_range(previous..self.cursor, "");
+                self.cursor = previous;
+            }
+            Key::Delete => {
+                let next = self.next();
+                self.text.replace_range(self.cursor..next, "");
+            }
+            Key::Left => self.cursor = self.previous(),
+            Key::Right => self.cursor = self.next(),
+            Key::Home | Key::Ctrl('a') => self.cursor = 0,
+            Key::End | Key::Ctrl('e') => self.cursor = self.text.len(),
+            Key::Ctrl('u') => {
+                self.text.replace_range(..self.cursor, "");
+                self.cursor = 0;
+            }
+            Key::Ctrl('k') => self.text.truncate(self.cursor),
+            Key::Ctrl('w') => {
+                // Back over any spaces, then the word before them.
+                let before = self.text[..self.cursor].trim_end_matches(' ');
+                let start = before.rfind(' ').map_or(0, |space| space + 1);
+                self.text.replace_range(start..self.cursor, "");
+                self.cursor = start;
+            }
+            _ => (),
+        }
+    }
+
+    fn complete<F>(&mut self, backward: bool, complete: F)
+    where
+        F: FnOnce(&str) -> (usize, Vec<String>),
+    {
+        let (start, candidates, shown) = match self.completing.take() {
+            Some((start, candidates, shown)) => {
+                let count = candidates.len();
+                let next = if backward {
+                    (shown + count - 1) % count
+                } else {
+                    (shown + 1) % count
+                };
+                (start, candidates, next)
+            }
+            None => {
+                let (start, candidates) = complete(&self.text[..self.cursor]);
+                if candidates.is_empty() {
+                    return;
+                }
+                let shown = if backward { candidates.len() - 1 } else { 0 };
+                (start, candidates, shown)
+            }
+        };
+
+        self.text
+            .replace_range(start..self.cursor, &candidates[shown]);
+        self.cursor = start + candidates[shown].len();
+        self.completing = Some((start, candidates, shown));
+    }
+
+    fn recall(&mut self, older: bool, history: &[String]) {
+        let (shown, typed) = match self.recalled.take() {
+            Some(recalled) => recalled,
+            None if older => (0, self.text.clone()),
+            None => return,
+        };
+        let matching = history
+            .iter()
+            .rev()
+            .filter(|entry| entry.starts_with(&typed))
+            .collect::<Vec<_>>();
+
+        // Entries are counted from one, nought being what was typed.
+        let shown = if older {
+            std::cmp::min(shown + 1, matching.len())
+        } else {
+            shown.saturating_sub(1)
+        };
+        if shown == 0 {
+            self.set_text(typed);
+        } else {
+            self.set_text(matching[shown - 1].clone());
+            self.recalled = Some((shown, typed));
+        }
+    }
+}
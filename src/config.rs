@@ -0,0 +1,414 @@
+//! Settings read once on starting from `$XDG_CONFIG_HOME/nep/config.toml`
+//! (by default `~/.config/nep/config.toml`). Every entry may be left out:
+//!
+//! ```toml
+//! [editor]
+//! indent_width = 4        # columns inserted by tab, and shown for a tab
+//! tabs = false            # whether tab inserts a tab rather than spaces
+//! gutter = true           # whether line numbers are shown
+//! wrap = true             # whether long lines carry on onto the next row
+//! splash = true           # whether the splash screen shows without a file
+//! search_case = "smart"   # "sensitive", "insensitive", or "smart" to
+//!                         # match case only in patterns with capitals
+//!
+//! [keys]
+//! "C-s" = "save"
+//!
+//! [keys.normal]
+//! ";" = "command"
+//!
+//! [theme]
+//! line_numbers = "yellow"
+//! ```
+//!
+//! Keys are written as for plugins. Those in `keys` work in every mode, so
+//! they cannot be characters, which insert mode types. Those in
+//! `keys.normal`, `keys.insert` and `keys.visual` work only in that mode,
+//! over any bound in every mode. Each is bound to an action, written as
+//! `Action::parse` reads it, such as `save` or `move_down 5`, or else to a
+//! command a script or plugin provides, or to `""` to unbind it.
+//!
+//! The theme sets the colours of `line_numbers`, `tildes`, `status`,
+//! `message`, `divider` and `search`, the last being what search matches
+//! are highlighted in. A colour is `default`, `black`, `red`, `green`,
+//! `yellow`, `blue`, `magenta`, `cyan` or `white`, any but `default` with
+//! `bright_` in front, a number from 0 to 255 or `#rrggbb`.
+//!
+//! Entries which cannot be used are reported and otherwise ignored.
+
+use std::fmt;
+use std::fs;
+use std::io;
+
+use termion::color;
+use termion::event::Key;
+use toml::value::Table;
+use toml::Value;
+
+use super::action::Action;
+use super::keymap::Bindings;
+use super::plugin::parse_key;
+use super::search::Case;
+
+/// The settings in the `editor` section, which can also be changed while
+/// running.
+pub const SETTINGS: &[&str] = &[
+    "indent_width",
+    "tabs",
+    "gutter",
+    "wrap",
+    "splash",
+    "search_case",
+];
+
+const COLOURS: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// A foreground colour, written out as the escape code which sets it.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Colour {
+    Default,
+    Ansi(u8),
+    Rgb(u8, u8, u8),
+}
+
+impl fmt::Display for Colour {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Colour::Default => write!(f, "{}", color::Fg(color::Reset)),
+            Colour::Ansi(n) => write!(f, "{}", color::Fg(color::AnsiValue(n))),
+            Colour::Rgb(r, g, b) => write!(f, "{}", color::Fg(color::Rgb(r, g, b))),
+        }
+    }
+}
+
+fn colour(value: &Value) -> Result<Colour, String> {
+    let error = || "expected a colour name, a number from 0 to 255 or #rrggbb".to_string();
+
+    if let Some(n) = value.as_integer() {
+        return match n {
+            0..=255 => Ok(Colour::Ansi(n as u8)),
+            _ => Err(error()),
+        };
+    }
+    let name = value.as_str().ok_or_else(error)?;
+    if name == "default" {
+        return Ok(Colour::Default);
+    }
+
+    if let Some(hex) = name.strip_prefix('#') {
+        let part = |i: usize| {
+            hex.get(i..i + 2)
+                .and_then(|part| u8::from_str_radix(part, 16).ok())
+        };
+        return match (hex.len(), part(0), part(2), part(4)) {
+            (6, Some(r), Some(g), Some(b)) => Ok(Colour::Rgb(r, g, b)),
+            _ => Err(error()),
+        };
+    }
+
+    let (bright, base) = match name.strip_prefix("bright_") {
+        Some(base) => (8, base),
+        None => (0, name),
+    };
+    let n = COLOURS
+        .iter()
+        .position(|&colour| colour == base)
+        .ok_or_else(error)?;
+    Ok(Colour::Ansi(bright + n as u8))
+}
+
+fn boolean(value: &Value) -> Result<bool, String> {
+    value
+        .as_bool()
+        .ok_or_else(|| "expected true or false".to_string())
+}
+
+#[derive(Clone, Debug)]
+pub struct Theme {
+    pub line_numbers: Colour,
+    pub tildes: Colour,
+    pub status: Colour,
+    pub message: Colour,
+    pub divider: Colour,
+    pub search: Colour,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            line_numbers: Colour::Default,
+            tildes: Colour::Default,
+            status: Colour::Default,
+            message: Colour::Default,
+            divider: Colour::Default,
+            search: Colour::Ansi(3),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Config {
+    pub indent_width: usize,
+    pub tabs: bool,
+    pub gutter: bool,
+    pub wrap: bool,
+    pub splash: bool,
+    pub search_case: Case,
+    pub theme: Theme,
+    keys: Bindings,
+}
+
+impl Default for Config {
+    fn default() -> Config {
+        let keys = [
+            ('q', Action::Quit),
+            ('s', Action::Save),
+            ('o', Action::Open(None)),
+            ('x', Action::Close),
+            ('z', Action::Undo),
+            ('y', Action::Redo),
+        ];
+        Config {
+            indent_width: 4,
+            tabs: false,
+            gutter: true,
+            wrap: true,
+            splash: true,
+            search_case: Case::Smart,
+            theme: Theme::default(),
+            keys: Bindings {
+                every: keys
+                    .iter()
+                    .map(|&(c, ref action)| (Key::Ctrl(c), action.clone()))
+                    .collect(),
+                ..Bindings::default()
+            },
+        }
+    }
+}
+
+impl Config {
+    /// Reads the configuration file, returning a message for each entry
+    /// which could not be used.
+    pub fn load() -> (Config, Vec<String>) {
+        let mut config = Config::default();
+        let path = match super::config_directory() {
+            Some(directory) => directory.join("config.toml"),
+            None => return (config, vec![]),
+        };
+        let errors = match fs::read_to_string(&path) {
+            Ok(source) => config.read(&source),
+            Err(ref error) if error.kind() == io::ErrorKind::NotFound => vec![],
+            Err(error) => vec![format!("{}: {}", path.display(), error)],
+        };
+        (config, errors)
+    }
+
+    /// Sets whatever `source` gives over the current settings.
+    fn read(&mut self, source: &str) -> Vec<String> {
+        let table = match toml::from_str::<Table>(source) {
+            Ok(table) => table,
+            Err(error) => return vec![format!("config.toml: {}", error)],
+        };
+
+        let mut errors = vec![];
+        for (section, entries) in table {
+            let set = match section.as_str() {
+                "editor" => Config::set,
+                "keys" => Config::bind,
+                "theme" => Config::set_colour,
+                _ => {
+                    errors.push(format!("config.toml: unknown section {}", section));
+                    continue;
+                }
+            };
+            let entries = match entries {
+                Value::Table(entries) => entries,
+                _ => {
+                    errors.push(format!("config.toml: {} should be a section", section));
+                    continue;
+                }
+            };
+            for (name, value) in entries {
+                // The keys bound in one mode are a section of their own.
+                if let ("keys", Value::Table(bindings)) = (section.as_str(), &value) {
+                    self.bind_in(&name, bindings, &mut errors);
+                    continue;
+                }
+                let result = set(self, &name, &value);
+                if let Err(error) = result {
+                    errors.push(format!("config.toml: {}.{}: {}", section, name, error));
+                }
+            }
+        }
+        errors
+    }
+
+    fn set(&mut self, name: &str, value: &Value) -> Result<(), String> {
+        match name {
+            "indent_width" => match value.as_integer() {
+                Some(n) if (1..=16).contains(&n) => self.indent_width = n as usize,
+                _ => return Err("expected a number from 1 to 16".to_string()),
+            },
+            "tabs" => self.tabs = boolean(value)?,
+            "gutter" => self.gutter = boolean(value)?,
+            "wrap" => self.wrap = boolean(value)?,
+            "splash" => self.splash = boolean(value)?,
+            "search_case" => {
+                self.search_case = match value.as_str() {
+                    Some("sensitive") => Case::Sensitive,
+                    Some("insensitive") => Case::Insensitive,
+                    Some("smart") => Case::Smart,
+                    _ => return Err("expected sensitive, insensitive or smart".to_string()),
+                }
+            }
+            _ => return Err("unknown setting".to_string()),
+        }
+        Ok(())
+    }
+
+    /// A setting's value, written as in the configuration file.
+    fn get(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "indent_width" => self.indent_width.to_string(),
+            "tabs" => self.tabs.to_string(),
+            "gutter" => self.gutter.to_string(),
+            "wrap" => self.wrap.to_string(),
+            "splash" => self.splash.to_string(),
+            "search_case" => match self.search_case {
+                Case::Sensitive => "sensitive",
+                Case::Insensitive => "insensitive",
+                Case::Smart => "smart",
+            }
+            .to_string(),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Changes or shows a setting written as in vi: `wrap` or `nowrap` to
+    /// turn one on or off, `indent_width=8` to give one a value, and
+    /// `indent_width` or `wrap?` to show it, which gives back what to show.
+    pub fn option(&mut self, written: &str) -> Result<Option<String>, String> {
+        let unknown = |name: &str| format!("unknown setting {}", name);
+        let shown = |config: &Config, name: &str| {
+            config
+                .get(name)
+                .map(|value| Some(format!("{}={}", name, value)))
+                .ok_or_else(|| unknown(name))
+        };
+
+        if let Some((name, value)) = written.split_once('=') {
+            let (name, value) = (name.trim(), value.trim());
+            let value = match value.parse() {
+                Ok(n) => Value::Integer(n),
+                Err(_) => match value {
+                    "true" => Value::Boolean(true),
+                    "false" => Value::Boolean(false),
+                    _ => Value::String(value.to_string()),
+                },
+            };
+            return match self.set(name, &value) {
+                Ok(()) => Ok(None),
+                Err(_) if self.get(name).is_none() => Err(unknown(name)),
+                Err(error) => Err(format!("{}: {}", name, error)),
+            };
+        }
+
+        if let Some(name) = written.strip_suffix('?') {
+            return shown(self, name);
+        }
+        let is_boolean = |config: &Config, name: &str| {
+            config
+                .get(name)
+                .is_some_and(|value| value.parse::<bool>().is_ok())
+        };
+        if is_boolean(self, written) {
+            self.set(written, &Value::Boolean(true))?;
+            return Ok(None);
+        }
+        match written.strip_prefix("no") {
+            Some(name) if is_boolean(self, name) => {
+                self.set(name, &Value::Boolean(false))?;
+                Ok(None)
+            }
+            _ => shown(self, written),
+        }
+    }
+
+    fn bind(&mut self, written: &str, value: &Value) -> Result<(), String> {
+        let (key, action) = binding(written, value)?;
+        if let Key::Char(_) = key {
+            return Err("characters can only be bound in one mode, as in keys.normal".to_string());
+        }
+        self.keys.every.retain(|&(bound, _)| bound != key);
+        self.keys.every.extend(action.map(|action| (key, action)));
+        Ok(())
+    }
+
+    /// Binds the keys in `bindings` in the mode called `mode`.
+    fn bind_in(&mut self, mode: &str, bindings: &Table, errors: &mut Vec<String>) {
+        let keys = match mode {
+            "normal" => &mut self.keys.normal,
+            "insert" => &mut self.keys.insert,
+            "visual" => &mut self.keys.visual,
+            _ => {
+                errors.push(format!("config.toml: keys.{}: unknown mode", mode));
+                return;
+            }
+        };
+        for (written, value) in bindings {
+            match binding(written, value) {
+                Ok((key, action)) => {
+                    keys.retain(|&(bound, _)| bound != key);
+                    keys.push((key, action));
+                }
+                Err(error) => {
+                    errors.push(format!("config.toml: keys.{}.{}: {}", mode, written, error))
+                }
+            }
+        }
+    }
+
+    fn set_colour(&mut self, name: &str, value: &Value) -> Result<(), String> {
+        let theme = &mut self.theme;
+        let target = match name {
+            "line_numbers" => &mut theme.line_numbers,
+            "tildes" => &mut theme.tildes,
+            "status" => &mut theme.status,
+            "message" => &mut theme.message,
+            "divider" => &mut theme.divider,
+            "search" => &mut theme.search,
+            _ => return Err("unknown colour".to_string()),
+        };
+        *target = colour(value)?;
+        Ok(())
+    }
+
+    /// The keys bound to actions, in every mode and in each.
+    pub fn keys(&self) -> Bindings {
+        self.keys.clone()
+    }
+
+    /// What the tab key inserts.
+    pub fn indent(&self) -> String {
+        if self.tabs {
+            "\t".to_string()
+        } else {
+            " ".repeat(self.indent_width)
+        }
+    }
+}
+
+/// Reads a key, written as for plugins, and what it is bound to, which is
+/// nothing if it is unbound.
+fn binding(written: &str, value: &Value) -> Result<(Key, Option<Action>), String> {
+    let key = parse_key(written).ok_or("not a key")?;
+    let written = value.as_str().ok_or("expected the name of an action")?;
+    let action = Some(written)
+        .filter(|written| !written.is_empty())
+        .map(|written| Action::parse(written).unwrap_or_else(|| Action::Run(written.to_string())));
+    Ok((key, action))
+}
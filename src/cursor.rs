@@ -1,10 +1,248 @@
 use super::buffer::Buffer;
+use super::mode::Motion;
 use std::sync::{Arc, Mutex};
 
+#[derive(Clone)]
 pub struct Cursor {
     buffer: Arc<Mutex<Buffer>>,
     line: usize,
     column: usize,
+    past_end: bool,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Class {
+    Space,
+    Word,
+    Punctuation,
+}
+
+fn class(c: Option<char>) -> Class {
+    match c {
+        Some(c) if c.is_alphanumeric() || c == '_' => Class::Word,
+        Some(c) if !c.is_whitespace() => Class::Punctuation,
+        _ => Class::Space,
+    }
+}
+
+/// The position one character on, treating the end of each line as a
+/// character of its own.
+fn forward(buffer: &Buffer, (line, column): (usize, usize)) -> Option<(usize, usize)> {
+    if column < buffer.borrow_line(line).len() {
+        Some((line, column + 1))
+    } else if line + 1 < buffer.len() {
+        Some((line + 1, 0))
+    } else {
+        None
+    }
+}
+
+/// The position just after `position`, for motions which include the
+/// character they land on.
+pub fn after(buffer: &Buffer, position: (usize, usize)) -> (usize, usize) {
+    forward(buffer, position).unwrap_or(position)
+}
+
+/// A place in a buffer which steps a character at a time, treating the end
+/// of each line as a character of its own. It keeps the byte offset of its
+/// column, so each step takes the same time however long the line is.
+#[derive(Clone, Copy)]
+struct Walk<'a> {
+    buffer: &'a Buffer,
+    line: usize,
+    column: usize,
+    text: &'a str,
+    offset: usize,
+}
+
+impl<'a> Walk<'a> {
+    fn new(buffer: &'a Buffer, (line, column): (usize, usize)) -> Walk<'a> {
+        let text = buffer.borrow_line(line).as_str();
+        let offset = text
+            .char_indices()
+            .nth(column)
+            .map_or(text.len(), |(offset, _)| offset);
+        Walk {
+            buffer,
+            line,
+            column,
+            text,
+            offset,
+        }
+    }
+
+    fn position(&self) -> (usize, usize) {
+        (self.line, self.column)
+    }
+
+    /// The character here, or `None` at the end of the line.
+    fn character(&self) -> Option<char> {
+        self.text[self.offset..].chars().next()
+    }
+
+    fn class(&self) -> Class {
+        class(self.character())
+    }
+
+    /// Whether this is on a line with nothing on it.
+    fn is_blank_line(&self) -> bool {
+        self.text.is_empty()
+    }
+
+    /// Steps on a character, returning whether there was one to step to.
+    fn forward(&mut self) -> bool {
+        match self.character() {
+            Some(c) => {
+                self.offset += c.len_utf8();
+                self.column += 1;
+            }
+            None if self.line + 1 < self.buffer.len() => {
+                self.line += 1;
+                self.column = 0;
+                self.text = self.buffer.borrow_line(self.line).as_str();
+                self.offset = 0;
+            }
+            None => return false,
+        }
+        true
+    }
+
+    /// Steps back a character, returning whether there was one to step to.
+    fn backward(&mut self) -> bool {
+        match self.text[..self.offset].char_indices().next_back() {
+            Some((offset, _)) => {
+                self.offset = offset;
+                self.column -= 1;
+            }
+            None if self.line > 0 => {
+                self.line -= 1;
+                let line = self.buffer.borrow_line(self.line);
+                self.text = line.as_str();
+                self.offset = self.text.len();
+                self.column = line.len();
+            }
+            None => return false,
+        }
+        true
+    }
+
+    /// A copy of this place stepped on a character, if there is one.
+    fn next(&self) -> Option<Walk<'a>> {
+        let mut next = *self;
+        if next.forward() {
+            Some(next)
+        } else {
+            None
+        }
+    }
+
+    /// A copy of this place stepped back a character, if there is one.
+    fn previous(&self) -> Option<Walk<'a>> {
+        let mut previous = *self;
+        if previous.backward() {
+            Some(previous)
+        } else {
+            None
+        }
+    }
+}
+
+fn word_forward(buffer: &Buffer, start: (usize, usize)) -> (usize, usize) {
+    let mut walk = Walk::new(buffer, start);
+    let start_class = walk.class();
+
+    if start_class != Class::Space {
+        while walk.class() == start_class {
+            if !walk.forward() {
+                return walk.position();
+            }
+        }
+    }
+
+    while walk.class() == Class::Space {
+        if walk.position() != start && walk.is_blank_line() {
+            break;
+        }
+        if !walk.forward() {
+            break;
+        }
+    }
+    walk.position()
+}
+
+fn word_backward(buffer: &Buffer, start: (usize, usize)) -> (usize, usize) {
+    let mut walk = Walk::new(buffer, start);
+    if !walk.backward() {
+        return start;
+    }
+
+    while walk.class() == Class::Space && !walk.is_blank_line() {
+        if !walk.backward() {
+            return walk.position();
+        }
+    }
+
+    let word_class = walk.class();
+    if word_class != Class::Space {
+        while let Some(previous) = walk.previous() {
+            if previous.class() != word_class {
+                break;
+            }
+            walk = previous;
+        }
+    }
+    walk.position()
+}
+
+fn word_end(buffer: &Buffer, start: (usize, usize)) -> (usize, usize) {
+    let mut walk = Walk::new(buffer, start);
+    if !walk.forward() {
+        return start;
+    }
+
+    while walk.class() == Class::Space {
+        if !walk.forward() {
+            return walk.position();
+        }
+    }
+
+    let word_class = walk.class();
+    while let Some(next) = walk.next() {
+        if next.class() != word_class {
+            break;
+        }
+        walk = next;
+    }
+    walk.position()
+}
+
+/// The columns from the start of the word at `position` to just after it,
+/// if there is a word there.
+fn word_at(buffer: &Buffer, position: (usize, usize)) -> Option<(usize, usize)> {
+    let walk = Walk::new(buffer, position);
+    if walk.class() != Class::Word {
+        return None;
+    }
+
+    let mut start = walk;
+    while let Some(previous) = start.previous() {
+        if previous.line != walk.line || previous.class() != Class::Word {
+            break;
+        }
+        start = previous;
+    }
+    let mut end = walk;
+    while end.class() == Class::Word {
+        end.forward();
+    }
+    Some((start.column, end.column))
+}
+
+fn first_non_blank(buffer: &Buffer, line: usize) -> usize {
+    let line = buffer.borrow_line(line);
+    line.chars()
+        .position(|c| !c.is_whitespace())
+        .unwrap_or_else(|| line.len())
 }
 
 impl Cursor {
@@ -13,21 +251,44 @@ impl Cursor {
             buffer: Arc::clone(buffer),
             line: 0,
             column: 0,
+            past_end: false,
         }
     }
 
+    /// The buffer the cursor is in.
+    pub fn buffer(&self) -> Arc<Mutex<Buffer>> {
+        Arc::clone(&self.buffer)
+    }
+
+    /// Sets whether the cursor may sit just past the last character of a
+    /// line, as it needs to when inserting, or must stay on a character.
+    pub fn set_past_end(&mut self, past_end: bool) {
+        self.past_end = past_end;
+    }
+
+    /// The cursor's position, kept within the bounds of the buffer.
+    fn position(&self, buffer: &Buffer) -> (usize, usize) {
+        let line = std::cmp::min(self.line, buffer.len().saturating_sub(1));
+        let len = buffer.borrow_line(line).len();
+        let last = if self.past_end {
+            len
+        } else {
+            len.saturating_sub(1)
+        };
+        (line, std::cmp::min(self.column, last))
+    }
+
     pub fn column(&self) -> usize {
-        std::cmp::min(
-            self.column,
-            self.buffer.lock().unwrap().borrow_line(self.line).len(),
-        )
+        self.position(&self.buffer.lock().unwrap()).1
     }
 
     pub fn line(&self) -> usize {
-        std::cmp::min(
-            self.line,
-            self.buffer.lock().unwrap().len().saturating_sub(1),
-        )
+        self.position(&self.buffer.lock().unwrap()).0
+    }
+
+    pub fn goto(&mut self, line: usize, column: usize) {
+        self.line = line;
+        self.column = column;
     }
 
     pub fn left(&mut self) {
@@ -42,6 +303,7 @@ impl Cursor {
     }
 
     pub fn up(&mut self) {
+        self.line = self.line();
         self.line = self.line.saturating_sub(1);
     }
 
@@ -50,14 +312,71 @@ impl Cursor {
         self.line = self.line()
     }
 
-    pub fn jump_next(&mut self) {
-        self.down();
-        self.column = 0;
+    /// Where the word under the cursor next appears as a whole word,
+    /// searching on from the cursor and wrapping round the end of the buffer.
+    pub fn next_match(&self) -> Option<(usize, usize)> {
+        let buffer = self.buffer.lock().unwrap();
+        let (line, column) = self.position(&buffer);
+        let (start, end) = word_at(&buffer, (line, column))?;
+        let word = buffer
+            .borrow_line(line)
+            .chars()
+            .skip(start)
+            .take(end - start)
+            .collect::<String>();
+
+        let lines = buffer.len();
+        for i in 0..=lines {
+            let at = (line + i) % lines;
+            let text = buffer.borrow_line(at).as_str();
+            for (byte, _) in text.match_indices(word.as_str()) {
+                let found = text[..byte].chars().count();
+                if i == 0 && found <= start {
+                    continue;
+                }
+                if i == lines && found >= start {
+                    break;
+                }
+                if word_at(&buffer, (at, found)) == Some((found, found + end - start)) {
+                    return Some((at, found));
+                }
+            }
+        }
+        None
     }
 
-    pub fn jump_prev(&mut self, position: usize) {
-        self.up();
-        self.column = position;
-        self.column = self.column();
+    /// Moves the cursor as a normal mode motion would.
+    pub fn apply(&mut self, motion: Motion) {
+        match motion {
+            Motion::Left => self.left(),
+            Motion::Right => self.right(),
+            Motion::Up => self.up(),
+            Motion::Down => self.down(),
+            // Past the end of any line, so moving up and down stays at the
+            // end of each line in turn.
+            Motion::LineEnd => self.column = usize::MAX,
+            motion => {
+                let buffer = self.buffer.lock().unwrap();
+                let position = self.position(&buffer);
+                let (line, column) = match motion {
+                    Motion::WordForward => word_forward(&buffer, position),
+                    Motion::WordBackward => word_backward(&buffer, position),
+                    Motion::WordEnd => word_end(&buffer, position),
+                    Motion::LineStart => (position.0, 0),
+                    Motion::FirstNonBlank => (position.0, first_non_blank(&buffer, position.0)),
+                    Motion::Line(line) => {
+                        let line = line.map_or(buffer.len(), |line| line.max(1)) - 1;
+                        let line = std::cmp::min(line, buffer.len() - 1);
+                        (line, first_non_blank(&buffer, line))
+                    }
+                    _ => unreachable!(),
+                };
+                self.line = line;
+                // Where the cursor is seen to be, so moving up and down
+                // afterwards keeps to that column.
+                self.column = column;
+                self.column = self.position(&buffer).1;
+            }
+        }
     }
 }
@@ -0,0 +1,244 @@
+//! Everything the editor waits for: keys typed, the terminal being resized
+//! and other threads having news to show. They are all waited on together
+//! with `poll`, the last two through a pipe written to by the `SIGWINCH`
+//! handler and by `wake`, so while nothing happens the editor does nothing
+//! at all.
+
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use libc;
+use termion::event;
+use termion::event::Key;
+
+/// The end of the pipe written to for the event loop, while there is one.
+static PIPE: AtomicI32 = AtomicI32::new(-1);
+
+/// What is written to the pipe when the terminal is resized.
+const RESIZED: u8 = b'r';
+/// What is written to the pipe by `wake`.
+const WOKEN: u8 = b'w';
+
+fn send(byte: u8) {
+    let fd = PIPE.load(Ordering::Relaxed);
+    if fd >= 0 {
+        unsafe {
+            libc::write(fd, &byte as *const u8 as *const libc::c_void, 1);
+        }
+    }
+}
+
+extern "C" fn on_resize(_: libc::c_int) {
+    send(RESIZED);
+}
+
+/// Wakes the event loop from another thread, to show what has changed.
+pub fn wake() {
+    send(WOKEN);
+}
+
+fn check(result: libc::c_int) -> io::Result<libc::c_int> {
+    if result < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(result)
+    }
+}
+
+/// A pipe with both ends non-blocking, so the signal handler never waits
+/// to write and draining it never waits to read, and closed in plugins.
+fn pipe() -> io::Result<(RawFd, RawFd)> {
+    let mut fds = [0; 2];
+    unsafe {
+        check(libc::pipe(fds.as_mut_ptr()))?;
+        for &fd in &fds {
+            check(libc::fcntl(fd, libc::F_SETFL, libc::O_NONBLOCK))?;
+            check(libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC))?;
+        }
+    }
+    Ok((fds[0], fds[1]))
+}
+
+fn set_handler(handler: libc::sighandler_t) -> io::Result<()> {
+    unsafe {
+        let mut action: libc::sigaction = std::mem::zeroed();
+        action.sa_sigaction = handler;
+        action.sa_flags = libc::SA_RESTART;
+        libc::sigemptyset(&mut action.sa_mask);
+        check(libc::sigaction(
+            libc::SIGWINCH,
+            &action,
+            std::ptr::null_mut(),
+        ))?;
+    }
+    Ok(())
+}
+
+/// How many bytes the first key in `bytes` takes up, if they hold all of
+/// it.
+fn key_length(bytes: &[u8]) -> Option<usize> {
+    let utf8_length = |byte: u8| match byte {
+        0xc0..=0xdf => 2,
+        0xe0..=0xef => 3,
+        0xf0..=0xf7 => 4,
+        _ => 1,
+    };
+    let length = match bytes {
+        [] => return None,
+        // A lone escape, read by itself, is the escape key rather than the
+        // start of a sequence.
+        [b'\x1b'] => 1,
+        [b'\x1b', b'[', b'M', ..] => 6,
+        [b'\x1b', b'[', rest @ ..] => {
+            3 + rest.iter().position(|byte| (0x40..=0x7e).contains(byte))?
+        }
+        [b'\x1b', b'O', ..] => 3,
+        [b'\x1b', byte, ..] => 1 + utf8_length(*byte),
+        [byte, ..] => utf8_length(*byte),
+    };
+    if length <= bytes.len() {
+        Some(length)
+    } else {
+        None
+    }
+}
+
+pub enum Event {
+    Key(Key),
+    /// The terminal has changed size.
+    Resize,
+    /// Another thread has called `wake`.
+    Wake,
+}
+
+/// The events coming from the terminal, whose input is read from `input`.
+/// Only one should exist at a time.
+pub struct Events<R: AsRawFd> {
+    input: R,
+    /// Bytes read from the input not yet made into keys.
+    bytes: Vec<u8>,
+    /// The end of the pipe read for resizes and wake-ups.
+    pipe: RawFd,
+}
+
+impl<R: AsRawFd> Events<R> {
+    pub fn new(input: R) -> io::Result<Events<R>> {
+        let (pipe, write) = pipe()?;
+        PIPE.store(write, Ordering::Relaxed);
+        set_handler(on_resize as *const () as libc::sighandler_t)?;
+        Ok(Events {
+            input,
+            bytes: vec![],
+            pipe,
+        })
+    }
+
+    /// Takes the first key out of the bytes read so far, if they hold a
+    /// whole one.
+    fn key(&mut self) -> Option<Key> {
+        while let Some(length) = key_length(&self.bytes) {
+            if self.bytes[..length] == [b'\x1b'] {
+                self.bytes.drain(..1);
+                return Some(Key::Esc);
+            }
+            let parsed = {
+                let mut rest = self.bytes[1..length].iter().map(|&byte| Ok(byte));
+                event::parse_event(self.bytes[0], &mut rest)
+            };
+            self.bytes.drain(..length);
+            if let Ok(event::Event::Key(key)) = parsed {
+                return Some(key);
+            }
+        }
+        None
+    }
+}
+
+impl<R: AsRawFd> Iterator for Events<R> {
+    type Item = io::Result<Event>;
+
+    /// Waits for the next event, or gives `None` once the input has ended.
+    fn next(&mut self) -> Option<io::Result<Event>> {
+        loop {
+            if let Some(key) = self.key() {
+                return Some(Ok(Event::Key(key)));
+            }
+
+            let mut fds = [
+                libc::pollfd {
+                    fd: self.input.as_raw_fd(),
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+                libc::pollfd {
+                    fd: self.pipe,
+                    events: libc::POLLIN,
+                    revents: 0,
+                },
+            ];
+            if let Err(error) = check(unsafe { libc::poll(fds.as_mut_ptr(), 2, -1) }) {
+                if error.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Some(Err(error));
+            }
+
+            if fds[1].revents != 0 {
+                // Several resizes or wake-ups at once need only be seen as
+                // one, and a resize redraws everything anyway.
+                let mut resized = false;
+                let mut drained = [0u8; 64];
+                loop {
+                    let count = unsafe {
+                        libc::read(
+                            self.pipe,
+                            drained.as_mut_ptr() as *mut libc::c_void,
+                            drained.len(),
+                        )
+                    };
+                    if count <= 0 {
+                        break;
+                    }
+                    resized |= drained[..count as usize].contains(&RESIZED);
+                }
+                return Some(Ok(if resized { Event::Resize } else { Event::Wake }));
+            }
+
+            if fds[0].revents != 0 {
+                // Read straight from the descriptor, as anything buffered
+                // out of sight of `poll` would not be seen until the next
+                // key came.
+                let mut read = [0u8; 1024];
+                let count = unsafe {
+                    libc::read(
+                        self.input.as_raw_fd(),
+                        read.as_mut_ptr() as *mut libc::c_void,
+                        read.len(),
+                    )
+                };
+                match count {
+                    0 => return None,
+                    count if count > 0 => self.bytes.extend_from_slice(&read[..count as usize]),
+                    _ => {
+                        let error = io::Error::last_os_error();
+                        if error.kind() != io::ErrorKind::Interrupted {
+                            return Some(Err(error));
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl<R: AsRawFd> Drop for Events<R> {
+    fn drop(&mut self) {
+        let _ = set_handler(libc::SIG_DFL);
+        let write = PIPE.swap(-1, Ordering::Relaxed);
+        unsafe {
+            libc::close(write);
+            libc::close(self.pipe);
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! The screen as a grid of cells, drawn into off-screen. Each frame is
+//! written out as the difference from the one before, so the terminal is
+//! only sent the cells which have changed.
+//!
+//! Every character takes up one cell, except wide ones, such as most CJK
+//! characters, which take up two: the second is left as a continuation
+//! cell, which is never written out itself. Terminals show some characters
+//! narrower than they are counted here, so the cursor is moved explicitly
+//! after writing any but a plain one-column character rather than trusting
+//! where the terminal leaves it.
+
+use std::io;
+
+use termion::{cursor, style};
+use unicode_width::UnicodeWidthChar;
+
+use super::config::Colour;
+
+/// How a cell is shown.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Style {
+    pub colour: Colour,
+    pub inverted: bool,
+}
+
+impl Style {
+    pub const PLAIN: Style = Style {
+        colour: Colour::Default,
+        inverted: false,
+    };
+
+    pub fn colour(colour: Colour) -> Style {
+        Style {
+            colour,
+            inverted: false,
+        }
+    }
+
+    pub fn inverted(self) -> Style {
+        Style {
+            inverted: true,
+            ..self
+        }
+    }
+
+    /// Writes the escape codes which make text come out in this style,
+    /// whatever came before.
+    fn write<W: io::Write>(self, write: &mut W) -> io::Result<()> {
+        write!(write, "{}", style::Reset)?;
+        if self.colour != Colour::Default {
+            write!(write, "{}", self.colour)?;
+        }
+        if self.inverted {
+            write!(write, "{}", style::Invert)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Cell {
+    pub character: char,
+    pub style: Style,
+}
+
+impl Cell {
+    /// Whether this cell is covered by the wide character before it.
+    pub fn is_continuation(&self) -> bool {
+        self.character == CONTINUATION
+    }
+}
+
+const BLANK: Cell = Cell {
+    character: ' ',
+    style: Style::PLAIN,
+};
+
+/// What the cell after a wide character holds.
+const CONTINUATION: char = '\0';
+
+/// How many unchanged cells are written over rather than jumped past, as
+/// jumping takes several bytes.
+const JUMP: usize = 4;
+
+/// Whether the terminal moves its cursor on by exactly one column after
+/// showing `character`.
+fn narrow(character: char) -> bool {
+    character.width() == Some(1)
+}
+
+/// How many cells `character` takes up: two for a wide character, and one
+/// for any other, even those the terminal shows narrower.
+pub fn char_width(character: char) -> usize {
+    if character.width() == Some(2) {
+        2
+    } else {
+        1
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Grid {
+    width: usize,
+    height: usize,
+    cells: Vec<Cell>,
+}
+
+impl Grid {
+    /// A blank grid, as the terminal is once cleared.
+    pub fn new(width: u16, height: u16) -> Grid {
+        let (width, height) = (width as usize, height as usize);
+        Grid {
+            width,
+            height,
+            cells: vec![BLANK; width * height],
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// The cell at column `x` of row `y`, counting from zero.
+    pub fn cell(&self, x: usize, y: usize) -> Cell {
+        self.cells[y * self.width + x]
+    }
+
+    /// Sets the cell at column `x` of row `y`, counting from zero, if it
+    /// is on the grid, along with the continuation cell after it for a wide
+    /// character. A wide character cut off by the right edge is left blank,
+    /// as is what remains of one partly set over.
+    pub fn set(&mut self, x: usize, y: usize, character: char, style: Style) {
+        if x >= self.width || y >= self.height {
+            return;
+        }
+        let i = y * self.width + x;
+        let wide = char_width(character) == 2;
+        let character = if wide && x + 1 == self.width {
+            ' '
+        } else {
+            character
+        };
+
+        self.break_up(x, y);
+        self.cells[i] = Cell { character, style };
+        if wide && x + 1 < self.width {
+            self.break_up(x + 1, y);
+            self.cells[i + 1] = Cell {
+                character: CONTINUATION,
+                style,
+            };
+        }
+    }
+
+    /// Blanks the other half of the wide character the cell at `(x, y)` is
+    /// part of, if it is part of one.
+    fn break_up(&mut self, x: usize, y: usize) {
+        let i = y * self.width + x;
+        if self.cells[i].is_continuation() {
+            self.cells[i - 1].character = ' ';
+        } else if char_width(self.cells[i].character) == 2 {
+            self.cells[i + 1].character = ' ';
+        }
+    }
+
+    /// Writes `text` along row `y` from column `x`, cutting off whatever
+    /// does not fit, and gives the column after it.
+    pub fn put(&mut self, x: usize, y: usize, text: &str, style: Style) -> usize {
+        let mut x = x;
+        for character in text.chars() {
+            self.set(x, y, character, style);
+            x += char_width(character);
+        }
+        x
+    }
+
+    /// Blanks `width` cells of row `y` from column `x`.
+    pub fn clear(&mut self, x: usize, y: usize, width: usize) {
+        for x in x..x + width {
+            self.set(x, y, ' ', Style::PLAIN);
+        }
+    }
+
+    /// Writes what turns a terminal showing `old`, a grid of the same size,
+    /// into one showing this grid, leaving the cursor wherever it ends up.
+    pub fn diff<W: io::Write>(&self, old: &Grid, write: &mut W) -> io::Result<()> {
+        debug_assert_eq!((self.width, self.height), (old.width, old.height));
+        // Where the terminal's cursor and style are, once known.
+        let mut at: Option<(usize, usize)> = None;
+        let mut current: Option<Style> = None;
+
+        for y in 0..self.height {
+            for x in 0..self.width {
+                // A continuation cell is written along with the wide
+                // character before it, which differs whenever it does.
+                let cell = self.cell(x, y);
+                if cell == old.cell(x, y) || cell.is_continuation() {
+                    continue;
+                }
+
+                let near = match at {
+                    Some((at_x, at_y)) if at_y == y && at_x <= x && x - at_x <= JUMP => (at_x..x)
+                        .all(|x| {
+                            let cell = self.cell(x, y);
+                            Some(cell.style) == current && narrow(cell.character)
+                        }),
+                    _ => false,
+                };
+                if near {
+                    for x in at.unwrap().0..x {
+                        write!(write, "{}", self.cell(x, y).character)?;
+                    }
+                } else {
+                    write!(write, "{}", cursor::Goto(x as u16 + 1, y as u16 + 1))?;
+                }
+
+                if current != Some(cell.style) {
+                    cell.style.write(write)?;
+                    current = Some(cell.style);
+                }
+                write!(write, "{}", cell.character)?;
+                // The cursor stays put after the last column, and is not
+                // known to be in the next one after a character of another
+                // width.
+                at = if x + 1 < self.width && narrow(cell.character) {
+                    Some((x + 1, y))
+                } else {
+                    None
+                };
+            }
+        }
+
+        if current.is_some_and(|style| style != Style::PLAIN) {
+            write!(write, "{}", style::Reset)?;
+        }
+        Ok(())
+    }
+}
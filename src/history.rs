@@ -0,0 +1,326 @@
+/// A single reversible change to a buffer.
+///
+/// Positions are (line, column) pairs, with columns counted in characters.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Edit {
+    /// `text` was inserted at `column` of `line`. Never contains a newline.
+    Insert {
+        line: usize,
+        column: usize,
+        text: String,
+    },
+    /// `text` was removed from `column` of `line`. Never contains a newline.
+    Delete {
+        line: usize,
+        column: usize,
+        text: String,
+    },
+    /// `line` was broken in two at `column`.
+    Split { line: usize, column: usize },
+    /// The line after `line` was appended to it; `column` is where they met.
+    Join { line: usize, column: usize },
+}
+
+impl Edit {
+    pub fn invert(&self) -> Edit {
+        match *self {
+            Edit::Insert {
+                line,
+                column,
+                ref text,
+            } => Edit::Delete {
+                line,
+                column,
+                text: text.clone(),
+            },
+            Edit::Delete {
+                line,
+                column,
+                ref text,
+            } => Edit::Insert {
+                line,
+                column,
+                text: text.clone(),
+            },
+            Edit::Split { line, column } => Edit::Join { line, column },
+            Edit::Join { line, column } => Edit::Split { line, column },
+        }
+    }
+
+    /// Where the cursor would have been just before making this edit.
+    pub fn before(&self) -> (usize, usize) {
+        match *self {
+            Edit::Insert { line, column, .. } => (line, column),
+            Edit::Delete {
+                line,
+                column,
+                ref text,
+            } => (line, column + text.chars().count()),
+            Edit::Split { line, column } => (line, column),
+            Edit::Join { line, .. } => (line + 1, 0),
+        }
+    }
+
+    /// Where the cursor would be just after making this edit.
+    pub fn after(&self) -> (usize, usize) {
+        match *self {
+            Edit::Insert {
+                line,
+                column,
+                ref text,
+            } => (line, column + text.chars().count()),
+            Edit::Delete { line, column, .. } => (line, column),
+            Edit::Split { line, .. } => (line + 1, 0),
+            Edit::Join { line, column } => (line, column),
+        }
+    }
+
+    /// Where `position` ends up once this edit has been made, so positions
+    /// elsewhere in the buffer can follow the text they were on.
+    pub fn shift(&self, (at_line, at_column): (usize, usize)) -> (usize, usize) {
+        match *self {
+            Edit::Insert {
+                line,
+                column,
+                ref text,
+            } if at_line == line && at_column >= column => {
+                (at_line, at_column + text.chars().count())
+            }
+            Edit::Delete {
+                line,
+                column,
+                ref text,
+            } if at_line == line && at_column > column => {
+                let count = text.chars().count();
+                (
+                    at_line,
+                    std::cmp::max(column, at_column.saturating_sub(count)),
+                )
+            }
+            Edit::Split { line, column } if at_line == line && at_column >= column => {
+                (line + 1, at_column - column)
+            }
+            Edit::Split { line, .. } if at_line > line => (at_line + 1, at_column),
+            Edit::Join { line, column } if at_line == line + 1 => (line, column + at_column),
+            Edit::Join { line, .. } if at_line > line + 1 => (at_line - 1, at_column),
+            _ => (at_line, at_column),
+        }
+    }
+
+    fn is_typing(&self) -> bool {
+        matches!(*self, Edit::Insert { .. } | Edit::Split { .. })
+    }
+
+    /// Folds `next` into this edit if it simply continues it, as when
+    /// typing or backspacing one character at a time along a line.
+    fn extend(&mut self, next: &Edit) -> bool {
+        match (self, next) {
+            (
+                &mut Edit::Insert {
+                    line,
+                    column,
+                    ref mut text,
+                },
+                &Edit::Insert {
+                    line: next_line,
+                    column: next_column,
+                    text: ref next_text,
+                },
+            ) if line == next_line && column + text.chars().count() == next_column => {
+                text.push_str(next_text);
+                true
+            }
+            (
+                &mut Edit::Delete {
+                    line,
+                    ref mut column,
+                    ref mut text,
+                },
+                &Edit::Delete {
+                    line: next_line,
+                    column: next_column,
+                    text: ref next_text,
+                },
+            ) if line == next_line && next_column + next_text.chars().count() == *column => {
+                *column = next_column;
+                text.insert_str(0, next_text);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Undo and redo stacks of steps, each step being a list of edits in the
+/// order they were made.
+///
+/// While a transaction is open, edits collect in `pending` instead and only
+/// become a step when the outermost transaction commits. `marks` holds the
+/// length of `pending` at the start of each open transaction, so an inner
+/// transaction can be aborted without losing the edits around it.
+pub struct History {
+    undo: Vec<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+    /// While typing or backspacing can still carry on the last undo step:
+    /// whether it was typing, and where each cursor which made it was left.
+    open: Option<(bool, Vec<(usize, usize)>)>,
+    pending: Vec<Edit>,
+    marks: Vec<usize>,
+}
+
+impl History {
+    pub fn new() -> History {
+        History {
+            undo: vec![],
+            redo: vec![],
+            open: None,
+            pending: vec![],
+            marks: vec![],
+        }
+    }
+
+    /// Whether `edits`, made one after another, each carry on from where
+    /// the matching cursor was left by the last undo step, all typing or
+    /// all backspacing as it was.
+    fn continues(&self, edits: &[Edit]) -> bool {
+        let (typing, ends) = match self.open {
+            Some((typing, ref ends)) => (typing, ends),
+            None => return false,
+        };
+        ends.len() == edits.len()
+            && edits.iter().enumerate().all(|(i, edit)| {
+                // The edits before this one have moved where it was left.
+                let end = edits[..i]
+                    .iter()
+                    .fold(ends[i], |end, before| before.shift(end));
+                edit.is_typing() == typing && edit.before() == end
+            })
+    }
+
+    /// Leaves the last undo step open to be carried on by typing or
+    /// backspacing from where each of `edits` leaves its cursor.
+    fn open(&mut self, edits: &[Edit]) {
+        let ends = edits
+            .iter()
+            .enumerate()
+            .map(|(i, edit)| {
+                edits[i + 1..]
+                    .iter()
+                    .fold(edit.after(), |end, after| after.shift(end))
+            })
+            .collect();
+        self.open = Some((edits[0].is_typing(), ends));
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        !self.marks.is_empty()
+    }
+
+    pub fn begin(&mut self) {
+        self.marks.push(self.pending.len());
+    }
+
+    /// Closes the innermost transaction, returning whether that completed a
+    /// new undo step.
+    pub fn commit(&mut self) -> bool {
+        self.marks.pop().expect("commit outside of a transaction");
+        if self.in_transaction() || self.pending.is_empty() {
+            return false;
+        }
+
+        self.redo.clear();
+        self.undo.push(self.pending.split_off(0));
+        self.open = None;
+        true
+    }
+
+    /// Closes the innermost transaction as `commit` does, except that the
+    /// edits made in it are added to the last undo step if they carry it
+    /// on, as the same key typed at several cursors at once does.
+    pub fn commit_typing(&mut self) -> bool {
+        self.marks.pop().expect("commit outside of a transaction");
+        if self.in_transaction() || self.pending.is_empty() {
+            return false;
+        }
+
+        self.redo.clear();
+        let edits = self.pending.split_off(0);
+        let continues = self.continues(&edits);
+        self.open(&edits);
+        if continues {
+            let step = self.undo.last_mut().unwrap();
+            for edit in edits {
+                if !step.last_mut().unwrap().extend(&edit) {
+                    step.push(edit);
+                }
+            }
+        } else {
+            self.undo.push(edits);
+        }
+        true
+    }
+
+    /// Closes the innermost transaction, handing back the edits made in it
+    /// so they can be reverted.
+    pub fn abort(&mut self) -> Vec<Edit> {
+        let mark = self.marks.pop().expect("abort outside of a transaction");
+        self.pending.split_off(mark)
+    }
+
+    /// Records an edit that has just been made. Runs of typing, or of
+    /// backspacing, are coalesced into a single undo step.
+    pub fn record(&mut self, edit: Edit) {
+        if let Some(&mark) = self.marks.last() {
+            if self.pending.len() > mark && self.pending.last_mut().unwrap().extend(&edit) {
+                return;
+            }
+            self.pending.push(edit);
+            return;
+        }
+
+        self.redo.clear();
+        let edits = [edit];
+        let continues = self.continues(&edits);
+        self.open(&edits);
+        let [edit] = edits;
+
+        match self.undo.last_mut() {
+            Some(step) if continues => {
+                if !step.last_mut().unwrap().extend(&edit) {
+                    step.push(edit);
+                }
+            }
+            _ => self.undo.push(vec![edit]),
+        }
+    }
+
+    pub fn checkpoint(&mut self) {
+        self.open = None;
+    }
+
+    pub fn undo(&mut self) -> Option<Vec<Edit>> {
+        if self.in_transaction() {
+            return None;
+        }
+        self.open = None;
+        let step = self.undo.pop()?;
+        self.redo.push(step.clone());
+        Some(step)
+    }
+
+    pub fn redo(&mut self) -> Option<Vec<Edit>> {
+        if self.in_transaction() {
+            return None;
+        }
+        self.open = None;
+        let step = self.redo.pop()?;
+        self.undo.push(step.clone());
+        Some(step)
+    }
+}
+
+impl Default for History {
+    fn default() -> History {
+        History::new()
+    }
+}
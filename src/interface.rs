@@ -1,412 +1,2060 @@
-use std::sync::{Arc, Condvar, Mutex};
-use std::thread;
-use std::thread::JoinHandle;
+use std::collections::HashSet;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
 
-use super::buffer::Buffer;
-use super::cursor::Cursor;
+use super::action::Action;
+use super::backend::Backend;
+use super::buffer::{Buffer, Line};
+use super::command;
+use super::command::CommandLine;
+use super::config::{Config, SETTINGS};
+use super::cursor::{after, Cursor};
+use super::event;
+use super::event::Event;
+use super::grid::{char_width, Grid, Style};
+use super::history::Edit;
+use super::keymap::Keymap;
+use super::mode::{Mode, Motion, Operator};
+use super::plugin::{Hook, Host, Plugins};
+use super::script;
+use super::script::Script;
+use super::search::Search;
+use super::substitute;
+use super::substitute::{Replacement, Substitution};
+use super::window::{can_split, neighbour, Direction, Rect, Screen, Window};
+use super::writer::Writer;
 
 use termion::event::Key;
-use termion::input::TermRead;
-use termion::raw::{IntoRawMode, RawTerminal};
-use termion::screen::AlternateScreen;
-use termion::{clear, cursor, style, terminal_size};
-
-pub struct Interface<T: 'static + Send + Sync + std::io::Write> {
-    write: Arc<Mutex<RawTerminal<AlternateScreen<T>>>>,
-    offset: Arc<Mutex<usize>>,
+
+/// The most text, in bytes, that one paste may insert, however large its
+/// count.
+const MAX_PASTE: usize = 64 << 20;
+
+/// Text removed or yanked by an operator, ready to be pasted.
+#[derive(Default)]
+struct Register {
+    text: String,
+    linewise: bool,
+}
+
+/// An open buffer, with the cursors and scroll offset it had when it was
+/// last shown.
+struct View {
+    buffer: Arc<Mutex<Buffer>>,
+    cursor: Cursor,
+    cursors: Vec<Cursor>,
+    offset: usize,
+}
+
+impl View {
+    fn new(buffer: Buffer) -> View {
+        let mut buffer = buffer;
+        buffer.track_changes();
+        let buffer = Arc::new(Mutex::new(buffer));
+        View {
+            cursor: Cursor::new(&buffer),
+            buffer,
+            cursors: vec![],
+            offset: 0,
+        }
+    }
+}
+
+/// A question asked in the status line, which takes every key until it is
+/// answered.
+enum Prompt {
+    /// The name of a file to open, as typed so far.
+    Open(String),
+    /// Whether to close the current buffer despite its unsaved changes.
+    Close,
+    /// The name of a file to save the buffer to from now on, as typed so
+    /// far.
+    SaveAs(String),
+    /// Whether to write over the file `name`, which is already there. The
+    /// buffer takes the file's name if `rename`, as for `save_to`.
+    Overwrite { name: String, rename: bool },
+    /// Whether to make the directories missing on the way to the file
+    /// `name`.
+    CreateDirectory { name: String, rename: bool },
+    /// A command being typed on the command line.
+    Command(CommandLine),
+    /// Text to search for, as typed so far, and which way, from where the
+    /// cursor was when the search began.
+    Search {
+        pattern: String,
+        forward: bool,
+        origin: (usize, usize),
+    },
+    /// A substitution to make over a range of lines, as typed so far.
+    Substitute { text: String, lines: (usize, usize) },
+    /// Whether to make replacement `next`, those before it having been
+    /// asked about already and the ones agreed to kept in `chosen`.
+    Confirm {
+        replacements: Vec<Replacement>,
+        next: usize,
+        chosen: Vec<Replacement>,
+    },
+}
+
+impl Prompt {
+    fn text(&self) -> String {
+        match *self {
+            Prompt::Open(ref name) => format!("open: {}", name),
+            Prompt::Command(ref line) => format!(":{}", line.text()),
+            Prompt::Search {
+                ref pattern,
+                forward,
+                ..
+            } => format!("{}{}", if forward { '/' } else { '?' }, pattern),
+            Prompt::Substitute { ref text, .. } => format!("substitute: {}", text),
+            Prompt::Confirm {
+                ref replacements,
+                next,
+                ..
+            } => format!("replace with {:?}? (y/n/a/q)", replacements[next].text),
+            Prompt::Close => "buffer has unsaved changes, close it anyway? (y/n)".to_string(),
+            Prompt::SaveAs(ref name) => format!("save as: {}", name),
+            Prompt::Overwrite { ref name, .. } => format!("{} exists, overwrite it? (y/n)", name),
+            Prompt::CreateDirectory { ref name, .. } => {
+                let directory = Path::new(name).parent().unwrap_or(Path::new(""));
+                format!("{} does not exist, create it? (y/n)", directory.display())
+            }
+        }
+    }
+}
+
+/// What a window showed when it was last drawn, so that the next frame can
+/// leave alone the rows which are still right.
+struct Drawn {
+    area: Rect,
     buffer: Arc<Mutex<Buffer>>,
-    cursor: Arc<Mutex<Cursor>>,
-    size: Arc<Mutex<(u16, u16)>>,
-    draw_ready: Arc<(Mutex<bool>, Condvar)>,
-    cursor_update: Arc<(Mutex<bool>, Condvar)>,
-    will_stop: Arc<Mutex<bool>>,
-    splashed: Arc<Mutex<bool>>,
+    /// The width of the line numbers.
+    number_width: u16,
+    offset: usize,
+    shift: usize,
+    /// Each line shown, with the row it starts on and how many it takes.
+    layout: Vec<(usize, usize, usize)>,
+    selection: Option<((usize, usize), (usize, usize))>,
+    /// Where the cursors besides the main one were.
+    extra: Vec<(usize, usize)>,
+    /// The search whose matches were highlighted.
+    search: Option<Search>,
+    status: Vec<(String, Style)>,
+}
+
+/// What has changed since the last frame in ways `Drawn` cannot tell.
+#[derive(Default)]
+struct Damage {
+    /// Whether the whole screen needs drawing again.
+    all: bool,
+    /// The first and last line changed in a buffer, the last being
+    /// `usize::MAX` when every line after the first may have moved.
+    lines: Vec<(Arc<Mutex<Buffer>>, usize, usize)>,
+}
+
+impl Damage {
+    fn edits(&mut self, buffer: &Arc<Mutex<Buffer>>, changes: &[Edit]) {
+        for change in changes {
+            let (first, last) = match *change {
+                Edit::Insert { line, .. } | Edit::Delete { line, .. } => (line, line),
+                Edit::Split { line, .. } | Edit::Join { line, .. } => (line, usize::MAX),
+            };
+            self.lines.push((Arc::clone(buffer), first, last));
+        }
+    }
+
+    /// Whether `line` of `buffer` has changed.
+    fn line(&self, buffer: &Arc<Mutex<Buffer>>, line: usize) -> bool {
+        self.lines.iter().any(|(damaged, first, last)| {
+            Arc::ptr_eq(damaged, buffer) && (*first..=*last).contains(&line)
+        })
+    }
+}
+
+fn order(a: (usize, usize), b: (usize, usize)) -> ((usize, usize), (usize, usize)) {
+    if a <= b {
+        (a, b)
+    } else {
+        (b, a)
+    }
+}
+
+/// The width of the line number column for a buffer of `lines` lines,
+/// not counting the space after it.
+fn number_width(lines: usize) -> u16 {
+    f64::log10(lines as f64).floor() as u16 + 2
+}
+
+/// The width of the gutter, with the space after the line numbers, or
+/// nothing if line numbers are turned off.
+fn gutter_width(config: &Config, lines: usize) -> u16 {
+    if config.gutter {
+        number_width(lines) + 1
+    } else {
+        0
+    }
+}
+
+/// How many columns of text fit beside the gutter in `area`.
+fn text_width(config: &Config, area: Rect, lines: usize) -> u16 {
+    std::cmp::max(1, area.width.saturating_sub(gutter_width(config, lines)))
+}
+
+/// The screen column each character of `text` starts at, as if the line
+/// were on one unbroken row, followed by the column where the line ends.
+/// Tabs reach to the next multiple of the indent width, and wide
+/// characters take up two columns.
+fn columns(config: &Config, text: &Line) -> Vec<usize> {
+    let mut columns = Vec::with_capacity(text.len() + 1);
+    let mut x = 0;
+    for character in text.chars() {
+        columns.push(x);
+        x += if character == '\t' {
+            config.indent_width - x % config.indent_width
+        } else {
+            char_width(character)
+        };
+    }
+    columns.push(x);
+    columns
+}
+
+/// How many rows a line whose columns are `columns` takes up.
+fn line_rows(config: &Config, width: usize, columns: &[usize]) -> usize {
+    if config.wrap {
+        columns[columns.len() - 1] / width + 1
+    } else {
+        1
+    }
+}
+
+/// How far the lines of a window are scrolled sideways when they are not
+/// wrapped. They move a whole width at a time, to keep the cursor, at
+/// screen column `x` of its line, in view.
+fn shift(config: &Config, width: usize, x: usize) -> usize {
+    if config.wrap {
+        0
+    } else {
+        x / width * width
+    }
 }
 
-impl<T: 'static + Send + Sync + std::io::Write> Interface<T> {
-    pub fn from(t: T) -> Interface<T> {
-        let buffer = Arc::new(Mutex::new(Buffer::new()));
-        let interface = Interface::from_buffer(t, buffer);
-        *interface.splashed.lock().unwrap() = false;
+/// Where screen column `x` of a line is drawn, as a row within the line
+/// and a column within the text, if it is in view.
+fn place(config: &Config, width: usize, shift: usize, x: usize) -> Option<(usize, usize)> {
+    if config.wrap {
+        Some((x / width, x % width))
+    } else if x >= shift && x < shift + width {
+        Some((0, x - shift))
+    } else {
+        None
+    }
+}
+
+/// The lines of `buffer` shown in `area` when scrolled down to line
+/// `offset`, each with the row it starts on and how many rows it takes up.
+/// The last row of the area is left for the status line.
+fn layout(
+    config: &Config,
+    buffer: &Buffer,
+    area: Rect,
+    offset: usize,
+) -> Vec<(usize, usize, usize)> {
+    let rows = area.height.saturating_sub(1) as usize;
+    let width = text_width(config, area, buffer.len()) as usize;
+    let mut layout = vec![];
+    let mut row = 0;
+    let mut line = offset;
+    while row < rows && line < buffer.len() {
+        let taken = line_rows(config, width, &columns(config, buffer.borrow_line(line)));
+        layout.push((line, row, taken));
+        row += taken;
+        line += 1;
+    }
+    layout
+}
+
+/// Draws `line` of `buffer` over the rows of `area` from `row`, stopping
+/// above the status line, with the line scrolled sideways by `shift`.
+/// Each position is shown in the style `style` gives it, the one just past
+/// the end of the line only if that is not plain.
+fn draw_line<F>(
+    grid: &mut Grid,
+    area: Rect,
+    config: &Config,
+    buffer: &Buffer,
+    (line, row): (usize, usize),
+    shift: usize,
+    style: F,
+) where
+    F: Fn((usize, usize)) -> Style,
+{
+    let rows = area.height.saturating_sub(1) as usize;
+    let gutter = gutter_width(config, buffer.len()) as usize;
+    let width = text_width(config, area, buffer.len()) as usize;
+    let (left, top) = (area.x as usize, area.y as usize + row);
+
+    let text = buffer.borrow_line(line);
+    let columns = columns(config, text);
+    let end = std::cmp::min(row + line_rows(config, width, &columns), rows);
+    for row in row..end {
+        grid.clear(left, area.y as usize + row, area.width as usize);
+    }
+    if config.gutter {
+        let number = format!("{: >1$}", line + 1, number_width(buffer.len()) as usize);
+        grid.put(left, top, &number, Style::colour(config.theme.line_numbers));
+    }
+
+    let cells = text
+        .chars()
+        .enumerate()
+        .flat_map(|(column, character)| {
+            // Control characters, such as the `\r` left at the end of some
+            // lines in a file which mixes line endings, are shown by the
+            // pictures standing for them.
+            let shown = match character {
+                '\t' => ' ',
+                '\u{7f}' => '\u{2421}',
+                c if c < ' ' => std::char::from_u32(0x2400 + c as u32).unwrap(),
+                c => c,
+            };
+            // The grid fills in the rest of a wide character itself.
+            let end = if character == '\t' {
+                columns[column + 1]
+            } else {
+                columns[column] + 1
+            };
+            (columns[column]..end).map(move |x| (x, column, shown))
+        })
+        .chain(Some((columns[text.len()], text.len(), ' ')));
+
+    for (x, column, shown) in cells {
+        let style = style((line, column));
+        if column == text.len() && style == Style::PLAIN {
+            break;
+        }
+        let (within, x) = match place(config, width, shift, x) {
+            Some(place) => place,
+            None => continue,
+        };
+        if row + within >= end {
+            break;
+        }
+        debug_assert!(x < width);
+        // A wide character is only drawn where all of it fits on the row.
+        let shown = if x + char_width(shown) > width {
+            ' '
+        } else {
+            shown
+        };
+        grid.set(left + gutter + x, top + within, shown, style);
+    }
+}
+
+/// Draws the marker for a row of `area` past the end of the buffer.
+fn draw_tilde(grid: &mut Grid, area: Rect, config: &Config, row: usize) {
+    let (left, top) = (area.x as usize, area.y as usize + row);
+    grid.clear(left, top, area.width as usize);
+    grid.put(left, top, "~", Style::colour(config.theme.tildes));
+}
+
+/// Cuts `text` down to at most `width` characters.
+fn fit(text: &str, width: usize) -> &str {
+    match text.char_indices().nth(width) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+pub struct Interface<B: Backend> {
+    backend: B,
+    /// The next frame, drawn over the last.
+    grid: Grid,
+    screen: Screen,
+    /// The focused window's buffer.
+    buffer: Arc<Mutex<Buffer>>,
+    size: (u16, u16),
+    /// What each window showed at the last frame, by index, and the areas
+    /// they were given.
+    drawn: Vec<Option<Drawn>>,
+    areas: Vec<(usize, Rect)>,
+    damage: Damage,
+    /// Whether the splash screen is showing, until the first key.
+    splash: bool,
+    /// Shared with the writer, which reports on saves here.
+    message: Arc<Mutex<Option<String>>>,
+    writer: Writer,
+    mode: Mode,
+    keymap: Keymap,
+    /// Set once the user has asked to quit.
+    quitting: bool,
+    register: Register,
+    /// Every open buffer, with the state of its view when it was last
+    /// switched away from.
+    views: Vec<View>,
+    /// The focused buffer's place in the list, from one, and the list's
+    /// length.
+    listing: (usize, usize),
+    prompt: Option<Prompt>,
+    /// The last search made, with whether it went forward.
+    search: Option<(Search, bool)>,
+    /// Whether the last search's matches are highlighted.
+    highlight: bool,
+    /// The last substitution made, as written.
+    substitution: Option<String>,
+    /// Commands run from the command line, oldest first.
+    history: Vec<String>,
+    config: Arc<Config>,
+    plugins: Plugins,
+    script: Script,
+    /// Edits made since plugins were last told about them.
+    edits: Vec<Edit>,
+}
+
+impl<B: Backend> Interface<B> {
+    pub fn from(backend: B) -> Interface<B> {
+        let mut interface = Interface::from_buffers(backend, vec![Buffer::new()]);
+        interface.splash = interface.config.splash;
         interface
     }
 
-    pub fn from_buffer(t: T, buffer: Arc<Mutex<Buffer>>) -> Interface<T> {
+    /// Opens the interface on a list of buffers, showing the first.
+    pub fn from_buffers(backend: B, buffers: Vec<Buffer>) -> Interface<B> {
+        let views = buffers.into_iter().map(View::new).collect::<Vec<_>>();
+        let window = Window::new(views[0].cursor.clone());
+        let (config, mut errors) = Config::load();
+        let (plugins, plugin_errors) = Plugins::load();
+        errors.extend(plugin_errors);
+        let message = Arc::new(Mutex::new(if errors.is_empty() {
+            None
+        } else {
+            Some(errors.join("; "))
+        }));
+
+        let writer = Writer::spawn(Arc::clone(&message), event::wake);
+
+        // The size is only asked for once the editor starts.
         Interface {
-            size: Arc::new(Mutex::new(terminal_size().unwrap())),
-            write: Arc::new(Mutex::new(
-                AlternateScreen::from(t).into_raw_mode().unwrap(),
-            )),
-            offset: Arc::new(Mutex::new(0)),
-            buffer: Arc::clone(&buffer),
-            cursor: Arc::new(Mutex::new(Cursor::new(&buffer))),
-            draw_ready: Arc::new((Mutex::new(true), Condvar::new())),
-            cursor_update: Arc::new((Mutex::new(true), Condvar::new())),
-            will_stop: Arc::new(Mutex::new(false)),
-            splashed: Arc::new(Mutex::new(true)),
-        }
-    }
-
-    fn start_resize(&mut self) -> JoinHandle<()> {
-        let size = Arc::clone(&self.size);
-        let draw_ready = Arc::clone(&self.draw_ready);
-        let cursor_update = Arc::clone(&self.cursor_update);
-        let will_stop = Arc::clone(&self.will_stop);
-        let splashed = Arc::clone(&self.splashed);
-
-        thread::spawn(move || loop {
-            if *will_stop.lock().unwrap() {
-                break;
+            backend,
+            size: (0, 0),
+            grid: Grid::new(0, 0),
+            buffer: Arc::clone(&views[0].buffer),
+            screen: Screen::new(window),
+            drawn: vec![],
+            areas: vec![],
+            damage: Damage {
+                all: true,
+                lines: vec![],
+            },
+            splash: false,
+            message,
+            writer,
+            mode: Mode::Normal,
+            keymap: Keymap::new(config.keys()),
+            quitting: false,
+            register: Register::default(),
+            listing: (1, views.len()),
+            views,
+            prompt: None,
+            search: None,
+            highlight: false,
+            substitution: None,
+            history: vec![],
+            config: Arc::new(config),
+            plugins,
+            script: Script::default(),
+            edits: vec![],
+        }
+    }
+
+    /// Shows `message` in the status line until it is replaced.
+    pub fn set_message(&self, message: String) {
+        *self.message.lock().unwrap() = Some(message);
+    }
+
+    /// Shows `message` in the status line after any message showing
+    /// already, such as one about the configuration.
+    pub fn add_message(&self, message: String) {
+        let mut shown = self.message.lock().unwrap();
+        *shown = Some(match shown.take() {
+            Some(before) => format!("{}; {}", before, message),
+            None => message,
+        });
+    }
+
+    /// Takes note of the screen's new size, redrawing everything to fit.
+    fn resize(&mut self) -> io::Result<()> {
+        let size = self.backend.size()?;
+        if self.size != size {
+            self.size = size;
+            self.damage.all = true;
+        }
+        Ok(())
+    }
+
+    /// Keeps the focused window's cursor in view, scrolling it down, up or
+    /// sideways as needed.
+    fn scroll(&mut self) {
+        let (width, height) = self.size;
+        let area = self.screen.focused(Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        });
+        let rows = area.height.saturating_sub(1) as usize;
+
+        let (line, column) = self.position();
+        let config = &self.config;
+        let window = &mut self.screen.windows[self.screen.focus];
+        let offset = &mut window.offset;
+        let buffer = self.buffer.lock().unwrap();
+        let width = text_width(config, area, buffer.len()) as usize;
+        let taken =
+            |line: usize| line_rows(config, width, &columns(config, buffer.borrow_line(line)));
+
+        if line < *offset {
+            *offset = line;
+        } else {
+            // Every line takes at least a row, so only the last few before
+            // the cursor's need counting.
+            *offset = std::cmp::max(*offset, (line + 1).saturating_sub(rows));
+            let mut used = (*offset..=line).map(taken).sum::<usize>();
+            while used > rows && *offset < line {
+                used -= taken(*offset);
+                *offset += 1;
             }
+        }
+
+        let x = columns(config, buffer.borrow_line(line))[column];
+        window.shift = shift(config, width, x);
+    }
+
+    /// Brings the screen up to date, drawing only the rows which have
+    /// changed, and leaves the cursor where it should be.
+    fn frame(&mut self) -> io::Result<()> {
+        self.scroll();
+        let (width, height) = self.size;
+        let whole = Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        };
+        let areas = self.screen.layout.areas(whole);
+        if areas != self.areas {
+            self.damage.all = true;
+        }
+
+        if self.damage.all {
+            self.grid = Grid::new(width, height);
+            self.drawn.clear();
+        }
+        self.drawn.resize_with(self.screen.windows.len(), || None);
+
+        let mut status_drawn = false;
+        for &(index, area) in &areas {
+            status_drawn |= self.draw_window(index, area);
+        }
 
-            let mut size_change = false;
-            let new_size = terminal_size().unwrap();
-            {
-                let mut size = size.lock().unwrap();
-                if *size != new_size {
-                    *size = new_size;
-                    size_change = true;
+        if self.damage.all {
+            let style = Style::colour(self.config.theme.divider);
+            for divider in self.screen.layout.dividers(whole) {
+                for row in 0..divider.height {
+                    let (x, y) = (divider.x as usize, (divider.y + row) as usize);
+                    self.grid.set(x, y, '|', style);
                 }
             }
+        }
+        if self.splash && self.damage.all {
+            self.draw_splash();
+        }
+        if status_drawn {
+            let x = width.saturating_sub(4) as usize;
+            self.grid.put(x, height as usize - 1, "nep ", Style::PLAIN);
+        }
 
-            if size_change {
-                *splashed.lock().unwrap() = true;
-                let &(ref lock, ref cvar) = &*cursor_update;
-                *lock.lock().unwrap() = true;
-                cvar.notify_one();
+        let place = self.cursor_place(&areas);
+        self.backend.present(&self.grid, place)?;
 
-                let &(ref lock, ref cvar) = &*draw_ready;
-                *lock.lock().unwrap() = true;
-                cvar.notify_one();
-            }
-        })
+        self.areas = areas;
+        self.damage = Damage::default();
+        Ok(())
     }
 
-    fn start_draw(&mut self) -> JoinHandle<()> {
-        let size = Arc::clone(&self.size);
-        let draw_ready = Arc::clone(&self.draw_ready);
-        let will_stop = Arc::clone(&self.will_stop);
-        let write = Arc::clone(&self.write);
-        let buffer = Arc::clone(&self.buffer);
-        let offset = Arc::clone(&self.offset);
-        let splashed = Arc::clone(&self.splashed);
+    /// Draws the rows of window `index`, given `area`, which have changed
+    /// since the last frame, returning whether its status line was drawn.
+    /// A window given no room at all is not drawn.
+    fn draw_window(&mut self, index: usize, area: Rect) -> bool {
+        if area.width == 0 || area.height == 0 {
+            self.drawn[index] = None;
+            return false;
+        }
+        let window = &self.screen.windows[index];
+        let focused = index == self.screen.focus;
+        let (offset, shift) = (window.offset, window.shift);
+        let cursor = &window.cursor;
+        let selection = match self.mode {
+            Mode::Visual(line, column) if focused => {
+                Some(order((line, column), (cursor.line(), cursor.column())))
+            }
+            _ => None,
+        };
+        let extra = window
+            .cursors
+            .iter()
+            .map(|cursor| (cursor.line(), cursor.column()))
+            .collect::<Vec<_>>();
+        let shared = cursor.buffer();
+        let buffer = shared.lock().unwrap();
+        let config = &self.config;
+        let layout = layout(config, &buffer, area, offset);
+        let status = self.status(&buffer, area, focused);
+        let search = self.highlighted();
 
-        thread::spawn(move || loop {
-            if *will_stop.lock().unwrap() {
-                break;
+        let drawn = self.drawn[index].take().filter(|drawn| {
+            drawn.area == area
+                && Arc::ptr_eq(&drawn.buffer, &shared)
+                && drawn.number_width == number_width(buffer.len())
+                && drawn.offset == offset
+                && drawn.shift == shift
+        });
+
+        // The lines whose marks may have changed.
+        let mut marked = vec![];
+        if let Some(ref drawn) = drawn {
+            if drawn.selection != selection {
+                let ends = drawn.selection.iter().chain(selection.iter());
+                let first = ends.clone().map(|&(start, _)| start.0).min().unwrap();
+                let last = ends.map(|&(_, end)| end.0).max().unwrap();
+                marked.push((first, last));
+            }
+            if drawn.extra != extra {
+                for &(line, _) in drawn.extra.iter().chain(extra.iter()) {
+                    marked.push((line, line));
+                }
             }
+            if drawn.search != search {
+                marked.push((0, usize::MAX));
+            }
+        }
+
+        for (i, &(line, row, rows)) in layout.iter().enumerate() {
+            let unchanged = drawn.as_ref().is_some_and(|drawn| {
+                drawn.layout.get(i) == Some(&(line, row, rows))
+                    && !self.damage.line(&shared, line)
+                    && !marked
+                        .iter()
+                        .any(|&(first, last)| first <= line && line <= last)
+            });
+            if unchanged {
+                continue;
+            }
+            let matches = search
+                .as_ref()
+                .map_or(vec![], |search| search.matches(buffer.borrow_line(line)));
+            draw_line(
+                &mut self.grid,
+                area,
+                config,
+                &buffer,
+                (line, row),
+                shift,
+                |position| {
+                    if selection.is_some_and(|(start, end)| start <= position && position <= end)
+                        || extra.contains(&position)
+                    {
+                        Style::PLAIN.inverted()
+                    } else if matches
+                        .iter()
+                        .any(|&(start, end)| start <= position.1 && position.1 < end)
+                    {
+                        Style::colour(config.theme.search).inverted()
+                    } else {
+                        Style::PLAIN
+                    }
+                },
+            );
+        }
 
-            let &(ref lock, ref cvar) = &*draw_ready;
-            let mut ready = lock.lock().unwrap();
-            while !*ready {
-                ready = cvar.wait(ready).unwrap();
+        let end = |layout: &[(usize, usize, usize)]| {
+            layout.last().map_or(0, |&(_, row, rows)| row + rows)
+        };
+        let rows = area.height.saturating_sub(1) as usize;
+        let tildes = match drawn {
+            Some(ref drawn) => end(&layout)..std::cmp::max(end(&layout), end(&drawn.layout)),
+            None => end(&layout)..rows,
+        };
+        for row in tildes.take_while(|&row| row < rows) {
+            draw_tilde(&mut self.grid, area, config, row);
+        }
+
+        let status_drawn = drawn.as_ref().is_none_or(|drawn| drawn.status != status);
+        if status_drawn {
+            let (mut x, y) = (area.x as usize, (area.y + area.height) as usize - 1);
+            for (text, style) in &status {
+                x = self.grid.put(x, y, text, *style);
             }
+        }
 
-            let mut write = write.lock().unwrap();
-            let (width, height) = *size.lock().unwrap();
-            let offset = *offset.lock().unwrap();
-            let buffer = buffer.lock().unwrap();
+        self.drawn[index] = Some(Drawn {
+            area,
+            buffer: Arc::clone(&shared),
+            number_width: number_width(buffer.len()),
+            offset,
+            shift,
+            layout,
+            selection,
+            extra,
+            search,
+            status,
+        });
+        status_drawn
+    }
 
-            write!(write, "{}{}", cursor::Hide, cursor::Save);
-            if *splashed.lock().unwrap() {
-                write!(write, "{}", clear::All);
+    /// The search whose matches are highlighted: the one being typed, if
+    /// any, or else the last one made, until it is put away.
+    fn highlighted(&self) -> Option<Search> {
+        match self.prompt {
+            Some(Prompt::Search { ref pattern, .. }) if pattern.is_empty() => None,
+            Some(Prompt::Search { ref pattern, .. }) => {
+                Some(Search::new(pattern, self.config.search_case))
             }
+            _ if self.highlight => self.search.as_ref().map(|(search, _)| search.clone()),
+            _ => None,
+        }
+    }
 
-            let line_number_width = f64::log10(buffer.len() as f64).floor() as u16 + 2;
+    /// The status line of a window given `area` showing `buffer`, as pieces
+    /// of text in their styles which fill its width. The focused window's
+    /// shows more.
+    fn status(&self, buffer: &Buffer, area: Rect, focused: bool) -> Vec<(String, Style)> {
+        let config = &self.config;
+        let width = area.width as usize;
+        let mut status = vec![];
+        let used = if focused {
+            let (shown, open) = self.listing;
+            let label = format!(
+                " {} {} [{}/{}] ",
+                self.mode.name(),
+                buffer.get_name(),
+                shown,
+                open
+            );
+            let label = fit(&label, width);
+            status.push((
+                label.to_string(),
+                Style::colour(config.theme.status).inverted(),
+            ));
 
-            let mut written = 0u16;
-            let mut used = 0usize;
-            while written < height.saturating_sub(1) {
-                if offset + used >= buffer.len() {
-                    break;
+            let mut used = label.chars().count();
+            if let Some(ref message) = *self.message.lock().unwrap() {
+                let message = fit(message, width.saturating_sub(used + 1));
+                if !message.is_empty() {
+                    status.push((" ".to_string(), Style::PLAIN));
+                    status.push((message.to_string(), Style::colour(config.theme.message)));
+                    used += message.chars().count() + 1;
                 }
+            }
+            used
+        } else {
+            let label = format!(" {} ", buffer.get_name());
+            let label = fit(&label, width);
+            status.push((label.to_string(), Style::PLAIN));
+            label.chars().count()
+        };
+        status.push((" ".repeat(width.saturating_sub(used)), Style::PLAIN));
+        status
+    }
 
-                let line = buffer.borrow_line(offset + used);
-                write!(write, "{}", cursor::Goto(1, written as u16 + 1));
-                write!(
-                    write,
-                    "{line: >0$} ",
-                    line_number_width as usize,
-                    line = offset + used + 1
-                );
+    /// Where on the screen the focused window's cursor is, counting from
+    /// zero.
+    fn cursor_place(&self, areas: &[(usize, Rect)]) -> (u16, u16) {
+        let focus = self.screen.focus;
+        let area = match areas.iter().find(|&&(index, _)| index == focus) {
+            Some(&(_, area)) => area,
+            None => return (0, 0),
+        };
+        let drawn = match self.drawn[focus] {
+            Some(ref drawn) => drawn,
+            None => return (area.x, area.y),
+        };
+        if let Some(Prompt::Command(ref line)) = self.prompt {
+            // In the status line, after the label, a space and the colon.
+            let label = drawn.status[0].0.chars().count();
+            let x = std::cmp::min(label + 2 + line.cursor(), area.width as usize - 1);
+            return (area.x + x as u16, area.y + area.height - 1);
+        }
+        let (line, column) = self.position();
+        let buffer = self.buffer.lock().unwrap();
 
-                let mut characters_written = line_number_width + 1;
-                for character in line.borrow_chars().iter() {
-                    write!(write, "{}", character);
-                    characters_written += 1;
+        let width = text_width(&self.config, area, buffer.len()) as usize;
+        let row = drawn
+            .layout
+            .iter()
+            .find(|&&(shown, _, _)| shown == line)
+            .map_or(0, |&(_, row, _)| row);
+        let x = columns(&self.config, buffer.borrow_line(line))[column];
+        let (within, x) = place(&self.config, width, drawn.shift, x).unwrap_or((0, 0));
+        (
+            area.x + gutter_width(&self.config, buffer.len()) + x as u16,
+            area.y + (row + within) as u16,
+        )
+    }
 
-                    if characters_written >= width {
-                        written += 1;
-                        characters_written = line_number_width + 1;
-                        if written >= height.saturating_sub(2) {
-                            break;
-                        } else {
-                            write!(write, "{}", cursor::Goto(line_number_width, written + 1));
-                            write!(write, "{line: >0$}", line_number_width as usize, line = " ");
-                        }
-                    }
+    /// Draws the splash screen over the empty buffer, if it fits above the
+    /// status line.
+    fn draw_splash(&mut self) {
+        let (width, height) = self.size;
+        let splash = include_str!("splash")
+            .lines()
+            .map(|line| line.trim_end_matches('#'))
+            .collect::<Vec<_>>();
+        let splash_width = splash[0].len();
+        let rows = height.saturating_sub(1) as usize;
+        if (width as usize) < splash_width + 6 || rows < splash.len() + 2 {
+            return;
+        }
+
+        let top = (rows - splash.len()) / 2;
+        let left = (width as usize - splash_width) / 2;
+        for (i, line) in splash.iter().enumerate() {
+            self.grid.put(left, top + i, line, Style::PLAIN);
+        }
+    }
+
+    /// Runs the editor until it is quit or its events run out, then gives
+    /// back the backend. If the backend fails, it is dropped, and the error
+    /// given back once any saves under way have finished.
+    pub fn start(mut self) -> io::Result<B> {
+        let result = self.run();
+        self.writer.finish();
+        result.map(|()| self.backend)
+    }
+
+    fn run(&mut self) -> io::Result<()> {
+        self.resize()?;
+        let buffers = self
+            .views
+            .iter()
+            .map(|view| Arc::clone(&view.buffer))
+            .collect::<Vec<_>>();
+        self.run_init();
+        for buffer in buffers {
+            self.hook(Hook::Open, &buffer);
+        }
+        self.frame()?;
+
+        while let Some(event) = self.backend.event() {
+            let key = match event? {
+                Event::Key(key) => key,
+                Event::Resize => {
+                    self.resize()?;
+                    self.frame()?;
+                    continue;
                 }
-                written += 1;
-                used += 1;
+                Event::Wake => {
+                    self.frame()?;
+                    continue;
+                }
+            };
+            if self.splash {
+                self.splash = false;
+                self.damage.all = true;
             }
 
-            while written < height.saturating_sub(1) {
-                write!(write, "{}", cursor::Goto(1, written + 1));
-                write!(write, "{}", "~");
-                written += 1;
+            if self.prompt.is_some() {
+                self.prompt_key(key);
+            } else {
+                let action = {
+                    let (script, plugins) = (&self.script, &self.plugins);
+                    self.keymap.feed(self.mode, key, |key| {
+                        script.binding(key).or_else(|| plugins.binding(key))
+                    })
+                };
+                if let Some(action) = action {
+                    self.dispatch(action);
+                }
             }
 
-            write!(
-                write,
-                "{}{} {} ",
-                cursor::Goto(1, height),
-                style::Invert,
-                buffer.get_name(),
-            );
+            if self.quitting {
+                break;
+            }
+
+            self.follow_changes();
+            if !self.edits.is_empty() {
+                let buffer = Arc::clone(&self.buffer);
+                self.hook(Hook::Edit, &buffer);
+                self.edits.clear();
+            }
+            if self.backend.waits_for_saves() {
+                self.writer.settle();
+            }
+            self.frame()?;
+        }
+        Ok(())
+    }
 
-            write!(write, "{}", style::Reset);
+    /// Lends the plugins this interface to act on, then moves the cursors
+    /// along with any edits they made.
+    fn with_plugins<F>(&mut self, call: F)
+    where
+        F: FnOnce(&mut Plugins, &mut Self) -> Vec<String>,
+    {
+        let mut plugins = std::mem::take(&mut self.plugins);
+        let errors = call(&mut plugins, self);
+        self.plugins = plugins;
 
-            write!(
-                write,
-                "{}nep ",
-                cursor::Goto(width.saturating_sub(3), height),
-            );
+        // Plugins are not told about their own edits.
+        let reported = self.edits.len();
+        self.follow_changes();
+        self.edits.truncate(reported);
 
-            write!(write, "{}{}", cursor::Restore, cursor::Show);
-            *ready = false;
-            write.flush().unwrap();
-        })
+        if !errors.is_empty() {
+            self.set_message(errors.join("; "));
+        }
+    }
+
+    fn run_plugin(&mut self, name: &str) {
+        let buffer = Arc::clone(&self.buffer);
+        self.with_plugins(|plugins, host| plugins.run(name, host, &buffer));
     }
 
-    fn start_cursor_update(&mut self) -> JoinHandle<()> {
-        let size = Arc::clone(&self.size);
-        let draw_ready = Arc::clone(&self.draw_ready);
-        let cursor_update = Arc::clone(&self.cursor_update);
-        let will_stop = Arc::clone(&self.will_stop);
-        let write = Arc::clone(&self.write);
+    /// Lends the script interpreter this interface and the focused buffer
+    /// to act on, making the edits it makes a single undo step, or taking
+    /// them back if it fails.
+    fn with_script<F>(&mut self, call: F) -> Result<(), script::Error>
+    where
+        F: FnOnce(&mut Script, &mut Self, &Arc<Mutex<Buffer>>) -> Result<(), script::Error>,
+    {
         let buffer = Arc::clone(&self.buffer);
-        let cursor = Arc::clone(&self.cursor);
-        let offset = Arc::clone(&self.offset);
+        let mut script = std::mem::take(&mut self.script);
+        buffer.lock().unwrap().begin();
+        let result = call(&mut script, self, &buffer);
+        if result.is_ok() {
+            buffer.lock().unwrap().commit();
+        } else {
+            buffer.lock().unwrap().abort();
+        }
+        self.script = script;
 
-        thread::spawn(move || loop {
-            if *will_stop.lock().unwrap() {
-                break;
+        self.follow_changes();
+        result
+    }
+
+    /// Runs the startup script, if there is one.
+    fn run_init(&mut self) {
+        let path = match super::config_directory() {
+            Some(directory) => directory.join("init.nep"),
+            None => return,
+        };
+        let source = match std::fs::read_to_string(&path) {
+            Ok(source) => source,
+            Err(ref error) if error.kind() == std::io::ErrorKind::NotFound => return,
+            Err(error) => {
+                self.set_message(format!("{}: {}", path.display(), error));
+                return;
+            }
+        };
+
+        if let Err(error) =
+            self.with_script(|script, host, buffer| script.run(&source, host, buffer))
+        {
+            self.set_message(format!("init.nep {}", error));
+        }
+    }
+
+    /// Runs the command `name`, which may come from a script or a plugin.
+    fn run_command(&mut self, name: &str) {
+        if self.script.has_command(name) {
+            let result =
+                self.with_script(|script, host, buffer| script.command(name, host, buffer));
+            if let Err(error) = result {
+                self.set_message(format!("{}: {}", name, error));
+            }
+        } else if self.plugins.has_command(name) {
+            self.run_plugin(name);
+        } else {
+            self.set_message(format!("no command {}", name));
+        }
+    }
+
+    fn hook(&mut self, hook: Hook, buffer: &Arc<Mutex<Buffer>>) {
+        if !self.plugins.hooked(hook) {
+            return;
+        }
+        let edits = std::mem::take(&mut self.edits);
+        self.with_plugins(|plugins, host| plugins.hook(hook, &edits, host, buffer));
+    }
+
+    fn ask(&mut self, prompt: Prompt) {
+        self.set_message(prompt.text());
+        self.prompt = Some(prompt);
+    }
+
+    /// Passes a key to the open prompt, acting on the answer once given.
+    fn prompt_key(&mut self, key: Key) {
+        *self.message.lock().unwrap() = None;
+        match (self.prompt.take().unwrap(), key) {
+            (Prompt::Search { origin, .. }, Key::Esc)
+            | (Prompt::Search { origin, .. }, Key::Ctrl('c')) => {
+                self.window_mut().cursor.goto(origin.0, origin.1)
+            }
+            // Stopping part way through still makes the replacements agreed
+            // to so far.
+            (Prompt::Confirm { chosen, .. }, Key::Esc)
+            | (Prompt::Confirm { chosen, .. }, Key::Ctrl('c'))
+            | (Prompt::Confirm { chosen, .. }, Key::Char('q')) => self.replace(chosen),
+            (_, Key::Esc) | (_, Key::Ctrl('c')) => (),
+            (Prompt::Open(name), Key::Char('\n')) => self.open(name),
+            (Prompt::Open(mut name), Key::Char(c)) => {
+                name.push(c);
+                self.ask(Prompt::Open(name));
+            }
+            (Prompt::Open(mut name), Key::Backspace) => {
+                name.pop();
+                self.ask(Prompt::Open(name));
+            }
+            (prompt @ Prompt::Open(_), _) => self.ask(prompt),
+            (Prompt::Close, Key::Char('y')) => self.close(),
+            (Prompt::Close, _) => (),
+            (Prompt::SaveAs(name), Key::Char('\n')) => self.save_to(name, true, false),
+            (Prompt::SaveAs(mut name), Key::Char(c)) => {
+                name.push(c);
+                self.ask(Prompt::SaveAs(name));
+            }
+            (Prompt::SaveAs(mut name), Key::Backspace) => {
+                name.pop();
+                self.ask(Prompt::SaveAs(name));
+            }
+            (prompt @ Prompt::SaveAs(_), _) => self.ask(prompt),
+            (Prompt::Overwrite { name, rename }, Key::Char('y')) => {
+                self.save_to(name, rename, true)
+            }
+            (Prompt::Overwrite { .. }, _) => (),
+            (Prompt::CreateDirectory { name, rename }, Key::Char('y')) => {
+                let directory = Path::new(&name).parent().unwrap_or(Path::new(""));
+                match fs::create_dir_all(directory) {
+                    Ok(()) => self.save_to(name, rename, true),
+                    Err(error) => self.set_message(format!("{}: {}", directory.display(), error)),
+                }
+            }
+            (Prompt::CreateDirectory { .. }, _) => (),
+            (Prompt::Command(line), Key::Char('\n')) => self.execute(line.text().to_string()),
+            // Backing out of an empty command line closes it.
+            (Prompt::Command(ref line), Key::Backspace) if line.text().is_empty() => (),
+            (Prompt::Command(mut line), key) => {
+                let mut commands = self.script.commands();
+                commands.extend(self.plugins.commands());
+                line.key(key, &self.history, |before| {
+                    command::complete(before, &commands, SETTINGS)
+                });
+                self.ask(Prompt::Command(line));
+            }
+            (
+                Prompt::Search {
+                    pattern,
+                    forward,
+                    origin,
+                },
+                Key::Char('\n'),
+            ) => {
+                // An empty pattern searches again for the last one.
+                let search = match self.search {
+                    _ if !pattern.is_empty() => Search::new(&pattern, self.config.search_case),
+                    Some((ref search, _)) => search.clone(),
+                    None => return,
+                };
+                self.window_mut().cursor.goto(origin.0, origin.1);
+                self.find(&search, forward, 1);
+                self.search = Some((search, forward));
+                self.highlight = true;
+            }
+            (
+                Prompt::Search {
+                    mut pattern,
+                    forward,
+                    origin,
+                },
+                key,
+            ) => {
+                match key {
+                    Key::Char(c) => pattern.push(c),
+                    Key::Backspace => drop(pattern.pop()),
+                    _ => (),
+                }
+                // Show where the search would go so far.
+                let search = Search::new(&pattern, self.config.search_case);
+                let found = search.next(&self.buffer.lock().unwrap(), origin, forward);
+                let (line, column) = found.map_or(origin, |(position, _)| position);
+                self.window_mut().cursor.goto(line, column);
+                self.ask(Prompt::Search {
+                    pattern,
+                    forward,
+                    origin,
+                });
+            }
+            (Prompt::Substitute { text, lines }, Key::Char('\n')) => {
+                // An empty substitution makes the last one again.
+                match self.substitution.clone() {
+                    _ if !text.is_empty() => self.substitute(text, lines),
+                    Some(last) => self.substitute(last, lines),
+                    None => (),
+                }
+            }
+            (Prompt::Substitute { mut text, lines }, key) => {
+                match key {
+                    Key::Char(c) => text.push(c),
+                    Key::Backspace => drop(text.pop()),
+                    _ => (),
+                }
+                self.ask(Prompt::Substitute { text, lines });
             }
+            (
+                Prompt::Confirm {
+                    replacements,
+                    next,
+                    mut chosen,
+                },
+                key,
+            ) => match key {
+                Key::Char('y') => {
+                    chosen.push(replacements[next].clone());
+                    self.confirm(replacements, next + 1, chosen);
+                }
+                Key::Char('n') => self.confirm(replacements, next + 1, chosen),
+                Key::Char('a') => {
+                    chosen.extend_from_slice(&replacements[next..]);
+                    self.replace(chosen);
+                }
+                _ => self.ask(Prompt::Confirm {
+                    replacements,
+                    next,
+                    chosen,
+                }),
+            },
+        }
+    }
+
+    /// Runs a command typed on the command line, keeping it in the history.
+    fn execute(&mut self, written: String) {
+        if written.trim().is_empty() {
+            return;
+        }
+        self.history.retain(|entry| *entry != written);
+        self.history.push(written.clone());
+
+        match command::parse(&written) {
+            Ok(action) => self.dispatch(action),
+            Err(error) => self.set_message(error),
+        }
+    }
+
+    /// Saves the buffer to the file `name`, which it takes the name of if
+    /// `rename` or if it is its own already, or else writes a copy there.
+    /// Asks first before writing over a file, unless `overwrite` says it
+    /// may, and before making any directories on the way to it.
+    fn save_to(&mut self, name: String, rename: bool, overwrite: bool) {
+        if name.is_empty() {
+            return;
+        }
+        let own = self.buffer.lock().unwrap().filename() == Some(name.as_str());
+        let path = Path::new(&name);
+        if path.is_dir() {
+            self.set_message(format!("{} is a directory", name));
+            return;
+        }
+        if path.exists() && !own && !overwrite {
+            self.ask(Prompt::Overwrite { name, rename });
+            return;
+        }
+        let missing = path
+            .parent()
+            .is_some_and(|directory| !directory.as_os_str().is_empty() && !directory.exists());
+        if missing {
+            self.ask(Prompt::CreateDirectory { name, rename });
+            return;
+        }
+
+        let buffer = Arc::clone(&self.buffer);
+        if own || rename {
+            buffer.lock().unwrap().set_filename(name);
+            self.hook(Hook::Save, &buffer);
+            self.writer.save(&buffer);
+        } else {
+            self.writer.save_to(&buffer, name);
+        }
+    }
 
-            let &(ref lock, ref cvar) = &*cursor_update;
-            let mut ready = lock.lock().unwrap();
-            while !*ready {
-                ready = cvar.wait(ready).unwrap();
+    /// Makes the substitution `written` over lines `first` to `last`, or
+    /// starts asking about each replacement if it says to.
+    fn substitute(&mut self, written: String, (first, last): (usize, usize)) {
+        let substitution = match Substitution::parse(&written) {
+            Ok(substitution) => substitution,
+            Err(error) => {
+                self.set_message(error);
+                return;
             }
+        };
+        self.substitution = Some(written);
 
-            let (width, height) = *size.lock().unwrap();
-            let mut new_offset = false;
+        let replacements = substitution.replacements(&self.buffer.lock().unwrap(), first, last);
+        if replacements.is_empty() {
+            self.set_message(format!("no match for {}", substitution.pattern()));
+        } else if substitution.confirm() {
+            self.confirm(replacements, 0, vec![]);
+        } else {
+            self.replace(replacements);
+        }
+    }
+
+    /// Asks whether to make replacement `next`, showing where it is, or
+    /// makes those chosen once there are none left to ask about.
+    fn confirm(&mut self, replacements: Vec<Replacement>, next: usize, chosen: Vec<Replacement>) {
+        match replacements.get(next) {
+            Some(replacement) => {
+                let (line, column) = replacement.start;
+                self.window_mut().cursor.goto(line, column);
+            }
+            None => return self.replace(chosen),
+        }
+        self.ask(Prompt::Confirm {
+            replacements,
+            next,
+            chosen,
+        });
+    }
 
-            {
-                let mut offset = offset.lock().unwrap();
-                let line = cursor.lock().unwrap().line();
+    /// Makes `replacements` as a single undo step, leaving the cursor at
+    /// the first, and says how many there were.
+    fn replace(&mut self, replacements: Vec<Replacement>) {
+        if let Some(first) = replacements.first() {
+            let (line, column) = first.start;
+            substitute::apply(&mut self.buffer.lock().unwrap(), &replacements);
+            self.window_mut().cursor.goto(line, column);
+        }
+        self.set_message(match replacements.len() {
+            1 => "replaced 1 match".to_string(),
+            count => format!("replaced {} matches", count),
+        });
+    }
 
-                if line <= *offset {
-                    *offset = line;
-                    new_offset = true;
-                } else if line > *offset + height.saturating_sub(2) as usize {
-                    *offset = line - height.saturating_sub(2) as usize;
-                    new_offset = true;
+    /// Moves the main cursor on to the `count`th match of `search`, going
+    /// forward or back, and says if it had to wrap round or found nothing.
+    fn find(&mut self, search: &Search, forward: bool, count: usize) {
+        let mut position = self.position();
+        let mut wrapped = false;
+        {
+            let buffer = self.buffer.lock().unwrap();
+            for _ in 0..count {
+                match search.next(&buffer, position, forward) {
+                    Some((found, wrap)) => {
+                        position = found;
+                        wrapped |= wrap;
+                    }
+                    None => {
+                        self.set_message(format!("no match for {}", search.pattern()));
+                        return;
+                    }
                 }
             }
+        }
+
+        self.window_mut().cursor.goto(position.0, position.1);
+        self.set_message(match (wrapped, forward) {
+            (false, true) => format!("/{}", search.pattern()),
+            (false, false) => format!("?{}", search.pattern()),
+            (true, true) => "search wrapped round to the top".to_string(),
+            (true, false) => "search wrapped round to the bottom".to_string(),
+        });
+    }
+
+    /// The focused buffer's place in the list.
+    fn current(&self) -> usize {
+        self.views
+            .iter()
+            .position(|view| Arc::ptr_eq(&view.buffer, &self.buffer))
+            .unwrap()
+    }
+
+    fn update_listing(&mut self) {
+        self.listing = (self.current() + 1, self.views.len());
+    }
+
+    /// Keeps the focused buffer's cursors and scroll offset in its view.
+    fn stash(&mut self) {
+        let current = self.current();
+        let window = &self.screen.windows[self.screen.focus];
+        let view = &mut self.views[current];
+        view.cursor = window.cursor.clone();
+        view.cursors = window.cursors.clone();
+        view.offset = window.offset;
+    }
+
+    /// Shows the buffer at `index` in the list in the focused window, as its
+    /// view was left.
+    fn load(&mut self, index: usize) {
+        let view = &self.views[index];
+        let window = &mut self.screen.windows[self.screen.focus];
+        window.cursor = view.cursor.clone();
+        window.cursors = view.cursors.clone();
+        window.offset = view.offset;
+        self.buffer = Arc::clone(&view.buffer);
+        self.update_listing();
+    }
+
+    fn show(&mut self, index: usize) {
+        self.set_mode(Mode::Normal);
+        self.keymap.clear();
+        self.stash();
+        self.load(index);
+    }
+
+    fn cycle(&mut self, steps: isize) {
+        let len = self.views.len() as isize;
+        let index = (self.current() as isize + steps).rem_euclid(len);
+        self.show(index as usize);
+    }
+
+    /// Shows the named file, opening it first if it is not already open.
+    fn open(&mut self, name: String) {
+        if name.is_empty() {
+            return;
+        }
 
-            if new_offset {
-                let &(ref lock, ref cvar) = &*draw_ready;
-                *lock.lock().unwrap() = true;
-                cvar.notify_one();
+        let open = self
+            .views
+            .iter()
+            .position(|view| view.buffer.lock().unwrap().filename() == Some(name.as_str()));
+        if let Some(index) = open {
+            self.show(index);
+            return;
+        }
+
+        match Buffer::from(name) {
+            Ok(buffer) => {
+                self.views.push(View::new(buffer));
+                let index = self.views.len() - 1;
+                self.show(index);
+                let buffer = Arc::clone(&self.buffer);
+                self.hook(Hook::Open, &buffer);
             }
+            Err(error) => self.set_message(error.to_string()),
+        }
+    }
+
+    /// Closes the focused buffer, leaving an empty one if it was the last.
+    /// Other windows showing it move on to the same buffer as this one.
+    fn close(&mut self) {
+        self.set_mode(Mode::Normal);
+        self.keymap.clear();
+        let current = self.current();
+        let closed = self.views.remove(current);
+        if self.views.is_empty() {
+            self.views.push(View::new(Buffer::new()));
+        }
+
+        let index = std::cmp::min(current, self.views.len() - 1);
+        self.load(index);
+
+        let view = &self.views[index];
+        for window in self.screen.windows.iter_mut() {
+            if Arc::ptr_eq(&window.cursor.buffer(), &closed.buffer) {
+                window.cursor = view.cursor.clone();
+                window.cursors = view.cursors.clone();
+                window.offset = view.offset;
+            }
+        }
+    }
+
+    /// Moves the focus to the window at `index`.
+    fn focus(&mut self, index: usize) {
+        self.set_mode(Mode::Normal);
+        self.keymap.clear();
+
+        self.screen.focus = index;
+        self.buffer = self.window().cursor.buffer();
+        self.update_listing();
+    }
+
+    /// Splits the focused window, focusing the new half, which starts out
+    /// showing the same place in the same buffer, unless either half would
+    /// be too small to show anything.
+    fn split(&mut self, vertical: bool) {
+        let (width, height) = self.size;
+        let area = self.screen.focused(Rect {
+            x: 0,
+            y: 0,
+            width,
+            height,
+        });
+        if !can_split(area, vertical) {
+            self.set_message("no room to split the window".to_string());
+            return;
+        }
+
+        let mut window = Window::new(self.window().cursor.clone());
+        window.offset = self.window().offset;
+
+        let index = {
+            let screen = &mut self.screen;
+            let index = screen.windows.len();
+            let focus = screen.focus;
+            screen.windows.push(window);
+            screen.layout.split(focus, index, vertical);
+            index
+        };
+        self.focus(index);
+    }
+
+    /// Closes the focused window, unless it is the only one.
+    fn close_window(&mut self) {
+        let index = {
+            let screen = &mut self.screen;
+            if screen.windows.len() == 1 {
+                return;
+            }
+            let focus = screen.focus;
+            screen.windows.remove(focus);
+            screen.layout.remove(focus);
+            std::cmp::min(focus, screen.windows.len() - 1)
+        };
+        self.focus(index);
+    }
 
-            let mut write = write.lock().unwrap();
-            let offset = *offset.lock().unwrap();
-            let cursor = cursor.lock().unwrap();
+    fn focus_towards(&mut self, direction: Direction) {
+        let (width, height) = self.size;
+        let next = {
+            let screen = &self.screen;
+            let areas = screen.layout.areas(Rect {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            });
+            neighbour(&areas, screen.focus, direction)
+        };
+        if let Some(index) = next {
+            self.focus(index);
+        }
+    }
 
-            let line = cursor.line();
-            let column = cursor.column();
+    fn next_window(&mut self) {
+        let index = {
+            let screen = &self.screen;
+            (screen.focus + 1) % screen.windows.len()
+        };
+        self.focus(index);
+    }
 
-            let buffer = buffer.lock().unwrap();
-            let line_number_width = f64::log10(buffer.len() as f64).floor() as u16 + 3;
-            let max_width = width - line_number_width;
+    /// Keeps the cursors of other windows onto the focused buffer on the
+    /// text they were on.
+    fn shift_others(&mut self, changes: &[Edit]) {
+        if changes.is_empty() {
+            return;
+        }
 
-            let mut displacement_y = 0;
-            for i in 0..(line - offset) {
-                let len = buffer.borrow_line(offset + i as usize).len();
-                if len > max_width as usize {
-                    displacement_y += len / (max_width as usize);
+        let screen = &mut self.screen;
+        for (index, window) in screen.windows.iter_mut().enumerate() {
+            if index == screen.focus || !Arc::ptr_eq(&window.cursor.buffer(), &self.buffer) {
+                continue;
+            }
+
+            let shift = |cursor: &mut Cursor| {
+                let mut position = (cursor.line(), cursor.column());
+                for change in changes {
+                    position = change.shift(position);
                 }
+                cursor.goto(position.0, position.1);
+            };
+            shift(&mut window.cursor);
+            window.cursors.iter_mut().for_each(shift);
+        }
+    }
+
+    fn mode(&self) -> Mode {
+        self.mode
+    }
+
+    fn set_mode(&mut self, mode: Mode) {
+        let leaving_insert = self.mode() == Mode::Insert;
+        if leaving_insert {
+            self.buffer.lock().unwrap().checkpoint();
+        }
+        self.each_cursor(|cursor| {
+            if leaving_insert {
+                cursor.left();
             }
+            cursor.set_past_end(mode == Mode::Insert);
+        });
+        self.mode = mode;
+    }
 
-            displacement_y += column / (max_width as usize);
-            let displacement_x = line_number_width as usize + column % (max_width as usize);
-            write!(
-                write,
-                "{}",
-                cursor::Goto(
-                    1 + displacement_x as u16,
-                    1 + (line - offset + displacement_y) as u16
-                )
-            );
+    /// The focused window.
+    fn window(&self) -> &Window {
+        &self.screen.windows[self.screen.focus]
+    }
 
-            *ready = false;
-            write.flush().unwrap();
-        })
+    fn window_mut(&mut self) -> &mut Window {
+        &mut self.screen.windows[self.screen.focus]
     }
 
-    fn notify_cursor(&self) {
-        *self.splashed.lock().unwrap() = true;
-        let &(ref lock, ref cvar) = &*self.cursor_update;
-        *lock.lock().unwrap() = true;
-        cvar.notify_one();
+    fn position(&self) -> (usize, usize) {
+        let cursor = &self.window().cursor;
+        (cursor.line(), cursor.column())
     }
 
-    fn notify_draw(&self) {
-        *self.splashed.lock().unwrap() = true;
-        let &(ref lock, ref cvar) = &*self.draw_ready;
-        *lock.lock().unwrap() = true;
-        cvar.notify_one();
+    /// The position of every cursor, the main cursor's first.
+    fn positions(&self) -> Vec<(usize, usize)> {
+        let mut positions = vec![self.position()];
+        for cursor in self.window().cursors.iter() {
+            positions.push((cursor.line(), cursor.column()));
+        }
+        positions
     }
 
-    fn show_splash(&self) {
-        let mut write = self.write.lock().unwrap();
-        let (width, height) = *self.size.lock().unwrap();
-        let (width, height) = (width as usize, height as usize);
+    /// Moves every cursor to the matching position, dropping any cursor
+    /// that lands where an earlier one already is.
+    fn set_positions(&mut self, positions: &[(usize, usize)]) {
+        let (&(line, column), rest) = positions.split_first().unwrap();
+        let window = self.window_mut();
+        window.cursor.goto(line, column);
 
-        let splash = include_str!("splash").to_string();
-        let mut splash = splash.split("#").peekable();
+        let cursors = &mut window.cursors;
+        for (cursor, &(line, column)) in cursors.iter_mut().zip(rest) {
+            cursor.goto(line, column);
+        }
+
+        let mut seen = HashSet::new();
+        seen.insert((line, column));
+        let mut rest = rest.iter();
+        cursors.retain(|_| seen.insert(*rest.next().unwrap()));
+    }
 
-        let (lower, _) = splash.size_hint();
+    fn each_cursor<F: FnMut(&mut Cursor)>(&mut self, mut f: F) {
+        let window = self.window_mut();
+        f(&mut window.cursor);
+        for cursor in window.cursors.iter_mut() {
+            f(cursor);
+        }
+    }
 
-        write!(write, "{}{}", cursor::Goto(5, 5), lower);
+    /// Makes an edit at every cursor in turn, as a single undo step, moving
+    /// each cursor along with the text around it. Edits which are `typing`
+    /// carry on the undo step before, as typing at a single cursor does.
+    fn edit_each<F>(&mut self, typing: bool, mut edit: F)
+    where
+        F: FnMut(&mut Buffer, (usize, usize)),
+    {
+        let mut positions = self.positions();
+        let mut buffer = self.buffer.lock().unwrap();
+        let several = positions.len() > 1;
 
-        if width >= splash.peek().unwrap().to_string().len() + 6 && height >= lower + 8 {
-            write!(write, "{}", clear::All);
-            for (i, line) in splash.enumerate() {
-                write!(
-                    write,
-                    "{}{}",
-                    cursor::Goto(
-                        ((width - line.len()) / 2) as u16,
-                        ((height - lower) / 2 + i - 8) as u16
-                    ),
-                    line,
-                );
+        let mut changes = vec![];
+        if several {
+            buffer.begin();
+        }
+        for i in 0..positions.len() {
+            edit(&mut buffer, positions[i]);
+            for change in buffer.take_changes() {
+                for position in positions.iter_mut() {
+                    *position = change.shift(*position);
+                }
+                changes.push(change);
             }
-            write.flush().unwrap();
         }
+        if several && typing {
+            buffer.commit_typing();
+        } else if several {
+            buffer.commit();
+        }
+
+        drop(buffer);
+        self.set_positions(&positions);
+        self.shift_others(&changes);
+        self.damage.edits(&self.buffer, &changes);
+        self.edits.extend(changes);
     }
 
-    pub fn start<U: std::io::Read>(mut self, u: U) {
-        if !*self.splashed.lock().unwrap() {
-            self.show_splash();
+    /// Keeps the other cursors on the same text when an edit made at the
+    /// main cursor moves it.
+    fn follow_changes(&mut self) {
+        let changes = self.buffer.lock().unwrap().take_changes();
+        if changes.is_empty() {
+            return;
         }
-        let draw_thread = self.start_draw();
-        let cursor_thread = self.start_cursor_update();
-        let resize_thread = self.start_resize();
 
-        for key in u.keys() {
-            match key.unwrap() {
-                Key::Char('\t') => {
-                    {
-                        let mut cursor = self.cursor.lock().unwrap();
-                        let line = cursor.line();
-                        let column = cursor.column();
-                        for _ in 0..4 {
-                            self.buffer
-                                .lock()
-                                .unwrap()
-                                .borrow_line_mut(line)
-                                .insert(column, ' ');
-                            cursor.right();
-                        }
-                    }
-                    self.notify_cursor();
-                    self.notify_draw();
+        let mut positions = self.positions();
+        for change in &changes {
+            for position in positions.iter_mut().skip(1) {
+                *position = change.shift(*position);
+            }
+        }
+        self.set_positions(&positions);
+        self.shift_others(&changes);
+        self.damage.edits(&self.buffer, &changes);
+        self.edits.extend(changes);
+    }
+
+    /// Adds a cursor where the main cursor is and at each of `added` but
+    /// the last, then moves the main cursor to the last.
+    fn add_cursors(&mut self, added: &[(usize, usize)]) {
+        let (&(line, column), rest) = match added.split_last() {
+            Some(split) => split,
+            None => return,
+        };
+        let mut cursor = self.window().cursor.clone();
+        let window = self.window_mut();
+        window.cursors.push(cursor.clone());
+        for &(line, column) in rest {
+            cursor.goto(line, column);
+            window.cursors.push(cursor.clone());
+        }
+        window.cursor.goto(line, column);
+
+        let positions = self.positions();
+        self.set_positions(&positions);
+    }
+
+    fn undo(&mut self) {
+        let position = self.buffer.lock().unwrap().undo();
+        if let Some((line, column)) = position {
+            self.window_mut().cursor.goto(line, column);
+        }
+    }
+
+    fn redo(&mut self) {
+        let position = self.buffer.lock().unwrap().redo();
+        if let Some((line, column)) = position {
+            self.window_mut().cursor.goto(line, column);
+        }
+    }
+
+    /// Quits, unless a buffer has unsaved changes and `force` is not set.
+    fn quit(&mut self, force: bool) {
+        let unsaved = self.views.iter().find_map(|view| {
+            let buffer = view.buffer.lock().unwrap();
+            if buffer.dirty() {
+                Some(buffer.filename().unwrap_or("[no name]").to_string())
+            } else {
+                None
+            }
+        });
+        match unsaved {
+            Some(name) if !force => {
+                self.set_message(format!("{} has unsaved changes, :q! quits anyway", name))
+            }
+            _ => self.quitting = true,
+        }
+    }
+
+    fn move_cursor(&mut self, motion: Motion, count: usize) {
+        let cursor = &mut self.window_mut().cursor;
+        for _ in 0..count {
+            cursor.apply(motion);
+        }
+    }
+
+    /// Does what `action` asks. Every change to the editor's state made
+    /// on behalf of the user goes through here, whether it was asked for by
+    /// a key, the command line, a script or a plugin.
+    fn dispatch(&mut self, action: Action) {
+        match action {
+            Action::Quit => self.quit(false),
+            Action::ForceQuit => self.quit(true),
+            Action::Save => {
+                let filename = self.buffer.lock().unwrap().filename().map(str::to_string);
+                match filename {
+                    Some(name) => self.save_to(name, true, false),
+                    None => self.ask(Prompt::SaveAs(String::new())),
                 }
-                Key::Char('\n') => {
-                    {
-                        let mut cursor = self.cursor.lock().unwrap();
-                        let line = cursor.line();
-                        let column = cursor.column();
-                        self.buffer.lock().unwrap().split_line(line, column);
-                        cursor.jump_next();
+            }
+            Action::SaveAs(Some(name)) => self.save_to(name, true, false),
+            Action::SaveAs(None) => {
+                let name = self
+                    .buffer
+                    .lock()
+                    .unwrap()
+                    .filename()
+                    .unwrap_or("")
+                    .to_string();
+                self.ask(Prompt::SaveAs(name));
+            }
+            Action::Write(name) => {
+                let unnamed = self.buffer.lock().unwrap().filename().is_none();
+                self.save_to(name, unnamed, false);
+            }
+            Action::SaveQuit => {
+                if self.buffer.lock().unwrap().filename().is_none() {
+                    self.set_message("no file name, give one with :w <name>".to_string());
+                    return;
+                }
+                self.dispatch(Action::Save);
+                self.writer.settle();
+                // A failed save leaves its message showing.
+                if !self.buffer.lock().unwrap().dirty() {
+                    self.quit(false);
+                }
+            }
+            Action::Open(Some(name)) => self.open(name),
+            Action::Open(None) => self.ask(Prompt::Open(String::new())),
+            Action::Close => {
+                if self.buffer.lock().unwrap().dirty() {
+                    self.ask(Prompt::Close);
+                } else {
+                    self.close();
+                }
+            }
+            Action::Undo => self.undo(),
+            Action::Redo => self.redo(),
+            Action::Run(name) => self.run_command(&name),
+            Action::Command => self.ask(Prompt::Command(CommandLine::new())),
+            Action::LineEndings(line_ending) => {
+                let format = {
+                    let mut buffer = self.buffer.lock().unwrap();
+                    if let Some(line_ending) = line_ending {
+                        buffer.set_line_ending(line_ending);
                     }
-                    self.notify_cursor();
-                    self.notify_draw();
+                    buffer.format()
+                };
+                self.set_message(format.to_string());
+            }
+            Action::Set(option) => match Arc::make_mut(&mut self.config).option(&option) {
+                Ok(Some(shown)) => self.set_message(shown),
+                Ok(None) => self.damage.all = true,
+                Err(error) => self.set_message(error),
+            },
+            Action::Normal => {
+                self.keymap.clear();
+                self.set_mode(Mode::Normal);
+            }
+            Action::Move(motion, count) => self.move_cursor(motion, count),
+            Action::MoveEach(motion) => self.each_cursor(|cursor| cursor.apply(motion)),
+            Action::Search | Action::SearchBackward => self.ask(Prompt::Search {
+                pattern: String::new(),
+                forward: action == Action::Search,
+                origin: self.position(),
+            }),
+            Action::NextMatch(count) | Action::PreviousMatch(count) => {
+                if let Some((search, forward)) = self.search.clone() {
+                    let forward = forward == (action == Action::NextMatch(count));
+                    self.find(&search, forward, count);
+                    self.highlight = true;
                 }
-                Key::Char(c) => {
-                    {
-                        let mut cursor = self.cursor.lock().unwrap();
-                        let line = cursor.line();
-                        let column = cursor.column();
-                        self.buffer
-                            .lock()
-                            .unwrap()
-                            .borrow_line_mut(line)
-                            .insert(column, c);
-                        cursor.right();
+            }
+            Action::InsertChar(c) => self.edit_each(true, |buffer, (line, column)| {
+                buffer.insert(line, column, c);
+            }),
+            Action::Indent => {
+                let indent = self.config.indent();
+                self.edit_each(false, |buffer, (line, column)| {
+                    buffer.insert_str(line, column, &indent);
+                })
+            }
+            Action::SplitLine => self.edit_each(true, |buffer, (line, column)| {
+                buffer.split_line(line, column);
+            }),
+            Action::Backspace => self.edit_each(true, |buffer, (line, column)| {
+                if column > 0 {
+                    buffer.delete(line, column - 1);
+                } else {
+                    buffer.merge_line(line);
+                }
+            }),
+            Action::Operate(operator, motion, count) => {
+                // As in vi, `cw` changes to the end of the word rather than
+                // up to the start of the next one.
+                let motion = match (operator, motion) {
+                    (Operator::Change, Motion::WordForward) => Motion::WordEnd,
+                    _ => motion,
+                };
+
+                let from = self.position();
+                let to = {
+                    let mut cursor = self.window().cursor.clone();
+                    for _ in 0..count {
+                        cursor.apply(motion);
                     }
-                    self.notify_cursor();
-                    self.notify_draw();
+                    (cursor.line(), cursor.column())
+                };
+
+                if motion.linewise() {
+                    let (first, last) = order(from, to);
+                    self.operate_lines(operator, first.0, last.0);
+                } else {
+                    let (start, end) = order(from, to);
+                    let end = {
+                        let buffer = self.buffer.lock().unwrap();
+                        if motion.inclusive() && end.1 < buffer.borrow_line(end.0).len() {
+                            after(&buffer, end)
+                        } else {
+                            end
+                        }
+                    };
+                    self.operate(operator, start, end);
                 }
-                Key::Up => {
-                    self.cursor.lock().unwrap().up();
-                    self.notify_cursor();
+            }
+            Action::OperateSelection(operator) => {
+                if let Mode::Visual(line, column) = self.mode() {
+                    let (start, end) = order((line, column), self.position());
+                    let end = after(&self.buffer.lock().unwrap(), end);
+                    self.set_mode(Mode::Normal);
+                    self.operate(operator, start, end);
                 }
-                Key::Down => {
-                    self.cursor.lock().unwrap().down();
-                    self.notify_cursor();
+            }
+            Action::OperateLines(operator, count) => {
+                let line = self.position().0;
+                let last = line.saturating_add(count.saturating_sub(1));
+                self.operate_lines(operator, line, last);
+            }
+            Action::DeleteChar(count) => {
+                let (line, column) = self.position();
+                let len = self.buffer.lock().unwrap().borrow_line(line).len();
+                let end = std::cmp::min(column + count, len);
+                if end > column {
+                    self.operate(Operator::Delete, (line, column), (line, end));
                 }
-                Key::Left => {
-                    self.cursor.lock().unwrap().left();
-                    self.notify_cursor();
+            }
+            Action::Insert => self.set_mode(Mode::Insert),
+            Action::Append => {
+                self.set_mode(Mode::Insert);
+                self.window_mut().cursor.right();
+            }
+            Action::InsertStart => {
+                self.window_mut().cursor.apply(Motion::FirstNonBlank);
+                self.set_mode(Mode::Insert);
+            }
+            Action::AppendEnd => {
+                self.set_mode(Mode::Insert);
+                self.window_mut().cursor.apply(Motion::LineEnd);
+            }
+            Action::OpenBelow => {
+                let line = self.position().0;
+                {
+                    let mut buffer = self.buffer.lock().unwrap();
+                    let len = buffer.borrow_line(line).len();
+                    buffer.split_line(line, len);
+                }
+                self.window_mut().cursor.goto(line + 1, 0);
+                self.set_mode(Mode::Insert);
+            }
+            Action::OpenAbove => {
+                let line = self.position().0;
+                self.buffer.lock().unwrap().split_line(line, 0);
+                self.window_mut().cursor.goto(line, 0);
+                self.set_mode(Mode::Insert);
+            }
+            Action::Paste(count) => self.paste(false, count),
+            Action::PasteBefore(count) => self.paste(true, count),
+            Action::Visual => {
+                let (line, column) = self.position();
+                self.set_mode(Mode::Visual(line, column));
+            }
+            Action::CursorAbove(count) | Action::CursorBelow(count) => {
+                // No more cursors are added than there are lines to put
+                // them on.
+                let line = self.position().0;
+                let lines = self.buffer.lock().unwrap().len();
+                let count = match action {
+                    Action::CursorAbove(_) => count.min(line),
+                    _ => count.min(lines - 1 - line),
+                };
+
+                let mut moved = self.window().cursor.clone();
+                let mut added = Vec::with_capacity(count);
+                for _ in 0..count {
+                    match action {
+                        Action::CursorAbove(_) => moved.up(),
+                        _ => moved.down(),
+                    }
+                    let position = (moved.line(), moved.column());
+                    moved.goto(position.0, position.1);
+                    added.push(position);
                 }
-                Key::Right => {
-                    self.cursor.lock().unwrap().right();
-                    self.notify_cursor();
+                self.add_cursors(&added);
+            }
+            Action::CursorAtMatch => {
+                let found = self.window().cursor.next_match();
+                if let Some(position) = found {
+                    self.add_cursors(&[position]);
                 }
-                Key::Backspace => {
-                    {
-                        let mut cursor = self.cursor.lock().unwrap();
-                        let line = cursor.line();
-                        let column = cursor.column();
-
-                        if column > 0 {
-                            self.buffer
-                                .lock()
-                                .unwrap()
-                                .borrow_line_mut(line)
-                                .delete(column - 1);
-                            if column < self.buffer.lock().unwrap().borrow_line(line).len() {
-                                cursor.left();
-                            }
-                        } else {
-                            let position = self.buffer.lock().unwrap().merge_line(line);
-                            cursor.jump_prev(position);
-                        }
+            }
+            Action::Substitute(written) => {
+                let lines = match self.mode() {
+                    Mode::Visual(line, _) => {
+                        let lines = (line, self.position().0);
+                        self.set_mode(Mode::Normal);
+                        (lines.0.min(lines.1), lines.0.max(lines.1))
                     }
+                    _ => (0, self.buffer.lock().unwrap().len() - 1),
+                };
+                match written {
+                    Some(written) => self.substitute(written, lines),
+                    None => self.ask(Prompt::Substitute {
+                        text: String::new(),
+                        lines,
+                    }),
+                }
+            }
+            Action::SubstituteLines {
+                every_line,
+                written,
+            } => {
+                let lines = if every_line {
+                    (0, self.buffer.lock().unwrap().len() - 1)
+                } else {
+                    (self.position().0, self.position().0)
+                };
+                match written.or_else(|| self.substitution.clone()) {
+                    Some(written) => self.substitute(written, lines),
+                    None => self.set_message("no substitution to repeat".to_string()),
+                }
+            }
+            Action::SingleCursor => {
+                self.window_mut().cursors.clear();
+                self.highlight = false;
+            }
+            Action::NextBuffer(count) => self.cycle(count as isize),
+            Action::PreviousBuffer(count) => self.cycle(-(count as isize)),
+            Action::Split => self.split(false),
+            Action::VerticalSplit => self.split(true),
+            Action::CloseWindow => self.close_window(),
+            Action::NextWindow => self.next_window(),
+            Action::Focus(direction) => self.focus_towards(direction),
+        }
+    }
+
+    /// Applies an operator to the text from `start` up to `end`.
+    fn operate(&mut self, operator: Operator, start: (usize, usize), end: (usize, usize)) {
+        let text = {
+            let mut buffer = self.buffer.lock().unwrap();
+            match operator {
+                Operator::Yank => buffer.text_range(start, end),
+                _ => buffer.delete_range(start, end),
+            }
+        };
+
+        self.register = Register {
+            text,
+            linewise: false,
+        };
+        self.window_mut().cursor.goto(start.0, start.1);
+        if operator == Operator::Change {
+            self.set_mode(Mode::Insert);
+        }
+    }
 
-                    self.notify_cursor();
-                    self.notify_draw();
+    /// Applies an operator to every line from `first` to `last`.
+    fn operate_lines(&mut self, operator: Operator, first: usize, last: usize) {
+        let line = {
+            let mut buffer = self.buffer.lock().unwrap();
+            let last = std::cmp::min(last, buffer.len() - 1);
+            let end = (last, buffer.borrow_line(last).len());
+
+            let mut text = buffer.text_range((first, 0), end);
+            text.push('\n');
+            self.register = Register {
+                text,
+                linewise: true,
+            };
+
+            match operator {
+                Operator::Yank => (),
+                Operator::Change => {
+                    buffer.delete_range((first, 0), end);
+                }
+                Operator::Delete if last + 1 < buffer.len() => {
+                    buffer.delete_range((first, 0), (last + 1, 0));
                 }
-                Key::Esc => {
-                    *self.will_stop.lock().unwrap() = true;
-                    resize_thread.join().unwrap();
-                    self.notify_cursor();
-                    cursor_thread.join().unwrap();
-                    self.notify_draw();
-                    draw_thread.join().unwrap();
-                    break;
+                // Deleting down to the end of the buffer also takes the line
+                // break before the first line, if there is one.
+                Operator::Delete if first > 0 => {
+                    let start = (first - 1, buffer.borrow_line(first - 1).len());
+                    buffer.delete_range(start, end);
                 }
-                Key::Ctrl('s') => {
-                    self.buffer.lock().unwrap().write_back();
-                    self.notify_draw();
+                Operator::Delete => {
+                    buffer.delete_range((first, 0), end);
                 }
-                _ => continue,
             }
+
+            std::cmp::min(first, buffer.len() - 1)
+        };
+
+        let cursor = &mut self.window_mut().cursor;
+        cursor.goto(line, 0);
+        if operator == Operator::Change {
+            self.set_mode(Mode::Insert);
+        } else {
+            cursor.apply(Motion::FirstNonBlank);
+        }
+    }
+
+    fn paste(&mut self, before: bool, count: usize) {
+        if self.register.text.is_empty() {
+            return;
+        }
+        if self.register.text.len().saturating_mul(count) > MAX_PASTE {
+            self.set_message(format!(
+                "cannot paste {} times, as it would be too large",
+                count
+            ));
+            return;
         }
+
+        let text = self.register.text.repeat(count);
+        let (line, column) = self.position();
+        let mut buffer = self.buffer.lock().unwrap();
+
+        if self.register.linewise {
+            if before {
+                buffer.insert_str(line, 0, &text);
+            } else if line + 1 < buffer.len() {
+                buffer.insert_str(line + 1, 0, &text);
+            } else {
+                // There is no line below to paste in front of, so start one.
+                let len = buffer.borrow_line(line).len();
+                let text = format!("\n{}", &text[..text.len() - 1]);
+                buffer.insert_str(line, len, &text);
+            }
+            drop(buffer);
+
+            let cursor = &mut self.window_mut().cursor;
+            cursor.goto(if before { line } else { line + 1 }, 0);
+            cursor.apply(Motion::FirstNonBlank);
+        } else {
+            let len = buffer.borrow_line(line).len();
+            let column = if before {
+                column
+            } else {
+                std::cmp::min(column + 1, len)
+            };
+            let (line, column) = buffer.insert_str(line, column, &text);
+            drop(buffer);
+            self.window_mut()
+                .cursor
+                .goto(line, column.saturating_sub(1));
+        }
+    }
+}
+
+impl<B: Backend> Host for Interface<B> {
+    fn cursor(&self) -> (usize, usize) {
+        self.position()
+    }
+
+    fn set_cursor(&mut self, line: usize, column: usize) {
+        self.window_mut().cursor.goto(line, column);
+    }
+
+    fn show_message(&mut self, message: String) {
+        self.set_message(message);
+    }
+
+    fn act(&mut self, action: &str) -> Result<(), String> {
+        let action = Action::parse(action).ok_or_else(|| format!("no action {}", action))?;
+        self.dispatch(action);
+        Ok(())
     }
 }
@@ -0,0 +1,285 @@
+use termion::event::Key;
+
+use super::action::{Action, MAX_COUNT};
+use super::mode::{Mode, Motion, Operator};
+use super::window::Direction;
+
+/// The keys of a normal mode command typed so far.
+#[derive(Default)]
+struct Pending {
+    count: Option<usize>,
+    operator: Option<(Operator, usize)>,
+    prefix: Option<Key>,
+}
+
+impl Pending {
+    fn new() -> Pending {
+        Pending::default()
+    }
+
+    fn clear(&mut self) {
+        *self = Pending::new();
+    }
+
+    /// Whether no keys of a command have been typed yet.
+    fn is_empty(&self) -> bool {
+        self.count.is_none() && self.operator.is_none() && self.prefix.is_none()
+    }
+
+    /// Takes the next key typed in normal mode, returning a command once
+    /// the keys so far make one up.
+    fn feed(&mut self, key: Key) -> Option<Action> {
+        if let Some(prefix) = self.prefix.take() {
+            return match (prefix, key) {
+                (Key::Char('g'), Key::Char('g')) => {
+                    let line = self.count.take().unwrap_or(1);
+                    self.motion(Motion::Line(Some(line)))
+                }
+                (Key::Char(']'), Key::Char('b')) | (Key::Char('['), Key::Char('b'))
+                    if self.operator.is_none() =>
+                {
+                    let count = self.count.unwrap_or(1);
+                    self.clear();
+                    if prefix == Key::Char(']') {
+                        Some(Action::NextBuffer(count))
+                    } else {
+                        Some(Action::PreviousBuffer(count))
+                    }
+                }
+                (Key::Ctrl('w'), key) if self.operator.is_none() => {
+                    self.clear();
+                    match key {
+                        Key::Char('s') | Key::Ctrl('s') => Some(Action::Split),
+                        Key::Char('v') | Key::Ctrl('v') => Some(Action::VerticalSplit),
+                        Key::Char('c') | Key::Char('q') => Some(Action::CloseWindow),
+                        Key::Char('w') | Key::Ctrl('w') => Some(Action::NextWindow),
+                        Key::Char('h') | Key::Left => Some(Action::Focus(Direction::Left)),
+                        Key::Char('l') | Key::Right => Some(Action::Focus(Direction::Right)),
+                        Key::Char('k') | Key::Up => Some(Action::Focus(Direction::Up)),
+                        Key::Char('j') | Key::Down => Some(Action::Focus(Direction::Down)),
+                        _ => None,
+                    }
+                }
+                _ => {
+                    self.clear();
+                    None
+                }
+            };
+        }
+
+        let motion = match key {
+            Key::Char('h') | Key::Left | Key::Backspace => Some(Motion::Left),
+            Key::Char('l') | Key::Right | Key::Char(' ') => Some(Motion::Right),
+            Key::Char('j') | Key::Down => Some(Motion::Down),
+            Key::Char('k') | Key::Up => Some(Motion::Up),
+            Key::Char('w') => Some(Motion::WordForward),
+            Key::Char('b') => Some(Motion::WordBackward),
+            Key::Char('e') => Some(Motion::WordEnd),
+            Key::Char('0') if self.count.is_none() => Some(Motion::LineStart),
+            Key::Char('^') => Some(Motion::FirstNonBlank),
+            Key::Char('$') => Some(Motion::LineEnd),
+            Key::Char('G') => Some(Motion::Line(self.count.take())),
+            _ => None,
+        };
+
+        if let Some(motion) = motion {
+            return self.motion(motion);
+        }
+
+        if let Key::Char(c) = key {
+            if let Some(digit) = c.to_digit(10) {
+                let count = self.count.unwrap_or(0).saturating_mul(10) + digit as usize;
+                self.count = Some(std::cmp::min(count, MAX_COUNT));
+                return None;
+            }
+        }
+
+        let operator = match key {
+            Key::Char('d') => Some(Operator::Delete),
+            Key::Char('c') => Some(Operator::Change),
+            Key::Char('y') => Some(Operator::Yank),
+            _ => None,
+        };
+
+        if let Some(operator) = operator {
+            let count = self.count.take().unwrap_or(1);
+            return match self.operator.take() {
+                None => {
+                    self.operator = Some((operator, count));
+                    None
+                }
+                Some((pending, first)) if pending == operator => {
+                    Some(Action::OperateLines(operator, times(first, count)))
+                }
+                Some(_) => None,
+            };
+        }
+
+        match key {
+            Key::Char('g') | Key::Char(']') | Key::Char('[') | Key::Ctrl('w') => {
+                self.prefix = Some(key);
+                return None;
+            }
+            _ => (),
+        }
+
+        let count = self.count.unwrap_or(1);
+        let pending_operator = self.operator.is_some();
+        self.clear();
+        if pending_operator {
+            return None;
+        }
+
+        match key {
+            Key::Char('x') | Key::Delete => Some(Action::DeleteChar(count)),
+            Key::Char('i') | Key::Insert => Some(Action::Insert),
+            Key::Char('a') => Some(Action::Append),
+            Key::Char('I') => Some(Action::InsertStart),
+            Key::Char('A') => Some(Action::AppendEnd),
+            Key::Char('o') => Some(Action::OpenBelow),
+            Key::Char('O') => Some(Action::OpenAbove),
+            Key::Char('p') => Some(Action::Paste(count)),
+            Key::Char('P') => Some(Action::PasteBefore(count)),
+            Key::Char('u') => Some(Action::Undo),
+            Key::Ctrl('r') => Some(Action::Redo),
+            Key::Char('v') => Some(Action::Visual),
+            Key::Char('/') => Some(Action::Search),
+            Key::Char('?') => Some(Action::SearchBackward),
+            Key::Char('n') => Some(Action::NextMatch(count)),
+            Key::Char('N') => Some(Action::PreviousMatch(count)),
+            Key::Char('&') => Some(Action::Substitute(None)),
+            Key::Char(':') => Some(Action::Command),
+            Key::Alt('k') => Some(Action::CursorAbove(count)),
+            Key::Alt('j') => Some(Action::CursorBelow(count)),
+            Key::Ctrl('n') => Some(Action::CursorAtMatch),
+            Key::Esc => Some(Action::SingleCursor),
+            _ => None,
+        }
+    }
+
+    fn motion(&mut self, motion: Motion) -> Option<Action> {
+        let count = self.count.take().unwrap_or(1);
+        let command = match self.operator.take() {
+            Some((operator, first)) => Action::Operate(operator, motion, times(first, count)),
+            None => Action::Move(motion, count),
+        };
+        self.clear();
+        Some(command)
+    }
+}
+
+/// The count for an operator given a count of its own and one for what
+/// follows it, as in `2d3w`.
+fn times(first: usize, second: usize) -> usize {
+    std::cmp::min(first.saturating_mul(second), MAX_COUNT)
+}
+
+/// Keys bound to actions, in every mode or in one. A key bound to `None`
+/// in a mode is unbound there, doing what it would by default.
+#[derive(Clone, Default, Debug)]
+pub struct Bindings {
+    pub every: Vec<(Key, Action)>,
+    pub normal: Vec<(Key, Option<Action>)>,
+    pub insert: Vec<(Key, Option<Action>)>,
+    pub visual: Vec<(Key, Option<Action>)>,
+}
+
+impl Bindings {
+    /// What `key` is bound to in `mode`, a binding for the mode coming
+    /// before one for every mode.
+    fn get(&self, mode: Mode, key: Key) -> Option<&Action> {
+        let own = match mode {
+            Mode::Normal => &self.normal,
+            Mode::Insert => &self.insert,
+            Mode::Visual(..) => &self.visual,
+        };
+        match own.iter().find(|&&(bound, _)| bound == key) {
+            Some((_, action)) => action.as_ref(),
+            None => self
+                .every
+                .iter()
+                .find(|&&(bound, _)| bound == key)
+                .map(|(_, action)| action),
+        }
+    }
+}
+
+/// Turns the keys typed into actions, according to the mode.
+pub struct Keymap {
+    bindings: Bindings,
+    pending: Pending,
+}
+
+impl Keymap {
+    pub fn new(bindings: Bindings) -> Keymap {
+        Keymap {
+            bindings,
+            pending: Pending::new(),
+        }
+    }
+
+    /// Forgets the keys of any command typed so far.
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+
+    /// Takes the next key typed in `mode`, returning an action once the
+    /// keys so far make one up. `command` gives the command a script or
+    /// plugin has bound a key to in normal mode, if any.
+    pub fn feed<F>(&mut self, mode: Mode, key: Key, command: F) -> Option<Action>
+    where
+        F: FnOnce(Key) -> Option<String>,
+    {
+        if let Some(action) = self.bindings.get(mode, key) {
+            self.pending.clear();
+            return Some(action.clone());
+        }
+
+        match mode {
+            Mode::Normal if self.pending.is_empty() => match command(key) {
+                Some(name) => Some(Action::Run(name)),
+                None => self.pending.feed(key),
+            },
+            Mode::Normal => self.pending.feed(key),
+            Mode::Insert => match key {
+                Key::Esc => Some(Action::Normal),
+                Key::Char('\t') => Some(Action::Indent),
+                Key::Char('\n') => Some(Action::SplitLine),
+                Key::Char(c) if !c.is_control() => Some(Action::InsertChar(c)),
+                Key::Backspace => Some(Action::Backspace),
+                Key::Up => Some(Action::MoveEach(Motion::Up)),
+                Key::Down => Some(Action::MoveEach(Motion::Down)),
+                Key::Left => Some(Action::MoveEach(Motion::Left)),
+                Key::Right => Some(Action::MoveEach(Motion::Right)),
+                _ => None,
+            },
+            Mode::Visual(..) => {
+                let operator = match key {
+                    Key::Char('d') | Key::Char('x') | Key::Delete => Some(Operator::Delete),
+                    Key::Char('c') => Some(Operator::Change),
+                    Key::Char('y') => Some(Operator::Yank),
+                    _ => None,
+                };
+                if let Some(operator) = operator {
+                    self.pending.clear();
+                    return Some(Action::OperateSelection(operator));
+                }
+
+                match key {
+                    Key::Esc | Key::Char('v') => {
+                        self.pending.clear();
+                        Some(Action::Normal)
+                    }
+                    Key::Char('&') => {
+                        self.pending.clear();
+                        Some(Action::Substitute(None))
+                    }
+                    key => match self.pending.feed(key) {
+                        Some(action @ Action::Move(..)) => Some(action),
+                        _ => None,
+                    },
+                }
+            }
+        }
+    }
+}
@@ -1,23 +1,75 @@
+#[macro_use]
+extern crate serde_json;
+extern crate libc;
+extern crate regex;
 extern crate termion;
+extern crate toml;
+extern crate unicode_width;
 
-mod buffer;
-mod cursor;
-mod interface;
+mod action;
+pub mod backend;
+pub mod buffer;
+mod command;
+mod config;
+pub mod cursor;
+mod event;
+mod grid;
+mod history;
+pub mod interface;
+mod keymap;
+pub mod mode;
+mod plugin;
+mod rope;
+mod script;
+mod search;
+mod substitute;
+mod window;
+mod writer;
 
-use std::sync::{Arc, Mutex};
+use std::env;
+use std::path::PathBuf;
 
 use termion::color;
 use termion::style;
 
-pub fn nep(args: Vec<String>) {
-    let interface = if args.len() == 0 {
-        interface::Interface::from(std::io::stdout())
+/// Where nep keeps its configuration: `$XDG_CONFIG_HOME/nep`, or
+/// `~/.config/nep`.
+fn config_directory() -> Option<PathBuf> {
+    let config = match env::var_os("XDG_CONFIG_HOME") {
+        Some(config) => PathBuf::from(config),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+    Some(config.join("nep"))
+}
+
+/// Runs the editor in the terminal on the files named in `args`, giving
+/// back whatever went wrong with the terminal once it is restored.
+pub fn nep(args: Vec<String>) -> std::io::Result<()> {
+    let terminal = backend::Terminal::new(std::io::stdin(), std::io::stdout())?;
+    let interface = if args.is_empty() {
+        interface::Interface::from(terminal)
     } else {
-        let buffer = Arc::new(Mutex::new(buffer::Buffer::from(args[0].clone())));
-        interface::Interface::from_buffer(std::io::stdout(), buffer)
+        let mut buffers = vec![];
+        let mut errors = vec![];
+        for name in args {
+            match buffer::Buffer::from(name) {
+                Ok(buffer) => buffers.push(buffer),
+                Err(error) => errors.push(error.to_string()),
+            }
+        }
+        if buffers.is_empty() {
+            buffers.push(buffer::Buffer::new());
+        }
+
+        let interface = interface::Interface::from_buffers(terminal, buffers);
+        if !errors.is_empty() {
+            interface.add_message(errors.join("; "));
+        }
+        interface
     };
 
-    interface.start(std::io::stdin());
+    // The terminal is restored when it is dropped, here or on failure.
+    drop(interface.start()?);
 
     println!("{}nep{} exited", style::Bold, style::Reset);
     println!(
@@ -27,4 +79,5 @@ pub fn nep(args: Vec<String>) {
         color::Fg(color::Reset),
         style::Reset
     );
+    Ok(())
 }
@@ -1,9 +1,13 @@
 extern crate nep;
 
 use std::env;
+use std::process;
 
 fn main() {
     let mut args: Vec<String> = env::args().collect();
     args.remove(0);
-    nep::nep(args);
+    if let Err(error) = nep::nep(args) {
+        eprintln!("nep: {}", error);
+        process::exit(1);
+    }
 }
@@ -0,0 +1,53 @@
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Mode {
+    Normal,
+    Insert,
+    /// Selecting text between an anchor, held here, and the cursor.
+    Visual(usize, usize),
+}
+
+impl Mode {
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Mode::Normal => "NORMAL",
+            Mode::Insert => "INSERT",
+            Mode::Visual(..) => "VISUAL",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordForward,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    /// A line by number, counting from one, or the last line.
+    Line(Option<usize>),
+}
+
+impl Motion {
+    /// Whether an operator given this motion acts on whole lines.
+    pub fn linewise(&self) -> bool {
+        matches!(*self, Motion::Up | Motion::Down | Motion::Line(_))
+    }
+
+    /// Whether an operator given this motion includes the character the
+    /// motion lands on.
+    pub fn inclusive(&self) -> bool {
+        *self == Motion::WordEnd || *self == Motion::LineEnd
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
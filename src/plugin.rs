@@ -0,0 +1,579 @@
+//! Plugins are separate programs which the editor runs and talks to over
+//! their standard input and output, so a plugin that crashes or hangs can
+//! only ever take itself down.
+//!
+//! Every executable file in `$XDG_CONFIG_HOME/nep/plugins` (by default
+//! `~/.config/nep/plugins`) is started as a plugin. Messages in both
+//! directions are JSON-RPC 2.0, one JSON object to a line.
+//!
+//! The editor first asks `initialize`, with `{"version": 1}`. The plugin
+//! answers with what it provides, any part of which may be left out:
+//!
+//! ```text
+//! {"commands": ["upcase"], "bindings": {"C-t": "upcase"}, "hooks": ["save"]}
+//! ```
+//!
+//! Bindings are normal mode keys: a character, or one prefixed with `C-`
+//! for control or `M-` for alt. The editor then calls the plugin with:
+//!
+//! - `command`, `{"name"}`, when one of its commands is run;
+//! - `open`, `{"name"}`, when a buffer is opened, if hooked;
+//! - `save`, `{"name"}`, just before a buffer is saved, if hooked;
+//! - `edit`, `{"name", "edits"}`, after each key that edits a buffer, if
+//!   hooked. Each edit is `{"kind", "line", "column"}`, where `kind` is
+//!   `insert`, `delete`, `split` or `join`, and inserts and deletes also
+//!   give their `text`.
+//!
+//! Until it answers, the plugin may make requests of its own:
+//!
+//! - `buffer/name`, giving the file name or null;
+//! - `buffer/lineCount`;
+//! - `buffer/getLines`, `{"start", "end"}`, giving the lines from `start` up
+//!   to but not including `end`;
+//! - `buffer/insert`, `{"line", "column", "text"}`, giving the position
+//!   after the inserted text as `{"line", "column"}`;
+//! - `buffer/delete`, `{"start", "end"}`, both positions, giving the text
+//!   removed;
+//! - `editor/getCursor` and `editor/setCursor`, `{"line", "column"}`;
+//! - `editor/message`, `{"text"}`, to show text in the status line;
+//! - `editor/action`, `{"name"}`, to do an action as a key bound to it
+//!   would, such as `save` or `move_down 3`.
+//!
+//! The `buffer/` requests act on the buffer the call is about, which for
+//! commands is the one in the focused window, and the cursor is that
+//! window's main cursor. Lines and columns count from zero, and columns
+//! count characters. The edits made during one call are undone as a single
+//! step, and are taken back if the call fails. A plugin which takes longer
+//! than two seconds to answer, stops answering at all, or stops reading
+//! what it is sent, is shut down.
+
+use std::error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{
+    channel, sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError,
+};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use serde_json::Value;
+use termion::event::Key;
+
+use super::buffer::Buffer;
+use super::history::Edit;
+
+const TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How many messages may wait to be written to a plugin before it is taken
+/// to have stopped reading them.
+const QUEUE: usize = 64;
+
+/// What plugins can reach of the editor, besides a buffer, while they are
+/// being called.
+pub trait Host {
+    fn cursor(&self) -> (usize, usize);
+    fn set_cursor(&mut self, line: usize, column: usize);
+    fn show_message(&mut self, message: String);
+    /// Does the action written as `action`, as a key bound to it would.
+    fn act(&mut self, action: &str) -> Result<(), String>;
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Hook {
+    Open,
+    Save,
+    Edit,
+}
+
+impl Hook {
+    fn method(&self) -> &'static str {
+        match *self {
+            Hook::Open => "open",
+            Hook::Save => "save",
+            Hook::Edit => "edit",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum Error {
+    /// The plugin could not be started or written to.
+    Io(io::Error),
+    /// The plugin exited or closed its output.
+    Closed,
+    TimedOut,
+    /// The plugin left too many messages unread.
+    Backlogged,
+    /// The plugin sent something which was not a valid answer.
+    Protocol(String),
+    /// The plugin answered with an error of its own.
+    Remote(String),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Error::Io(ref error) => write!(f, "{}", error),
+            Error::Closed => write!(f, "exited"),
+            Error::TimedOut => write!(f, "stopped responding"),
+            Error::Backlogged => write!(f, "stopped reading its input"),
+            Error::Protocol(ref message) => write!(f, "bad message: {}", message),
+            Error::Remote(ref message) => write!(f, "{}", message),
+        }
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match *self {
+            Error::Io(ref error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Error {
+        Error::Io(error)
+    }
+}
+
+/// Reads a key written as in a plugin's bindings.
+pub fn parse_key(key: &str) -> Option<Key> {
+    let mut chars = key.chars();
+    let key = match (chars.next(), chars.next(), chars.next()) {
+        (Some(c), None, _) => Key::Char(c),
+        (Some('C'), Some('-'), Some(c)) => Key::Ctrl(c),
+        (Some('M'), Some('-'), Some(c)) => Key::Alt(c),
+        _ => return None,
+    };
+    if chars.next().is_some() {
+        return None;
+    }
+    Some(key)
+}
+
+fn edit_to_json(edit: &Edit) -> Value {
+    match *edit {
+        Edit::Insert {
+            line,
+            column,
+            ref text,
+        } => json!({"kind": "insert", "line": line, "column": column, "text": text}),
+        Edit::Delete {
+            line,
+            column,
+            ref text,
+        } => json!({"kind": "delete", "line": line, "column": column, "text": text}),
+        Edit::Split { line, column } => json!({"kind": "split", "line": line, "column": column}),
+        Edit::Join { line, column } => json!({"kind": "join", "line": line, "column": column}),
+    }
+}
+
+fn number(params: &Value, name: &str) -> Result<usize, String> {
+    params[name]
+        .as_u64()
+        .map(|n| n as usize)
+        .ok_or_else(|| format!("expected a number for {}", name))
+}
+
+/// Reads a `{"line", "column"}` position, checking it is in the buffer.
+fn position(buffer: &Buffer, value: &Value) -> Result<(usize, usize), String> {
+    let line = number(value, "line")?;
+    let column = number(value, "column")?;
+    if line >= buffer.len() || column > buffer.borrow_line(line).len() {
+        return Err(format!("{}:{} is outside the buffer", line, column));
+    }
+    Ok((line, column))
+}
+
+/// Carries out a request made by a plugin.
+fn serve(
+    host: &mut dyn Host,
+    buffer: &Arc<Mutex<Buffer>>,
+    method: &str,
+    params: &Value,
+) -> Result<Value, String> {
+    match method {
+        "buffer/name" => Ok(json!(buffer.lock().unwrap().filename())),
+        "buffer/lineCount" => Ok(json!(buffer.lock().unwrap().len())),
+        "buffer/getLines" => {
+            let buffer = buffer.lock().unwrap();
+            let end = std::cmp::min(number(params, "end")?, buffer.len());
+            let start = std::cmp::min(number(params, "start")?, end);
+            let lines = (start..end)
+                .map(|line| buffer.borrow_line(line).as_str().to_string())
+                .collect::<Vec<_>>();
+            Ok(json!(lines))
+        }
+        "buffer/insert" => {
+            let mut buffer = buffer.lock().unwrap();
+            let (line, column) = position(&buffer, params)?;
+            let text = params["text"].as_str().ok_or("expected text")?;
+            let (line, column) = buffer.insert_str(line, column, text);
+            Ok(json!({"line": line, "column": column}))
+        }
+        "buffer/delete" => {
+            let mut buffer = buffer.lock().unwrap();
+            let start = position(&buffer, &params["start"])?;
+            let end = position(&buffer, &params["end"])?;
+            if end < start {
+                return Err("the end comes before the start".to_string());
+            }
+            Ok(json!(buffer.delete_range(start, end)))
+        }
+        "editor/getCursor" => {
+            let (line, column) = host.cursor();
+            Ok(json!({"line": line, "column": column}))
+        }
+        "editor/setCursor" => {
+            let (line, column) = position(&buffer.lock().unwrap(), params)?;
+            host.set_cursor(line, column);
+            Ok(Value::Null)
+        }
+        "editor/message" => {
+            let text = params["text"].as_str().ok_or("expected text")?;
+            host.show_message(text.to_string());
+            Ok(Value::Null)
+        }
+        "editor/action" => {
+            let name = params["name"].as_str().ok_or("expected a name")?;
+            host.act(name)?;
+            Ok(Value::Null)
+        }
+        _ => Err(format!("no method {}", method)),
+    }
+}
+
+/// A running plugin and what it has registered.
+pub struct Plugin {
+    name: String,
+    child: Child,
+    /// Lines waiting to be written to the plugin's input, which is done on
+    /// a thread of its own so that a plugin which stops reading cannot hold
+    /// up the editor.
+    input: SyncSender<String>,
+    messages: Receiver<Value>,
+    next_id: u64,
+    commands: Vec<String>,
+    bindings: Vec<(Key, String)>,
+    hooks: Vec<Hook>,
+}
+
+impl Plugin {
+    /// Starts the program at `path` and asks what it provides.
+    pub fn start(path: &Path) -> Result<Plugin, Error> {
+        let mut child = Command::new(path)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        let output = child.stdout.take().unwrap();
+
+        // Ends once the plugin is dropped, or once it cannot be written to.
+        let (input, lines) = sync_channel::<String>(QUEUE);
+        thread::spawn(move || {
+            for line in lines {
+                if writeln!(stdin, "{}", line)
+                    .and_then(|()| stdin.flush())
+                    .is_err()
+                {
+                    break;
+                }
+            }
+        });
+
+        let (sender, messages) = channel();
+        thread::spawn(move || {
+            for line in BufReader::new(output).lines() {
+                let message = match line {
+                    Ok(line) => serde_json::from_str(&line).unwrap_or(Value::Null),
+                    Err(_) => break,
+                };
+                if sender.send(message).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let mut plugin = Plugin {
+            name: path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            child,
+            input,
+            messages,
+            next_id: 0,
+            commands: vec![],
+            bindings: vec![],
+            hooks: vec![],
+        };
+
+        let provides = plugin.call("initialize", json!({"version": 1}), None)?;
+        if let Some(commands) = provides["commands"].as_array() {
+            plugin.commands = commands
+                .iter()
+                .filter_map(|command| command.as_str().map(String::from))
+                .collect();
+        }
+        if let Some(bindings) = provides["bindings"].as_object() {
+            for (key, command) in bindings {
+                match (parse_key(key), command.as_str()) {
+                    (Some(key), Some(command)) => plugin.bindings.push((key, command.to_string())),
+                    _ => return Err(Error::Protocol(format!("bad binding {}", key))),
+                }
+            }
+        }
+        if let Some(hooks) = provides["hooks"].as_array() {
+            for hook in hooks {
+                let hook = match hook.as_str() {
+                    Some("open") => Hook::Open,
+                    Some("save") => Hook::Save,
+                    Some("edit") => Hook::Edit,
+                    _ => return Err(Error::Protocol(format!("unknown hook {}", hook))),
+                };
+                plugin.hooks.push(hook);
+            }
+        }
+        Ok(plugin)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn send(&mut self, message: &Value) -> Result<(), Error> {
+        match self.input.try_send(message.to_string()) {
+            Ok(()) => Ok(()),
+            Err(TrySendError::Full(_)) => Err(Error::Backlogged),
+            Err(TrySendError::Disconnected(_)) => Err(Error::Closed),
+        }
+    }
+
+    /// Calls `method` and waits for the answer, serving any requests the
+    /// plugin makes in the meantime.
+    fn call(
+        &mut self,
+        method: &str,
+        params: Value,
+        mut host: Option<(&mut dyn Host, &Arc<Mutex<Buffer>>)>,
+    ) -> Result<Value, Error> {
+        self.next_id += 1;
+        let id = self.next_id;
+        self.send(&json!({"jsonrpc": "2.0", "id": id, "method": method, "params": params}))?;
+
+        let deadline = Instant::now() + TIMEOUT;
+        loop {
+            let wait = deadline.saturating_duration_since(Instant::now());
+            let message = match self.messages.recv_timeout(wait) {
+                Ok(message) => message,
+                Err(RecvTimeoutError::Timeout) => return Err(Error::TimedOut),
+                Err(RecvTimeoutError::Disconnected) => return Err(Error::Closed),
+            };
+
+            if let Some(method) = message["method"].as_str() {
+                let answer = match host {
+                    Some((ref mut host, buffer)) => {
+                        serve(&mut **host, buffer, method, &message["params"])
+                    }
+                    None => Err("the editor is not ready".to_string()),
+                };
+                if message["id"].is_null() {
+                    continue;
+                }
+                let reply = match answer {
+                    Ok(result) => json!({"jsonrpc": "2.0", "id": message["id"], "result": result}),
+                    Err(error) => json!({
+                        "jsonrpc": "2.0",
+                        "id": message["id"],
+                        "error": {"code": -32602, "message": error},
+                    }),
+                };
+                self.send(&reply)?;
+            } else if message["id"] == json!(id) {
+                if let Some(error) = message.get("error") {
+                    let text = error["message"].as_str().unwrap_or("unknown error");
+                    return Err(Error::Remote(text.to_string()));
+                }
+                return Ok(message["result"].clone());
+            } else {
+                return Err(Error::Protocol(message.to_string()));
+            }
+        }
+    }
+}
+
+impl Drop for Plugin {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+fn directory() -> Option<PathBuf> {
+    Some(super::config_directory()?.join("plugins"))
+}
+
+/// Every running plugin.
+#[derive(Default)]
+pub struct Plugins {
+    plugins: Vec<Plugin>,
+}
+
+impl Plugins {
+    /// Starts every plugin in the plugin directory, returning a message for
+    /// each one that failed.
+    pub fn load() -> (Plugins, Vec<String>) {
+        let mut plugins = Plugins::default();
+        let mut errors = vec![];
+
+        let entries = match directory().map(fs::read_dir) {
+            Some(Ok(entries)) => entries,
+            _ => return (plugins, errors),
+        };
+        let mut paths = entries
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|path| {
+                fs::metadata(path)
+                    .map(|metadata| {
+                        metadata.is_file() && metadata.permissions().mode() & 0o111 != 0
+                    })
+                    .unwrap_or(false)
+            })
+            .collect::<Vec<_>>();
+        paths.sort();
+
+        for path in paths {
+            match Plugin::start(&path) {
+                Ok(plugin) => plugins.plugins.push(plugin),
+                Err(error) => errors.push(format!(
+                    "plugin {} failed to start: {}",
+                    path.display(),
+                    error
+                )),
+            }
+        }
+        (plugins, errors)
+    }
+
+    /// The command bound to `key` by a plugin, if any.
+    pub fn binding(&self, key: Key) -> Option<String> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| plugin.bindings.iter())
+            .find(|&&(bound, _)| bound == key)
+            .map(|(_, command)| command.clone())
+    }
+
+    pub fn has_command(&self, name: &str) -> bool {
+        self.plugins
+            .iter()
+            .any(|plugin| plugin.commands.iter().any(|command| command == name))
+    }
+
+    /// The names of the commands every plugin provides.
+    pub fn commands(&self) -> Vec<String> {
+        self.plugins
+            .iter()
+            .flat_map(|plugin| plugin.commands.iter().cloned())
+            .collect()
+    }
+
+    pub fn hooked(&self, hook: Hook) -> bool {
+        self.plugins
+            .iter()
+            .any(|plugin| plugin.hooks.contains(&hook))
+    }
+
+    /// Calls each plugin chosen by `wanted` in turn about `buffer`,
+    /// shutting down any which fail and returning a message for each.
+    fn call_each<F>(
+        &mut self,
+        wanted: F,
+        method: &str,
+        params: &Value,
+        host: &mut dyn Host,
+        buffer: &Arc<Mutex<Buffer>>,
+    ) -> Vec<String>
+    where
+        F: Fn(&Plugin) -> bool,
+    {
+        let mut errors = vec![];
+        let mut i = 0;
+        while i < self.plugins.len() {
+            if !wanted(&self.plugins[i]) {
+                i += 1;
+                continue;
+            }
+
+            buffer.lock().unwrap().begin();
+            let result = self.plugins[i].call(method, params.clone(), Some((&mut *host, buffer)));
+            if result.is_ok() {
+                buffer.lock().unwrap().commit();
+            } else {
+                buffer.lock().unwrap().abort();
+            }
+
+            match result {
+                Ok(_) => i += 1,
+                Err(Error::Remote(message)) => {
+                    errors.push(format!("{}: {}", self.plugins[i].name(), message));
+                    i += 1;
+                }
+                Err(error) => {
+                    let plugin = self.plugins.remove(i);
+                    errors.push(format!("plugin {} {}, stopping it", plugin.name(), error));
+                }
+            }
+        }
+        errors
+    }
+
+    /// Runs the plugin command `name` on `buffer`.
+    pub fn run(
+        &mut self,
+        name: &str,
+        host: &mut dyn Host,
+        buffer: &Arc<Mutex<Buffer>>,
+    ) -> Vec<String> {
+        let params = json!({ "name": name });
+        self.call_each(
+            |plugin| plugin.commands.iter().any(|command| command == name),
+            "command",
+            &params,
+            host,
+            buffer,
+        )
+    }
+
+    /// Tells every plugin hooked into `hook` that it has happened to
+    /// `buffer`. `edits` are only sent for edit hooks.
+    pub fn hook(
+        &mut self,
+        hook: Hook,
+        edits: &[Edit],
+        host: &mut dyn Host,
+        buffer: &Arc<Mutex<Buffer>>,
+    ) -> Vec<String> {
+        let name = buffer.lock().unwrap().filename().map(String::from);
+        let mut params = json!({ "name": name });
+        if hook == Hook::Edit {
+            params["edits"] = Value::Array(edits.iter().map(edit_to_json).collect());
+        }
+        self.call_each(
+            |plugin| plugin.hooks.contains(&hook),
+            hook.method(),
+            &params,
+            host,
+            buffer,
+        )
+    }
+}
@@ -0,0 +1,280 @@
+use std::iter::FromIterator;
+use std::slice;
+use std::sync::Arc;
+
+const MAX_LEAF: usize = 128;
+const MAX_CHILDREN: usize = 16;
+
+/// A B-tree of items addressed by position.
+///
+/// Inserting or removing an item only touches the nodes on the path to it,
+/// so edits near the top of a very long buffer cost the same as edits near
+/// the bottom. Nodes are reference counted and copied on write, which makes
+/// cloning a rope (say, to snapshot a buffer) constant time.
+#[derive(Clone)]
+pub struct Rope<T: Clone> {
+    root: Arc<Node<T>>,
+}
+
+#[derive(Clone)]
+enum Node<T: Clone> {
+    Leaf(Vec<T>),
+    Internal(usize, Vec<Arc<Node<T>>>),
+}
+
+impl<T: Clone> Node<T> {
+    fn len(&self) -> usize {
+        match *self {
+            Node::Leaf(ref items) => items.len(),
+            Node::Internal(len, _) => len,
+        }
+    }
+
+    fn is_underfull(&self) -> bool {
+        match *self {
+            Node::Leaf(ref items) => items.len() < MAX_LEAF / 2,
+            Node::Internal(_, ref children) => children.len() < MAX_CHILDREN / 2,
+        }
+    }
+
+    fn get(&self, index: usize) -> &T {
+        match *self {
+            Node::Leaf(ref items) => &items[index],
+            Node::Internal(_, ref children) => {
+                let (i, index) = locate(children, index);
+                children[i].get(index)
+            }
+        }
+    }
+
+    fn get_mut(&mut self, index: usize) -> &mut T {
+        match *self {
+            Node::Leaf(ref mut items) => &mut items[index],
+            Node::Internal(_, ref mut children) => {
+                let (i, index) = locate(children, index);
+                Arc::make_mut(&mut children[i]).get_mut(index)
+            }
+        }
+    }
+
+    /// Inserts `item` at `index`, returning the right half of this node if
+    /// it had to be split.
+    fn insert(&mut self, index: usize, item: T) -> Option<Node<T>> {
+        match *self {
+            Node::Leaf(ref mut items) => items.insert(index, item),
+            Node::Internal(ref mut len, ref mut children) => {
+                *len += 1;
+                let (i, index) = locate(children, index);
+                if let Some(split) = Arc::make_mut(&mut children[i]).insert(index, item) {
+                    children.insert(i + 1, Arc::new(split));
+                }
+            }
+        }
+        self.split_if_overfull()
+    }
+
+    fn remove(&mut self, index: usize) -> T {
+        match *self {
+            Node::Leaf(ref mut items) => items.remove(index),
+            Node::Internal(ref mut len, ref mut children) => {
+                *len -= 1;
+                let (i, index) = locate(children, index);
+                let item = Arc::make_mut(&mut children[i]).remove(index);
+                if children.len() > 1 && children[i].is_underfull() {
+                    rebalance(children, i);
+                }
+                item
+            }
+        }
+    }
+
+    /// Appends the contents of `other`, a node at the same depth, returning
+    /// the right half of the result if it had to be split.
+    fn absorb(&mut self, other: Node<T>) -> Option<Node<T>> {
+        match (&mut *self, other) {
+            (&mut Node::Leaf(ref mut items), Node::Leaf(mut rest)) => items.append(&mut rest),
+            (
+                &mut Node::Internal(ref mut len, ref mut children),
+                Node::Internal(rest_len, mut rest),
+            ) => {
+                *len += rest_len;
+                children.append(&mut rest);
+            }
+            _ => unreachable!("rope siblings at different depths"),
+        }
+        self.split_if_overfull()
+    }
+
+    fn split_if_overfull(&mut self) -> Option<Node<T>> {
+        match *self {
+            Node::Leaf(ref mut items) if items.len() > MAX_LEAF => {
+                let half = items.len() / 2;
+                Some(Node::Leaf(items.split_off(half)))
+            }
+            Node::Internal(ref mut len, ref mut children) if children.len() > MAX_CHILDREN => {
+                let half = children.len() / 2;
+                let right = children.split_off(half);
+                let right_len = right.iter().map(|child| child.len()).sum();
+                *len -= right_len;
+                Some(Node::Internal(right_len, right))
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Finds the child containing `index`, and the index relative to that child.
+/// An index one past the end resolves to the end of the last child.
+fn locate<T: Clone>(children: &[Arc<Node<T>>], mut index: usize) -> (usize, usize) {
+    let last = children.len() - 1;
+    for (i, child) in children.iter().enumerate() {
+        if index < child.len() || i == last {
+            return (i, index);
+        }
+        index -= child.len();
+    }
+    unreachable!()
+}
+
+/// Merges the underfull child at `i` with a neighbour, splitting the result
+/// again if the two together are too large for one node.
+fn rebalance<T: Clone>(children: &mut Vec<Arc<Node<T>>>, i: usize) {
+    let left = i.saturating_sub(1);
+    let right = children.remove(left + 1);
+    let right = Arc::try_unwrap(right).unwrap_or_else(|shared| (*shared).clone());
+    if let Some(split) = Arc::make_mut(&mut children[left]).absorb(right) {
+        children.insert(left + 1, Arc::new(split));
+    }
+}
+
+impl<T: Clone> Rope<T> {
+    pub fn new() -> Rope<T> {
+        Rope {
+            root: Arc::new(Node::Leaf(vec![])),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.root.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn get(&self, index: usize) -> &T {
+        assert!(index < self.len(), "rope index out of bounds");
+        self.root.get(index)
+    }
+
+    pub fn get_mut(&mut self, index: usize) -> &mut T {
+        assert!(index < self.len(), "rope index out of bounds");
+        Arc::make_mut(&mut self.root).get_mut(index)
+    }
+
+    pub fn insert(&mut self, index: usize, item: T) {
+        assert!(index <= self.len(), "rope index out of bounds");
+        if let Some(split) = Arc::make_mut(&mut self.root).insert(index, item) {
+            let left = self.root.clone();
+            let len = left.len() + split.len();
+            self.root = Arc::new(Node::Internal(len, vec![left, Arc::new(split)]));
+        }
+    }
+
+    pub fn push(&mut self, item: T) {
+        let len = self.len();
+        self.insert(len, item);
+    }
+
+    pub fn remove(&mut self, index: usize) -> T {
+        assert!(index < self.len(), "rope index out of bounds");
+        let item = Arc::make_mut(&mut self.root).remove(index);
+        loop {
+            let child = match *self.root {
+                Node::Internal(_, ref children) if children.len() == 1 => children[0].clone(),
+                _ => break,
+            };
+            self.root = child;
+        }
+        item
+    }
+
+    pub fn iter(&self) -> Iter<'_, T> {
+        match *self.root {
+            Node::Leaf(ref items) => Iter {
+                stack: vec![],
+                leaf: items.iter(),
+            },
+            Node::Internal(_, ref children) => Iter {
+                stack: vec![children.iter()],
+                leaf: [].iter(),
+            },
+        }
+    }
+}
+
+impl<T: Clone> Default for Rope<T> {
+    fn default() -> Rope<T> {
+        Rope::new()
+    }
+}
+
+impl<T: Clone> FromIterator<T> for Rope<T> {
+    /// Builds the tree bottom up from packed leaves, which is much quicker
+    /// than inserting items one at a time when loading a large file.
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Rope<T> {
+        let mut nodes = vec![];
+        let mut items = Vec::with_capacity(MAX_LEAF);
+        for item in iter {
+            items.push(item);
+            if items.len() == MAX_LEAF {
+                nodes.push(Arc::new(Node::Leaf(items)));
+                items = Vec::with_capacity(MAX_LEAF);
+            }
+        }
+        if !items.is_empty() || nodes.is_empty() {
+            nodes.push(Arc::new(Node::Leaf(items)));
+        }
+
+        while nodes.len() > 1 {
+            nodes = nodes
+                .chunks(MAX_CHILDREN)
+                .map(|chunk| {
+                    let len = chunk.iter().map(|node| node.len()).sum();
+                    Arc::new(Node::Internal(len, chunk.to_vec()))
+                })
+                .collect();
+        }
+
+        Rope {
+            root: nodes.pop().unwrap(),
+        }
+    }
+}
+
+pub struct Iter<'a, T: 'a + Clone> {
+    stack: Vec<slice::Iter<'a, Arc<Node<T>>>>,
+    leaf: slice::Iter<'a, T>,
+}
+
+impl<'a, T: 'a + Clone> Iterator for Iter<'a, T> {
+    type Item = &'a T;
+
+    fn next(&mut self) -> Option<&'a T> {
+        loop {
+            if let Some(item) = self.leaf.next() {
+                return Some(item);
+            }
+
+            match self.stack.last_mut()?.next() {
+                Some(node) => match **node {
+                    Node::Leaf(ref items) => self.leaf = items.iter(),
+                    Node::Internal(_, ref children) => self.stack.push(children.iter()),
+                },
+                None => {
+                    self.stack.pop();
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,1121 @@
+//! A small scripting language for configuring the editor and adding
+//! commands to it, run by an interpreter inside the editor itself.
+//!
+//! On starting, the editor runs `$XDG_CONFIG_HOME/nep/init.nep` (by default
+//! `~/.config/nep/init.nep`) if there is one. For example:
+//!
+//! ```text
+//! # upper cases the line under the cursor
+//! fn upcase() {
+//!     let line = cursor_line()
+//!     let text = get_line(line)
+//!     delete(line, 0, line, len(text))
+//!     insert(line, 0, upper(text))
+//! }
+//!
+//! command("upcase", upcase)
+//! bind("C-u", "upcase")
+//! ```
+//!
+//! Statements end at a newline or `;`, and `#` starts a comment. They are:
+//!
+//! - `let name = value`, declaring a variable, and `name = value`, changing
+//!   one;
+//! - `fn name(a, b) { ... }`, declaring a function, which may `return` a
+//!   value;
+//! - `if condition { ... } else { ... }`, where `else` may be followed by
+//!   another `if`, and `while condition { ... }`, which may `break`;
+//! - any expression, such as a call.
+//!
+//! Values are `nil`, `true` and `false`, whole numbers, strings in double
+//! quotes and functions. Only `nil` and `false` are false. The operators
+//! are, loosest first, `or`, `and`, `not`, the comparisons `==`, `!=`, `<`,
+//! `<=`, `>` and `>=`, then `+` and `-`, then `*`, `/` and `%`, then
+//! negation. `+` joins strings, turning a number on either side into
+//! text. Variables declared in a function are local to that call; any other
+//! is global.
+//!
+//! The editor provides these functions:
+//!
+//! - `line_count()`, `get_line(line)` and `buffer_name()`, which is `nil`
+//!   for a buffer without a file;
+//! - `insert(line, column, text)`, where the text may run over several
+//!   lines, and `delete(line, column, end_line, end_column)`, giving the
+//!   text removed;
+//! - `cursor_line()`, `cursor_column()` and `goto(line, column)`;
+//! - `message(text)`, to show text in the status line;
+//! - `action(name)`, doing an action as a key bound to it would, such as
+//!   `action("save")` or `action("move_down 3")`;
+//! - `command(name, function)`, making a function a command, and
+//!   `bind(key, name)`, binding a normal mode key to a command, which may
+//!   also be one a plugin provides. Keys are written as for plugins;
+//! - `len(text)`, `upper(text)`, `lower(text)`, `slice(text, start, end)`,
+//!   `find(text, pattern)`, giving the position of the pattern or `nil`,
+//!   `str(value)` and `num(text)`, giving `nil` for text that is not a
+//!   number.
+//!
+//! Lines and columns count from zero, and columns and text positions count
+//! characters. The edits a command makes are undone as a single step, and
+//! are taken back if it fails. A script that runs for too long is stopped.
+
+use std::collections::HashMap;
+use std::error;
+use std::fmt;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+use termion::event::Key;
+
+use super::buffer::Buffer;
+use super::plugin::{parse_key, Host};
+
+/// How many statements and calls a script may run before it is stopped.
+const STEPS: usize = 1_000_000;
+
+/// How deeply function calls may nest.
+const DEPTH: usize = 200;
+
+#[derive(Debug)]
+pub struct Error {
+    /// The line the error happened on, or zero if it was not in a script.
+    line: usize,
+    message: String,
+}
+
+impl Error {
+    fn new<S: Into<String>>(line: usize, message: S) -> Error {
+        Error {
+            line,
+            message: message.into(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "{}", self.message)
+        } else {
+            write!(f, "line {}: {}", self.line, self.message)
+        }
+    }
+}
+
+impl error::Error for Error {}
+
+#[derive(Clone, PartialEq, Debug)]
+enum Token {
+    Number(i64),
+    Str(String),
+    Name(String),
+    Symbol(&'static str),
+    /// The end of a statement, written as a newline or `;`.
+    Newline,
+    End,
+}
+
+const SYMBOLS: [&str; 17] = [
+    "==", "!=", "<=", ">=", "(", ")", "{", "}", ",", "=", "<", ">", "+", "-", "*", "/", "%",
+];
+
+const KEYWORDS: [&str; 13] = [
+    "let", "fn", "if", "else", "while", "return", "break", "and", "or", "not", "true", "false",
+    "nil",
+];
+
+/// Splits `source` into tokens, each with the line it is on.
+fn tokenize(source: &str) -> Result<Vec<(usize, Token)>, Error> {
+    let mut tokens = vec![];
+    let mut line = 1;
+    let mut chars = source.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        match c {
+            '\n' | ';' => {
+                chars.next();
+                tokens.push((line, Token::Newline));
+                if c == '\n' {
+                    line += 1;
+                }
+            }
+            '#' => {
+                while chars.peek().is_some_and(|&c| c != '\n') {
+                    chars.next();
+                }
+            }
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '0'..='9' => {
+                let mut digits = String::new();
+                while let Some(&c) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                    digits.push(c);
+                    chars.next();
+                }
+                let number = digits
+                    .parse()
+                    .map_err(|_| Error::new(line, "number too large"))?;
+                tokens.push((line, Token::Number(number)));
+            }
+            '"' => {
+                chars.next();
+                let mut text = String::new();
+                loop {
+                    match chars.next() {
+                        None | Some('\n') => return Err(Error::new(line, "unterminated string")),
+                        Some('"') => break,
+                        Some('\\') => text.push(match chars.next() {
+                            Some('n') => '\n',
+                            Some('t') => '\t',
+                            Some('"') => '"',
+                            Some('\\') => '\\',
+                            _ => return Err(Error::new(line, "unknown escape in string")),
+                        }),
+                        Some(c) => text.push(c),
+                    }
+                }
+                tokens.push((line, Token::Str(text)));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let mut name = String::new();
+                while let Some(&c) = chars.peek().filter(|&&c| c.is_alphanumeric() || c == '_') {
+                    name.push(c);
+                    chars.next();
+                }
+                tokens.push((line, Token::Name(name)));
+            }
+            _ => {
+                let rest = chars.clone().take(2).collect::<String>();
+                let symbol = SYMBOLS
+                    .iter()
+                    .find(|symbol| rest.starts_with(*symbol))
+                    .ok_or_else(|| Error::new(line, format!("unexpected {}", c)))?;
+                for _ in 0..symbol.len() {
+                    chars.next();
+                }
+                tokens.push((line, Token::Symbol(symbol)));
+            }
+        }
+    }
+    tokens.push((line, Token::End));
+    Ok(tokens)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum Operator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Remainder,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+}
+
+impl Operator {
+    fn symbol(&self) -> &'static str {
+        match *self {
+            Operator::Add => "+",
+            Operator::Subtract => "-",
+            Operator::Multiply => "*",
+            Operator::Divide => "/",
+            Operator::Remainder => "%",
+            Operator::Equal => "==",
+            Operator::NotEqual => "!=",
+            Operator::Less => "<",
+            Operator::LessEqual => "<=",
+            Operator::Greater => ">",
+            Operator::GreaterEqual => ">=",
+            Operator::And => "and",
+            Operator::Or => "or",
+        }
+    }
+}
+
+#[derive(Debug)]
+enum Expression {
+    Literal(Value),
+    Variable(String),
+    Call(Box<Expression>, Vec<Expression>),
+    Negate(Box<Expression>),
+    Not(Box<Expression>),
+    Binary(Operator, Box<Expression>, Box<Expression>),
+}
+
+#[derive(Debug)]
+enum Statement {
+    Let(String, Expression),
+    Assign(String, Expression),
+    Function(Rc<Function>),
+    If(Expression, Block, Block),
+    While(Expression, Block),
+    Return(Option<Expression>),
+    Break,
+    Expression(Expression),
+}
+
+/// Statements, each with the line it starts on.
+type Block = Vec<(usize, Statement)>;
+
+#[derive(Debug)]
+pub struct Function {
+    name: String,
+    parameters: Vec<String>,
+    body: Block,
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+    /// How many loops and functions the parser is inside, so `break` and
+    /// `return` can be checked.
+    loops: usize,
+    functions: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &Token {
+        &self.tokens[self.next].1
+    }
+
+    fn line(&self) -> usize {
+        self.tokens[self.next].0
+    }
+
+    fn advance(&mut self) -> Token {
+        let token = self.tokens[self.next].1.clone();
+        if token != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn error<T, S: Into<String>>(&self, message: S) -> Result<T, Error> {
+        Err(Error::new(self.line(), message))
+    }
+
+    /// Takes the next token if it is the symbol or keyword `word`.
+    fn eat(&mut self, word: &str) -> bool {
+        let matches = match *self.peek() {
+            Token::Symbol(symbol) => symbol == word,
+            Token::Name(ref name) => name == word && KEYWORDS.contains(&word),
+            _ => false,
+        };
+        if matches {
+            self.advance();
+        }
+        matches
+    }
+
+    fn expect(&mut self, word: &str) -> Result<(), Error> {
+        if self.eat(word) {
+            Ok(())
+        } else {
+            self.error(format!("expected {}", word))
+        }
+    }
+
+    fn skip_newlines(&mut self) {
+        while *self.peek() == Token::Newline {
+            self.advance();
+        }
+    }
+
+    fn name(&mut self) -> Result<String, Error> {
+        match *self.peek() {
+            Token::Name(ref name) if !KEYWORDS.contains(&name.as_str()) => (),
+            _ => return self.error("expected a name"),
+        }
+        match self.advance() {
+            Token::Name(name) => Ok(name),
+            _ => unreachable!(),
+        }
+    }
+
+    /// Statements up to the end of the script, or the `}` closing a block.
+    fn statements(&mut self, in_block: bool) -> Result<Block, Error> {
+        let mut statements = vec![];
+        loop {
+            self.skip_newlines();
+            match *self.peek() {
+                Token::End if in_block => return self.error("expected }"),
+                Token::End => return Ok(statements),
+                Token::Symbol("}") if in_block => {
+                    self.advance();
+                    return Ok(statements);
+                }
+                _ => (),
+            }
+
+            let line = self.line();
+            statements.push((line, self.statement()?));
+            match *self.peek() {
+                Token::Newline | Token::End | Token::Symbol("}") => (),
+                _ => return self.error("expected the end of the statement"),
+            }
+        }
+    }
+
+    fn block(&mut self) -> Result<Block, Error> {
+        self.expect("{")?;
+        self.statements(true)
+    }
+
+    fn statement(&mut self) -> Result<Statement, Error> {
+        if self.eat("let") {
+            let name = self.name()?;
+            self.expect("=")?;
+            Ok(Statement::Let(name, self.expression()?))
+        } else if self.eat("fn") {
+            let name = self.name()?;
+            self.expect("(")?;
+            let mut parameters = vec![];
+            if !self.eat(")") {
+                loop {
+                    parameters.push(self.name()?);
+                    if self.eat(")") {
+                        break;
+                    }
+                    self.expect(",")?;
+                }
+            }
+
+            let loops = std::mem::replace(&mut self.loops, 0);
+            self.functions += 1;
+            let body = self.block();
+            self.functions -= 1;
+            self.loops = loops;
+
+            Ok(Statement::Function(Rc::new(Function {
+                name,
+                parameters,
+                body: body?,
+            })))
+        } else if self.eat("if") {
+            self.conditional()
+        } else if self.eat("while") {
+            let condition = self.expression()?;
+            self.loops += 1;
+            let body = self.block();
+            self.loops -= 1;
+            Ok(Statement::While(condition, body?))
+        } else if self.eat("return") {
+            if self.functions == 0 {
+                return self.error("return outside a function");
+            }
+            match *self.peek() {
+                Token::Newline | Token::End | Token::Symbol("}") => Ok(Statement::Return(None)),
+                _ => Ok(Statement::Return(Some(self.expression()?))),
+            }
+        } else if self.eat("break") {
+            if self.loops == 0 {
+                return self.error("break outside a loop");
+            }
+            Ok(Statement::Break)
+        } else {
+            let expression = self.expression()?;
+            if !self.eat("=") {
+                return Ok(Statement::Expression(expression));
+            }
+            match expression {
+                Expression::Variable(name) => Ok(Statement::Assign(name, self.expression()?)),
+                _ => self.error("can only assign to a variable"),
+            }
+        }
+    }
+
+    /// The rest of an `if` statement, after the `if`.
+    fn conditional(&mut self) -> Result<Statement, Error> {
+        let condition = self.expression()?;
+        let then = self.block()?;
+
+        // `else` may start the next line.
+        let next = self.next;
+        self.skip_newlines();
+        if !self.eat("else") {
+            self.next = next;
+            return Ok(Statement::If(condition, then, vec![]));
+        }
+
+        let otherwise = if self.eat("if") {
+            let line = self.line();
+            vec![(line, self.conditional()?)]
+        } else {
+            self.block()?
+        };
+        Ok(Statement::If(condition, then, otherwise))
+    }
+
+    fn expression(&mut self) -> Result<Expression, Error> {
+        let mut left = self.and()?;
+        while self.eat("or") {
+            left = Expression::Binary(Operator::Or, Box::new(left), Box::new(self.and()?));
+        }
+        Ok(left)
+    }
+
+    fn and(&mut self) -> Result<Expression, Error> {
+        let mut left = self.not()?;
+        while self.eat("and") {
+            left = Expression::Binary(Operator::And, Box::new(left), Box::new(self.not()?));
+        }
+        Ok(left)
+    }
+
+    fn not(&mut self) -> Result<Expression, Error> {
+        if self.eat("not") {
+            Ok(Expression::Not(Box::new(self.not()?)))
+        } else {
+            self.comparison()
+        }
+    }
+
+    fn comparison(&mut self) -> Result<Expression, Error> {
+        let left = self.sum()?;
+        let operator = match *self.peek() {
+            Token::Symbol("==") => Operator::Equal,
+            Token::Symbol("!=") => Operator::NotEqual,
+            Token::Symbol("<") => Operator::Less,
+            Token::Symbol("<=") => Operator::LessEqual,
+            Token::Symbol(">") => Operator::Greater,
+            Token::Symbol(">=") => Operator::GreaterEqual,
+            _ => return Ok(left),
+        };
+        self.advance();
+        Ok(Expression::Binary(
+            operator,
+            Box::new(left),
+            Box::new(self.sum()?),
+        ))
+    }
+
+    fn sum(&mut self) -> Result<Expression, Error> {
+        let mut left = self.product()?;
+        loop {
+            let operator = match *self.peek() {
+                Token::Symbol("+") => Operator::Add,
+                Token::Symbol("-") => Operator::Subtract,
+                _ => return Ok(left),
+            };
+            self.advance();
+            left = Expression::Binary(operator, Box::new(left), Box::new(self.product()?));
+        }
+    }
+
+    fn product(&mut self) -> Result<Expression, Error> {
+        let mut left = self.unary()?;
+        loop {
+            let operator = match *self.peek() {
+                Token::Symbol("*") => Operator::Multiply,
+                Token::Symbol("/") => Operator::Divide,
+                Token::Symbol("%") => Operator::Remainder,
+                _ => return Ok(left),
+            };
+            self.advance();
+            left = Expression::Binary(operator, Box::new(left), Box::new(self.unary()?));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expression, Error> {
+        if self.eat("-") {
+            return Ok(Expression::Negate(Box::new(self.unary()?)));
+        }
+
+        let mut expression = self.primary()?;
+        while self.eat("(") {
+            let mut arguments = vec![];
+            self.skip_newlines();
+            if !self.eat(")") {
+                loop {
+                    arguments.push(self.expression()?);
+                    self.skip_newlines();
+                    if self.eat(")") {
+                        break;
+                    }
+                    self.expect(",")?;
+                    self.skip_newlines();
+                }
+            }
+            expression = Expression::Call(Box::new(expression), arguments);
+        }
+        Ok(expression)
+    }
+
+    fn primary(&mut self) -> Result<Expression, Error> {
+        if self.eat("(") {
+            self.skip_newlines();
+            let expression = self.expression()?;
+            self.skip_newlines();
+            self.expect(")")?;
+            return Ok(expression);
+        }
+        if self.eat("true") {
+            return Ok(Expression::Literal(Value::Bool(true)));
+        }
+        if self.eat("false") {
+            return Ok(Expression::Literal(Value::Bool(false)));
+        }
+        if self.eat("nil") {
+            return Ok(Expression::Literal(Value::Nil));
+        }
+
+        match *self.peek() {
+            Token::Number(number) => {
+                self.advance();
+                Ok(Expression::Literal(Value::Number(number)))
+            }
+            Token::Str(ref text) => {
+                let text = text.clone();
+                self.advance();
+                Ok(Expression::Literal(Value::Str(text)))
+            }
+            Token::Name(_) => Ok(Expression::Variable(self.name()?)),
+            _ => self.error("expected a value"),
+        }
+    }
+}
+
+fn parse(source: &str) -> Result<Block, Error> {
+    let mut parser = Parser {
+        tokens: tokenize(source)?,
+        next: 0,
+        loops: 0,
+        functions: 0,
+    };
+    parser.statements(false)
+}
+
+#[derive(Clone, Debug)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(i64),
+    Str(String),
+    Function(Rc<Function>),
+    /// A function provided by the editor, by name.
+    Builtin(&'static str),
+}
+
+impl Value {
+    fn truthy(&self) -> bool {
+        !matches!(*self, Value::Nil | Value::Bool(false))
+    }
+
+    fn kind(&self) -> &'static str {
+        match *self {
+            Value::Nil => "nil",
+            Value::Bool(_) => "a bool",
+            Value::Number(_) => "a number",
+            Value::Str(_) => "a string",
+            Value::Function(_) | Value::Builtin(_) => "a function",
+        }
+    }
+
+    fn equals(&self, other: &Value) -> bool {
+        match (self, other) {
+            (&Value::Nil, &Value::Nil) => true,
+            (&Value::Bool(a), &Value::Bool(b)) => a == b,
+            (&Value::Number(a), &Value::Number(b)) => a == b,
+            (Value::Str(a), Value::Str(b)) => a == b,
+            (Value::Function(a), Value::Function(b)) => Rc::ptr_eq(a, b),
+            (&Value::Builtin(a), &Value::Builtin(b)) => a == b,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Nil => write!(f, "nil"),
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Str(ref text) => write!(f, "{}", text),
+            Value::Function(ref function) => write!(f, "fn {}", function.name),
+            Value::Builtin(name) => write!(f, "fn {}", name),
+        }
+    }
+}
+
+/// The functions the editor provides, with how many arguments each takes.
+const BUILTINS: [(&str, usize); 19] = [
+    ("line_count", 0),
+    ("get_line", 1),
+    ("buffer_name", 0),
+    ("insert", 3),
+    ("delete", 4),
+    ("cursor_line", 0),
+    ("cursor_column", 0),
+    ("goto", 2),
+    ("message", 1),
+    ("action", 1),
+    ("command", 2),
+    ("bind", 2),
+    ("len", 1),
+    ("upper", 1),
+    ("lower", 1),
+    ("slice", 3),
+    ("find", 2),
+    ("str", 1),
+    ("num", 1),
+];
+
+fn number(value: &Value) -> Result<i64, String> {
+    match *value {
+        Value::Number(n) => Ok(n),
+        _ => Err(format!("expected a number, not {}", value.kind())),
+    }
+}
+
+fn index(value: &Value) -> Result<usize, String> {
+    let n = number(value)?;
+    if n < 0 {
+        return Err(format!("{} is negative", n));
+    }
+    Ok(n as usize)
+}
+
+fn text(value: &Value) -> Result<&str, String> {
+    match *value {
+        Value::Str(ref text) => Ok(text),
+        _ => Err(format!("expected a string, not {}", value.kind())),
+    }
+}
+
+/// Reads a line and column, checking they are in the buffer.
+fn position(buffer: &Buffer, line: &Value, column: &Value) -> Result<(usize, usize), String> {
+    let (line, column) = (index(line)?, index(column)?);
+    if line >= buffer.len() || column > buffer.borrow_line(line).len() {
+        return Err(format!("{}:{} is outside the buffer", line, column));
+    }
+    Ok((line, column))
+}
+
+/// The byte offset of the character at `index` in `text`, or of its end.
+fn offset(text: &str, index: usize) -> Option<usize> {
+    text.char_indices()
+        .map(|(offset, _)| offset)
+        .chain(Some(text.len()))
+        .nth(index)
+}
+
+/// How a statement finished.
+enum Flow {
+    Next,
+    Break,
+    Return(Value),
+}
+
+/// Runs a script against the editor, with the variables of each function
+/// call being run.
+struct Machine<'a> {
+    script: &'a mut Script,
+    host: &'a mut dyn Host,
+    buffer: &'a Arc<Mutex<Buffer>>,
+    frames: Vec<HashMap<String, Value>>,
+    steps: usize,
+    line: usize,
+}
+
+impl<'a> Machine<'a> {
+    fn step(&mut self) -> Result<(), Error> {
+        self.steps += 1;
+        if self.steps > STEPS {
+            return Err(Error::new(self.line, "took too long, stopping it"));
+        }
+        Ok(())
+    }
+
+    fn scope(&mut self) -> &mut HashMap<String, Value> {
+        match self.frames.last_mut() {
+            Some(frame) => frame,
+            None => &mut self.script.globals,
+        }
+    }
+
+    fn block(&mut self, block: &[(usize, Statement)]) -> Result<Flow, Error> {
+        for &(line, ref statement) in block {
+            self.line = line;
+            self.step()?;
+            match self.statement(statement)? {
+                Flow::Next => (),
+                flow => return Ok(flow),
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn statement(&mut self, statement: &Statement) -> Result<Flow, Error> {
+        match *statement {
+            Statement::Let(ref name, ref value) => {
+                let value = self.evaluate(value)?;
+                self.scope().insert(name.clone(), value);
+            }
+            Statement::Assign(ref name, ref value) => {
+                let value = self.evaluate(value)?;
+                let variable = match self.frames.last_mut() {
+                    Some(frame) if frame.contains_key(name) => frame.get_mut(name),
+                    _ => self.script.globals.get_mut(name),
+                };
+                match variable {
+                    Some(variable) => *variable = value,
+                    None => return Err(Error::new(self.line, format!("no variable {}", name))),
+                }
+            }
+            Statement::Function(ref function) => {
+                let value = Value::Function(Rc::clone(function));
+                self.scope().insert(function.name.clone(), value);
+            }
+            Statement::If(ref condition, ref then, ref otherwise) => {
+                return if self.evaluate(condition)?.truthy() {
+                    self.block(then)
+                } else {
+                    self.block(otherwise)
+                };
+            }
+            Statement::While(ref condition, ref body) => {
+                let line = self.line;
+                while self.evaluate(condition)?.truthy() {
+                    match self.block(body)? {
+                        Flow::Next => (),
+                        Flow::Break => break,
+                        flow => return Ok(flow),
+                    }
+                    self.line = line;
+                    self.step()?;
+                }
+            }
+            Statement::Return(ref value) => {
+                let value = match *value {
+                    Some(ref value) => self.evaluate(value)?,
+                    None => Value::Nil,
+                };
+                return Ok(Flow::Return(value));
+            }
+            Statement::Break => return Ok(Flow::Break),
+            Statement::Expression(ref expression) => {
+                self.evaluate(expression)?;
+            }
+        }
+        Ok(Flow::Next)
+    }
+
+    fn lookup(&self, name: &str) -> Option<Value> {
+        self.frames
+            .last()
+            .and_then(|frame| frame.get(name))
+            .or_else(|| self.script.globals.get(name))
+            .cloned()
+            .or_else(|| {
+                BUILTINS
+                    .iter()
+                    .find(|&&(builtin, _)| builtin == name)
+                    .map(|&(builtin, _)| Value::Builtin(builtin))
+            })
+    }
+
+    fn evaluate(&mut self, expression: &Expression) -> Result<Value, Error> {
+        let value = match *expression {
+            Expression::Literal(ref value) => value.clone(),
+            Expression::Variable(ref name) => match self.lookup(name) {
+                Some(value) => value,
+                None => return Err(Error::new(self.line, format!("no variable {}", name))),
+            },
+            Expression::Call(ref function, ref arguments) => {
+                let function = self.evaluate(function)?;
+                let arguments = arguments
+                    .iter()
+                    .map(|argument| self.evaluate(argument))
+                    .collect::<Result<Vec<_>, _>>()?;
+                return self.call(&function, arguments);
+            }
+            Expression::Negate(ref value) => {
+                let n = number(&self.evaluate(value)?).map_err(|e| Error::new(self.line, e))?;
+                Value::Number(
+                    n.checked_neg()
+                        .ok_or_else(|| Error::new(self.line, "number too large"))?,
+                )
+            }
+            Expression::Not(ref value) => Value::Bool(!self.evaluate(value)?.truthy()),
+            Expression::Binary(Operator::And, ref left, ref right) => {
+                Value::Bool(self.evaluate(left)?.truthy() && self.evaluate(right)?.truthy())
+            }
+            Expression::Binary(Operator::Or, ref left, ref right) => {
+                Value::Bool(self.evaluate(left)?.truthy() || self.evaluate(right)?.truthy())
+            }
+            Expression::Binary(operator, ref left, ref right) => {
+                let left = self.evaluate(left)?;
+                let right = self.evaluate(right)?;
+                binary(operator, &left, &right).map_err(|e| Error::new(self.line, e))?
+            }
+        };
+        Ok(value)
+    }
+
+    fn call(&mut self, function: &Value, arguments: Vec<Value>) -> Result<Value, Error> {
+        self.step()?;
+        let function = match *function {
+            Value::Function(ref function) => Rc::clone(function),
+            Value::Builtin(name) => {
+                let &(_, count) = BUILTINS.iter().find(|&&(b, _)| b == name).unwrap();
+                if arguments.len() != count {
+                    return Err(Error::new(
+                        self.line,
+                        format!(
+                            "{} takes {} arguments, not {}",
+                            name,
+                            count,
+                            arguments.len()
+                        ),
+                    ));
+                }
+                return self
+                    .builtin(name, &arguments)
+                    .map_err(|e| Error::new(self.line, format!("{}: {}", name, e)));
+            }
+            ref value => {
+                return Err(Error::new(
+                    self.line,
+                    format!("cannot call {}", value.kind()),
+                ))
+            }
+        };
+
+        if arguments.len() != function.parameters.len() {
+            return Err(Error::new(
+                self.line,
+                format!(
+                    "{} takes {} arguments, not {}",
+                    function.name,
+                    function.parameters.len(),
+                    arguments.len()
+                ),
+            ));
+        }
+        if self.frames.len() >= DEPTH {
+            return Err(Error::new(self.line, "calls nested too deeply"));
+        }
+
+        let frame = function.parameters.iter().cloned().zip(arguments).collect();
+        self.frames.push(frame);
+        let line = self.line;
+        let flow = self.block(&function.body);
+        self.frames.pop();
+        self.line = line;
+
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            _ => Ok(Value::Nil),
+        }
+    }
+
+    fn builtin(&mut self, name: &str, arguments: &[Value]) -> Result<Value, String> {
+        let value = match name {
+            "line_count" => Value::Number(self.buffer.lock().unwrap().len() as i64),
+            "get_line" => {
+                let buffer = self.buffer.lock().unwrap();
+                let line = index(&arguments[0])?;
+                if line >= buffer.len() {
+                    return Err(format!("no line {}", line));
+                }
+                Value::Str(buffer.borrow_line(line).as_str().to_string())
+            }
+            "buffer_name" => match self.buffer.lock().unwrap().filename() {
+                Some(name) => Value::Str(name.to_string()),
+                None => Value::Nil,
+            },
+            "insert" => {
+                let mut buffer = self.buffer.lock().unwrap();
+                let (line, column) = position(&buffer, &arguments[0], &arguments[1])?;
+                buffer.insert_str(line, column, text(&arguments[2])?);
+                Value::Nil
+            }
+            "delete" => {
+                let mut buffer = self.buffer.lock().unwrap();
+                let start = position(&buffer, &arguments[0], &arguments[1])?;
+                let end = position(&buffer, &arguments[2], &arguments[3])?;
+                if end < start {
+                    return Err("the end comes before the start".to_string());
+                }
+                Value::Str(buffer.delete_range(start, end))
+            }
+            "cursor_line" => Value::Number(self.host.cursor().0 as i64),
+            "cursor_column" => Value::Number(self.host.cursor().1 as i64),
+            "goto" => {
+                let (line, column) =
+                    position(&self.buffer.lock().unwrap(), &arguments[0], &arguments[1])?;
+                self.host.set_cursor(line, column);
+                Value::Nil
+            }
+            "message" => {
+                self.host.show_message(arguments[0].to_string());
+                Value::Nil
+            }
+            "action" => {
+                self.host.act(text(&arguments[0])?)?;
+                Value::Nil
+            }
+            "command" => {
+                let name = text(&arguments[0])?.to_string();
+                match arguments[1] {
+                    Value::Function(ref function) => {
+                        self.script.commands.insert(name, Rc::clone(function));
+                    }
+                    ref value => return Err(format!("expected a function, not {}", value.kind())),
+                }
+                Value::Nil
+            }
+            "bind" => {
+                let written = text(&arguments[0])?;
+                let key = parse_key(written).ok_or_else(|| format!("bad key {}", written))?;
+                let command = text(&arguments[1])?.to_string();
+                self.script.bindings.retain(|&(bound, _)| bound != key);
+                self.script.bindings.push((key, command));
+                Value::Nil
+            }
+            "len" => Value::Number(text(&arguments[0])?.chars().count() as i64),
+            "upper" => Value::Str(text(&arguments[0])?.to_uppercase()),
+            "lower" => Value::Str(text(&arguments[0])?.to_lowercase()),
+            "slice" => {
+                let whole = text(&arguments[0])?;
+                let (start, end) = (index(&arguments[1])?, index(&arguments[2])?);
+                match (offset(whole, start), offset(whole, end)) {
+                    (Some(start), Some(end)) if start <= end => {
+                        Value::Str(whole[start..end].to_string())
+                    }
+                    _ => return Err(format!("{}..{} is outside the string", start, end)),
+                }
+            }
+            "find" => {
+                let whole = text(&arguments[0])?;
+                match whole.find(text(&arguments[1])?) {
+                    Some(offset) => Value::Number(whole[..offset].chars().count() as i64),
+                    None => Value::Nil,
+                }
+            }
+            "str" => Value::Str(arguments[0].to_string()),
+            "num" => match text(&arguments[0])?.trim().parse() {
+                Ok(n) => Value::Number(n),
+                Err(_) => Value::Nil,
+            },
+            _ => unreachable!(),
+        };
+        Ok(value)
+    }
+}
+
+fn binary(operator: Operator, left: &Value, right: &Value) -> Result<Value, String> {
+    let too_large = || "number too large".to_string();
+    let value = match (operator, left, right) {
+        (Operator::Equal, _, _) => Value::Bool(left.equals(right)),
+        (Operator::NotEqual, _, _) => Value::Bool(!left.equals(right)),
+        (Operator::Add, &Value::Str(_), _) | (Operator::Add, _, &Value::Str(_)) => {
+            Value::Str(format!("{}{}", left, right))
+        }
+        (_, &Value::Number(a), &Value::Number(b)) => match operator {
+            Operator::Add => Value::Number(a.checked_add(b).ok_or_else(too_large)?),
+            Operator::Subtract => Value::Number(a.checked_sub(b).ok_or_else(too_large)?),
+            Operator::Multiply => Value::Number(a.checked_mul(b).ok_or_else(too_large)?),
+            Operator::Divide | Operator::Remainder if b == 0 => {
+                return Err("division by zero".to_string())
+            }
+            Operator::Divide => Value::Number(a.checked_div(b).ok_or_else(too_large)?),
+            Operator::Remainder => Value::Number(a.checked_rem(b).ok_or_else(too_large)?),
+            Operator::Less => Value::Bool(a < b),
+            Operator::LessEqual => Value::Bool(a <= b),
+            Operator::Greater => Value::Bool(a > b),
+            Operator::GreaterEqual => Value::Bool(a >= b),
+            _ => unreachable!(),
+        },
+        (_, Value::Str(a), Value::Str(b)) => match operator {
+            Operator::Less => Value::Bool(a < b),
+            Operator::LessEqual => Value::Bool(a <= b),
+            Operator::Greater => Value::Bool(a > b),
+            Operator::GreaterEqual => Value::Bool(a >= b),
+            _ => return Err(format!("cannot use {} on strings", operator.symbol())),
+        },
+        _ => {
+            return Err(format!(
+                "cannot use {} on {} and {}",
+                operator.symbol(),
+                left.kind(),
+                right.kind()
+            ))
+        }
+    };
+    Ok(value)
+}
+
+/// The global variables, commands and key bindings scripts have defined.
+#[derive(Default)]
+pub struct Script {
+    globals: HashMap<String, Value>,
+    commands: HashMap<String, Rc<Function>>,
+    bindings: Vec<(Key, String)>,
+}
+
+impl Script {
+    /// Runs `source` from the top, with `buffer` as the one being edited.
+    pub fn run(
+        &mut self,
+        source: &str,
+        host: &mut dyn Host,
+        buffer: &Arc<Mutex<Buffer>>,
+    ) -> Result<(), Error> {
+        let block = parse(source)?;
+        let mut machine = Machine {
+            script: self,
+            host,
+            buffer,
+            frames: vec![],
+            steps: 0,
+            line: 1,
+        };
+        machine.block(&block).map(|_| ())
+    }
+
+    pub fn has_command(&self, name: &str) -> bool {
+        self.commands.contains_key(name)
+    }
+
+    /// The names of the commands defined so far.
+    pub fn commands(&self) -> Vec<String> {
+        self.commands.keys().cloned().collect()
+    }
+
+    /// Runs the command `name` on `buffer`.
+    pub fn command(
+        &mut self,
+        name: &str,
+        host: &mut dyn Host,
+        buffer: &Arc<Mutex<Buffer>>,
+    ) -> Result<(), Error> {
+        let function = match self.commands.get(name) {
+            Some(function) => Value::Function(Rc::clone(function)),
+            None => return Err(Error::new(0, format!("no command {}", name))),
+        };
+        let mut machine = Machine {
+            script: self,
+            host,
+            buffer,
+            frames: vec![],
+            steps: 0,
+            line: 0,
+        };
+        machine.call(&function, vec![]).map(|_| ())
+    }
+
+    /// The command bound to `key` by a script, if any.
+    pub fn binding(&self, key: Key) -> Option<String> {
+        self.bindings
+            .iter()
+            .find(|&&(bound, _)| bound == key)
+            .map(|(_, command)| command.clone())
+    }
+}
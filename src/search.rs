@@ -0,0 +1,110 @@
+//! Finding text in a buffer. Patterns are plain text, matched against each
+//! line where it is stored, so searching copies nothing.
+
+use super::buffer::{Buffer, Line};
+
+/// Whether letters must match in case.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Case {
+    Sensitive,
+    Insensitive,
+    /// Sensitive only if the pattern has a capital letter in it.
+    Smart,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Search {
+    pattern: Vec<char>,
+    ignore_case: bool,
+}
+
+impl Search {
+    pub fn new(pattern: &str, case: Case) -> Search {
+        let ignore_case = match case {
+            Case::Sensitive => false,
+            Case::Insensitive => true,
+            Case::Smart => !pattern.chars().any(char::is_uppercase),
+        };
+        Search {
+            pattern: pattern.chars().collect(),
+            ignore_case,
+        }
+    }
+
+    pub fn pattern(&self) -> String {
+        self.pattern.iter().collect()
+    }
+
+    fn same(&self, a: char, b: char) -> bool {
+        a == b || self.ignore_case && a.to_lowercase().eq(b.to_lowercase())
+    }
+
+    /// The column of every match in `line`, in order, overlapping or not.
+    fn starts<'a>(&'a self, line: &'a Line) -> impl Iterator<Item = usize> + 'a {
+        let text = line.as_str();
+        text.char_indices()
+            .enumerate()
+            .filter(move |&(_, (offset, _))| {
+                let mut rest = text[offset..].chars();
+                !self.pattern.is_empty()
+                    && self
+                        .pattern
+                        .iter()
+                        .all(|&wanted| rest.next().is_some_and(|c| self.same(wanted, c)))
+            })
+            .map(|(column, _)| column)
+    }
+
+    /// The columns each match in `line` runs over, from its first to just
+    /// after its last, leaving out any which overlap one before.
+    pub fn matches(&self, line: &Line) -> Vec<(usize, usize)> {
+        let mut matches: Vec<(usize, usize)> = vec![];
+        for start in self.starts(line) {
+            if matches.last().is_none_or(|&(_, end)| end <= start) {
+                matches.push((start, start + self.pattern.len()));
+            }
+        }
+        matches
+    }
+
+    /// The nearest match after `from`, or before it if not `forward`,
+    /// wrapping round the end of the buffer, along with whether it did.
+    /// A match at `from` itself is only found once every other line has
+    /// been looked through.
+    pub fn next(
+        &self,
+        buffer: &Buffer,
+        from: (usize, usize),
+        forward: bool,
+    ) -> Option<((usize, usize), bool)> {
+        let lines = buffer.len();
+        let (line, column) = from;
+        for i in 0..=lines {
+            let at = if forward {
+                (line + i) % lines
+            } else {
+                (line + lines - i % lines) % lines
+            };
+            // The line searched from is looked through twice: first beyond
+            // `from`, then, once the search has wrapped round, up to it.
+            let mut starts = self.starts(buffer.borrow_line(at));
+            let found = match (i, forward) {
+                (0, true) => starts.find(|&start| start > column),
+                (0, false) => starts.take_while(|&start| start < column).last(),
+                (_, true) if i == lines => starts.next().filter(|&start| start <= column),
+                (_, false) if i == lines => starts.last().filter(|&start| start >= column),
+                (_, true) => starts.next(),
+                (_, false) => starts.last(),
+            };
+            if let Some(start) = found {
+                let wrapped = if forward {
+                    at <= line && i > 0
+                } else {
+                    at >= line && i > 0
+                };
+                return Some(((at, start), wrapped));
+            }
+        }
+        None
+    }
+}
@@ -0,0 +1,198 @@
+//! Replacing what a regular expression matches. A substitution is written
+//! as in vi, `/pattern/replacement/flags`, though any punctuation may stand
+//! in for the slashes, and the last one may be left off:
+//!
+//! - The pattern is a regular expression as the `regex` crate reads it,
+//!   with `^` and `$` matching at the start and end of every line. It is
+//!   matched against the lines joined by newlines, so `\n` matches a line
+//!   break.
+//! - In the replacement, `$1` or `\1` stands for what the first group
+//!   matched, `${name}` for a named group, and `$0` or `&` for the whole
+//!   match. `\n` breaks the line, `\t` is a tab, and `$$`, `\&` and `\\`
+//!   are a plain `$`, `&` and `\`.
+//! - The flags are `g` to replace every match rather than only the first
+//!   starting on each line, `i` to ignore case, and `c` to ask before each.
+//!
+//! In either part a backslash before the separator makes it a plain
+//! character.
+
+use regex::{Captures, Regex, RegexBuilder};
+
+use super::buffer::Buffer;
+
+pub struct Substitution {
+    regex: Regex,
+    replacement: String,
+    every: bool,
+    confirm: bool,
+}
+
+/// Text to put in place of a match.
+#[derive(Clone, Debug)]
+pub struct Replacement {
+    pub start: (usize, usize),
+    pub end: (usize, usize),
+    pub text: String,
+}
+
+/// Splits `written` at each `separator` not escaped with a backslash,
+/// dropping the backslash from those which are.
+fn split(written: &str, separator: char) -> Vec<String> {
+    let mut parts = vec![String::new()];
+    let mut chars = written.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => match chars.next() {
+                Some(next) if next == separator => parts.last_mut().unwrap().push(next),
+                Some(next) => {
+                    let part = parts.last_mut().unwrap();
+                    part.push('\\');
+                    part.push(next);
+                }
+                None => parts.last_mut().unwrap().push('\\'),
+            },
+            c if c == separator => parts.push(String::new()),
+            c => parts.last_mut().unwrap().push(c),
+        }
+    }
+    parts
+}
+
+/// Turns a replacement as written into the form `Captures::expand` takes.
+fn replacement(written: &str) -> String {
+    let mut replacement = String::new();
+    let mut chars = written.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '&' => replacement.push_str("${0}"),
+            '\\' => match chars.next() {
+                Some('n') => replacement.push('\n'),
+                Some('t') => replacement.push('\t'),
+                Some('&') => replacement.push('&'),
+                Some('\\') | None => replacement.push('\\'),
+                Some(digit) if digit.is_ascii_digit() => {
+                    replacement.push_str(&format!("${{{}}}", digit))
+                }
+                Some(other) => {
+                    replacement.push('\\');
+                    replacement.push(other);
+                }
+            },
+            c => replacement.push(c),
+        }
+    }
+    replacement
+}
+
+impl Substitution {
+    pub fn parse(written: &str) -> Result<Substitution, String> {
+        let separator = written
+            .chars()
+            .next()
+            .filter(|c| c.is_ascii_punctuation() && *c != '\\')
+            .ok_or("expected /pattern/replacement/")?;
+        let parts = split(&written[separator.len_utf8()..], separator);
+        if parts.len() < 2 || parts.len() > 3 {
+            return Err("expected /pattern/replacement/".to_string());
+        }
+
+        let (mut every, mut ignore_case, mut confirm) = (false, false, false);
+        for flag in parts.get(2).map_or("", |flags| flags.as_str()).chars() {
+            match flag {
+                'g' => every = true,
+                'i' => ignore_case = true,
+                'c' => confirm = true,
+                _ => return Err(format!("unknown flag {}", flag)),
+            }
+        }
+
+        let regex = RegexBuilder::new(&parts[0])
+            .multi_line(true)
+            .case_insensitive(ignore_case)
+            .build()
+            .map_err(|error| match error {
+                regex::Error::Syntax(ref message) => {
+                    // The last line says what is wrong, below a picture of
+                    // where.
+                    message.lines().last().unwrap_or("").to_string()
+                }
+                error => error.to_string(),
+            })?;
+        Ok(Substitution {
+            regex,
+            replacement: replacement(&parts[1]),
+            every,
+            confirm,
+        })
+    }
+
+    pub fn pattern(&self) -> &str {
+        self.regex.as_str()
+    }
+
+    /// Whether each replacement is to be asked about first.
+    pub fn confirm(&self) -> bool {
+        self.confirm
+    }
+
+    /// What to replace in lines `first` to `last` of `buffer`, in order.
+    pub fn replacements(&self, buffer: &Buffer, first: usize, last: usize) -> Vec<Replacement> {
+        let end = (last, buffer.borrow_line(last).len());
+        let text = buffer.text_range((first, 0), end);
+
+        // Positions are worked out walking along the text, as the matches
+        // come in order.
+        let mut walked = (0, (first, 0));
+        let mut position = |offset: usize| {
+            let (from, (mut line, mut column)) = walked;
+            for c in text[from..offset].chars() {
+                if c == '\n' {
+                    line += 1;
+                    column = 0;
+                } else {
+                    column += 1;
+                }
+            }
+            walked = (offset, (line, column));
+            (line, column)
+        };
+
+        let mut replacements: Vec<Replacement> = vec![];
+        for captures in self.regex.captures_iter(&text) {
+            let found = captures.get(0).unwrap();
+            let start = position(found.start());
+            let end = position(found.end());
+            let same_line = replacements
+                .last()
+                .is_some_and(|last| last.start.0 == start.0);
+            if same_line && !self.every {
+                continue;
+            }
+            replacements.push(Replacement {
+                start,
+                end,
+                text: self.expand(&captures),
+            });
+        }
+        replacements
+    }
+
+    fn expand(&self, captures: &Captures) -> String {
+        let mut text = String::new();
+        captures.expand(&self.replacement, &mut text);
+        text
+    }
+}
+
+/// Makes `replacements`, which must be in order and not overlap, as a
+/// single undo step.
+pub fn apply(buffer: &mut Buffer, replacements: &[Replacement]) {
+    buffer.begin();
+    // From the last, so each leaves the positions of those before alone.
+    for replacement in replacements.iter().rev() {
+        buffer.delete_range(replacement.start, replacement.end);
+        let (line, column) = replacement.start;
+        buffer.insert_str(line, column, &replacement.text);
+    }
+    buffer.commit();
+}
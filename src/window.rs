@@ -0,0 +1,250 @@
+use super::cursor::Cursor;
+
+/// A rectangle of terminal cells, counted from zero at the top left.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct Rect {
+    pub x: u16,
+    pub y: u16,
+    pub width: u16,
+    pub height: u16,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Direction {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+/// A view onto a buffer with its own cursors and scroll offset. Any number
+/// of windows may show the same buffer.
+pub struct Window {
+    pub cursor: Cursor,
+    /// Cursors besides the main one, at each of which typing in insert
+    /// mode is repeated.
+    pub cursors: Vec<Cursor>,
+    pub offset: usize,
+    /// How far lines are scrolled sideways when they are not wrapped.
+    pub shift: usize,
+}
+
+impl Window {
+    pub fn new(cursor: Cursor) -> Window {
+        Window {
+            cursor,
+            cursors: vec![],
+            offset: 0,
+            shift: 0,
+        }
+    }
+}
+
+/// How the terminal is divided between windows, which are named by their
+/// index in `Screen::windows`. Each split halves its area, either side by
+/// side (vertically) or one above the other.
+pub enum Layout {
+    Window(usize),
+    Split {
+        vertical: bool,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+
+/// The two halves of `area`, and the column dividing them for a vertical
+/// split.
+fn halves(area: Rect, vertical: bool) -> (Rect, Rect, Option<Rect>) {
+    if vertical {
+        let width = area.width.saturating_sub(1) / 2;
+        let first = Rect { width, ..area };
+        let divider = Rect {
+            x: area.x + width,
+            width: std::cmp::min(1, area.width),
+            ..area
+        };
+        let second = Rect {
+            x: divider.x + divider.width,
+            width: area.width - width - divider.width,
+            ..area
+        };
+        (first, second, Some(divider))
+    } else {
+        let height = area.height / 2;
+        let first = Rect { height, ..area };
+        let second = Rect {
+            y: area.y + height,
+            height: area.height - height,
+            ..area
+        };
+        (first, second, None)
+    }
+}
+
+/// Whether `area` can be split with room left in each half for at least a
+/// row of text above its status line.
+pub fn can_split(area: Rect, vertical: bool) -> bool {
+    let (first, second, _) = halves(area, vertical);
+    [first, second]
+        .iter()
+        .all(|half| half.width >= 1 && half.height >= 2)
+}
+
+impl Layout {
+    /// Splits the area given to window `target`, putting window `new` in
+    /// the second half.
+    pub fn split(&mut self, target: usize, new: usize, vertical: bool) {
+        match *self {
+            Layout::Window(index) if index == target => {
+                *self = Layout::Split {
+                    vertical,
+                    first: Box::new(Layout::Window(target)),
+                    second: Box::new(Layout::Window(new)),
+                };
+            }
+            Layout::Window(_) => (),
+            Layout::Split {
+                ref mut first,
+                ref mut second,
+                ..
+            } => {
+                first.split(target, new, vertical);
+                second.split(target, new, vertical);
+            }
+        }
+    }
+
+    /// Takes window `target` out of the layout, giving its area to its
+    /// neighbour. Windows after it move down one index to fill the gap.
+    pub fn remove(&mut self, target: usize) {
+        let replacement = match *self {
+            Layout::Window(ref mut index) => {
+                if *index > target {
+                    *index -= 1;
+                }
+                return;
+            }
+            Layout::Split {
+                ref mut first,
+                ref mut second,
+                ..
+            } => match (&**first, &**second) {
+                (&Layout::Window(index), _) if index == target => {
+                    std::mem::replace(&mut **second, Layout::Window(0))
+                }
+                (_, &Layout::Window(index)) if index == target => {
+                    std::mem::replace(&mut **first, Layout::Window(0))
+                }
+                _ => {
+                    first.remove(target);
+                    second.remove(target);
+                    return;
+                }
+            },
+        };
+        *self = replacement;
+        self.remove(target);
+    }
+
+    /// The area each window is given within `area`.
+    pub fn areas(&self, area: Rect) -> Vec<(usize, Rect)> {
+        match *self {
+            Layout::Window(index) => vec![(index, area)],
+            Layout::Split {
+                vertical,
+                ref first,
+                ref second,
+            } => {
+                let (first_area, second_area, _) = halves(area, vertical);
+                let mut areas = first.areas(first_area);
+                areas.extend(second.areas(second_area));
+                areas
+            }
+        }
+    }
+
+    /// The columns drawn between windows side by side.
+    pub fn dividers(&self, area: Rect) -> Vec<Rect> {
+        match *self {
+            Layout::Window(_) => vec![],
+            Layout::Split {
+                vertical,
+                ref first,
+                ref second,
+            } => {
+                let (first_area, second_area, divider) = halves(area, vertical);
+                let mut dividers = first.dividers(first_area);
+                dividers.extend(divider);
+                dividers.extend(second.dividers(second_area));
+                dividers
+            }
+        }
+    }
+}
+
+/// The window next to window `from` in `direction`, given the area of each
+/// window. Of several, the one nearest the top or left is chosen.
+pub fn neighbour(areas: &[(usize, Rect)], from: usize, direction: Direction) -> Option<usize> {
+    let &(_, area) = areas.iter().find(|&&(index, _)| index == from)?;
+    let overlaps = |a: u16, a_len: u16, b: u16, b_len: u16| a < b + b_len && b < a + a_len;
+
+    areas
+        .iter()
+        .filter(|&&(index, other)| {
+            index != from
+                && match direction {
+                    Direction::Left => {
+                        other.x + other.width < area.x
+                            && overlaps(area.y, area.height, other.y, other.height)
+                    }
+                    Direction::Right => {
+                        other.x > area.x + area.width
+                            && overlaps(area.y, area.height, other.y, other.height)
+                    }
+                    Direction::Up => {
+                        other.y + other.height <= area.y
+                            && overlaps(area.x, area.width, other.x, other.width)
+                    }
+                    Direction::Down => {
+                        other.y >= area.y + area.height
+                            && overlaps(area.x, area.width, other.x, other.width)
+                    }
+                }
+        })
+        .min_by_key(|&&(_, other)| {
+            let distance = match direction {
+                Direction::Left => area.x - other.x,
+                Direction::Right => other.x - area.x,
+                Direction::Up => area.y - other.y,
+                Direction::Down => other.y - area.y,
+            };
+            (distance, other.y, other.x)
+        })
+        .map(|&(index, _)| index)
+}
+
+/// Every window, how they are laid out, and which one has the focus.
+pub struct Screen {
+    pub windows: Vec<Window>,
+    pub layout: Layout,
+    pub focus: usize,
+}
+
+impl Screen {
+    pub fn new(window: Window) -> Screen {
+        Screen {
+            windows: vec![window],
+            layout: Layout::Window(0),
+            focus: 0,
+        }
+    }
+
+    /// The area the focused window is given within `area`.
+    pub fn focused(&self, area: Rect) -> Rect {
+        self.layout
+            .areas(area)
+            .into_iter()
+            .find(|&(index, _)| index == self.focus)
+            .map_or(area, |(_, rect)| rect)
+    }
+}
@@ -0,0 +1,126 @@
+use std::sync::mpsc::{channel, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::thread::JoinHandle;
+
+use super::buffer::{Buffer, Snapshot};
+
+enum Job {
+    Save(Arc<Mutex<Buffer>>, Snapshot),
+    /// Answers once every save queued before it is done.
+    Settle(Sender<()>),
+}
+
+/// Writes buffers back to disk on a background thread, so that saving a
+/// large file never stalls the input loop.
+///
+/// Progress and the outcome of each save are reported through `message`,
+/// calling `notify` after every update so the status line can be redrawn.
+pub struct Writer {
+    jobs: Option<Sender<Job>>,
+    thread: Option<JoinHandle<()>>,
+    message: Arc<Mutex<Option<String>>>,
+}
+
+impl Writer {
+    pub fn spawn<F>(message: Arc<Mutex<Option<String>>>, notify: F) -> Writer
+    where
+        F: 'static + Send + Fn(),
+    {
+        let (jobs, queue) = channel::<Job>();
+        let shown = Arc::clone(&message);
+
+        let thread = thread::spawn(move || {
+            for job in queue {
+                let (buffer, snapshot) = match job {
+                    Job::Save(buffer, snapshot) => (buffer, snapshot),
+                    Job::Settle(done) => {
+                        let _ = done.send(());
+                        continue;
+                    }
+                };
+                let total = snapshot.len();
+                let mut shown = None;
+
+                let result = snapshot.write_back(|written| {
+                    let percent = written * 100 / total;
+                    if shown != Some(percent) {
+                        shown = Some(percent);
+                        *message.lock().unwrap() =
+                            Some(format!("saving {} {}%", snapshot.name(), percent));
+                        notify();
+                    }
+                });
+
+                let status = match result {
+                    Ok(()) => {
+                        // A copy written elsewhere leaves the buffer as it was.
+                        let mut buffer = buffer.lock().unwrap();
+                        if buffer.filename() == Some(snapshot.name()) {
+                            buffer.mark_saved(snapshot.revision());
+                        }
+                        format!("wrote {} lines to {}", total, snapshot.name())
+                    }
+                    Err(error) => format!("save failed: {}", error),
+                };
+
+                *message.lock().unwrap() = Some(status);
+                notify();
+            }
+        });
+
+        Writer {
+            jobs: Some(jobs),
+            thread: Some(thread),
+            message: shown,
+        }
+    }
+
+    /// Queues `snapshot` of `buffer` to be written, reporting the save as
+    /// failed if the writer thread has stopped.
+    fn queue(&self, buffer: &Arc<Mutex<Buffer>>, snapshot: Snapshot) {
+        if let Some(ref jobs) = self.jobs {
+            let name = snapshot.name().to_string();
+            if jobs.send(Job::Save(Arc::clone(buffer), snapshot)).is_err() {
+                *self.message.lock().unwrap() =
+                    Some(format!("save failed: {}: the writer has stopped", name));
+            }
+        }
+    }
+
+    /// Queues a snapshot of `buffer` to be written. Buffers without a file
+    /// are left alone.
+    pub fn save(&self, buffer: &Arc<Mutex<Buffer>>) {
+        let snapshot = buffer.lock().unwrap().snapshot();
+        if let Some(snapshot) = snapshot {
+            self.queue(buffer, snapshot);
+        }
+    }
+
+    /// Queues a copy of `buffer` to be written to the file `name`.
+    pub fn save_to(&self, buffer: &Arc<Mutex<Buffer>>, name: String) {
+        let snapshot = buffer.lock().unwrap().snapshot_to(name);
+        self.queue(buffer, snapshot);
+    }
+
+    /// Waits for every save queued so far to finish, leaving the writer
+    /// running.
+    pub fn settle(&self) {
+        if let Some(ref jobs) = self.jobs {
+            let (done, wait) = channel();
+            if jobs.send(Job::Settle(done)).is_ok() {
+                let _ = wait.recv();
+            }
+        }
+    }
+
+    /// Waits for every queued save to finish.
+    pub fn finish(&mut self) {
+        self.jobs.take();
+        // A thread which panicked has already said why, and its failed
+        // saves have been reported.
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
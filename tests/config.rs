@@ -0,0 +1,64 @@
+//! Runs the editor with a configuration file, checking what it does with
+//! the settings and key bindings in it.
+
+extern crate nep;
+extern crate termion;
+
+use std::fs;
+
+use nep::backend::Headless;
+use nep::buffer::Buffer;
+use nep::interface::Interface;
+use termion::event::Key;
+
+const CONFIG: &str = r#"
+[keys]
+";" = "command"
+"C-k" = "move_down"
+
+[keys.normal]
+";" = "command"
+
+[keys.insert]
+"C-k" = ""
+"#;
+
+/// Opens the editor on an empty buffer with `CONFIG` as its configuration,
+/// which every test here shares.
+fn open(backend: Headless) -> Interface<Headless> {
+    fs::create_dir_all("target/config/nep").unwrap();
+    fs::write("target/config/nep/config.toml", CONFIG).unwrap();
+    std::env::set_var("XDG_CONFIG_HOME", "target/config");
+    Interface::from_buffers(backend, vec![Buffer::new()])
+}
+
+#[test]
+fn later_messages_are_shown_after_configuration_errors() {
+    let interface = open(Headless::new(160, 3));
+    interface.add_message("nowhere: not found".to_string());
+    let backend = interface.start().unwrap();
+
+    assert!(backend.screen().contains(
+        "config.toml: keys.;: characters can only be bound in one mode, as in keys.normal; \
+         nowhere: not found"
+    ));
+}
+
+#[test]
+fn keys_are_bound_in_one_mode() {
+    let mut backend = Headless::new(40, 4);
+    backend.type_text("ia\nbc");
+    backend.press(Key::Esc);
+    backend.type_text("kA");
+    backend.press(Key::Ctrl('k'));
+    backend.type_text(";");
+    backend.press(Key::Esc);
+    backend.press(Key::Ctrl('k'));
+    backend.type_text("x;");
+    let backend = open(backend).start().unwrap();
+
+    assert_eq!(
+        backend.screen(),
+        " 1 a;\n 2 b\n~\n NORMAL [no name]* [1/1]  :         nep\n"
+    );
+}
@@ -0,0 +1,157 @@
+//! Opens and saves real files, checking what is kept of them and what
+//! happens when that goes wrong.
+
+extern crate libc;
+extern crate nep;
+
+use std::env;
+use std::fs;
+use std::os::unix::fs::{chown, MetadataExt, PermissionsExt};
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use nep::buffer::{Buffer, Error};
+
+/// Someone other than whoever runs the tests, for the tests which need
+/// root to give files away.
+const OTHER_USER: u32 = 1234;
+const OTHER_GROUP: u32 = 2345;
+
+/// A fresh, empty directory for the test `name`.
+fn directory(name: &str) -> PathBuf {
+    let directory = PathBuf::from("target/files").join(name);
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+fn root() -> bool {
+    unsafe { libc::geteuid() == 0 }
+}
+
+/// Opens the file at `path`, adds a line to the start and saves it.
+fn edit(path: &Path) -> Result<(), Error> {
+    let mut buffer = Buffer::from(path.to_str().unwrap().to_string())?;
+    buffer.insert_str(0, 0, "added\n");
+    buffer.snapshot().unwrap().write_back(|_| ())
+}
+
+#[test]
+fn directories_are_not_opened() {
+    let directory = directory("directory");
+    let name = directory.to_str().unwrap().to_string();
+    match Buffer::from(name.clone()) {
+        Err(Error::IsDirectory(ref shown)) if *shown == name => (),
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn invalid_utf8_gives_the_line_it_is_on() {
+    let path = directory("utf8").join("file");
+    fs::write(&path, b"one\ntwo\nthr\xffee\nfour\n").unwrap();
+    match Buffer::from(path.to_str().unwrap().to_string()) {
+        Err(Error::InvalidUtf8(_, 3)) => (),
+        other => panic!("{:?}", other.map(|_| ())),
+    }
+}
+
+#[test]
+fn saving_keeps_the_mode_and_owner() {
+    let path = directory("mode").join("file");
+    fs::write(&path, "text\n").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o640)).unwrap();
+    if root() {
+        chown(&path, Some(OTHER_USER), Some(OTHER_GROUP)).unwrap();
+    }
+    let before = fs::metadata(&path).unwrap();
+
+    edit(&path).unwrap();
+
+    let after = fs::metadata(&path).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "added\ntext\n");
+    assert_ne!(after.ino(), before.ino());
+    assert_eq!(after.mode() & 0o7777, 0o640);
+    assert_eq!((after.uid(), after.gid()), (before.uid(), before.gid()));
+}
+
+/// Saves `NEP_SAVE` when run by `files_owned_by_others_are_saved_as_the_user`
+/// as someone else.
+fn save_as_someone_else() -> bool {
+    match env::var_os("NEP_SAVE") {
+        Some(path) => {
+            edit(Path::new(&path)).unwrap();
+            true
+        }
+        None => false,
+    }
+}
+
+#[test]
+fn files_owned_by_others_are_saved_as_the_user() {
+    if save_as_someone_else() || !root() {
+        // Only root can make a file another user may save but not own.
+        return;
+    }
+
+    // The other user must be able to reach both the file and this test, so
+    // they go outside the build directory.
+    let directory = env::temp_dir().join(format!("nep-files-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&directory);
+    fs::create_dir_all(&directory).unwrap();
+    fs::set_permissions(&directory, fs::Permissions::from_mode(0o777)).unwrap();
+    let test = directory.join("test");
+    fs::copy(env::current_exe().unwrap(), &test).unwrap();
+    fs::set_permissions(&test, fs::Permissions::from_mode(0o755)).unwrap();
+
+    let path = directory.join("file");
+    fs::write(&path, "text\n").unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o664)).unwrap();
+    chown(&path, Some(0), Some(OTHER_GROUP)).unwrap();
+
+    let status = Command::new(&test)
+        .args(["--exact", "files_owned_by_others_are_saved_as_the_user"])
+        .env("NEP_SAVE", &path)
+        .current_dir(&directory)
+        .stdout(Stdio::null())
+        .uid(OTHER_USER)
+        .gid(OTHER_GROUP)
+        .status()
+        .unwrap();
+
+    // The file cannot be given back to root, but keeps its group.
+    let after = fs::metadata(&path).unwrap();
+    let contents = fs::read_to_string(&path).unwrap();
+    fs::remove_dir_all(&directory).unwrap();
+    assert!(status.success());
+    assert_eq!(contents, "added\ntext\n");
+    assert_eq!(after.mode() & 0o7777, 0o664);
+    assert_eq!((after.uid(), after.gid()), (OTHER_USER, OTHER_GROUP));
+}
+
+#[test]
+fn a_failed_rename_leaves_the_original_alone() {
+    // Nothing can be renamed over a directory, though a temporary file can
+    // be written beside it.
+    let directory = directory("rename");
+    let path = directory.join("file");
+    fs::create_dir(&path).unwrap();
+    fs::write(path.join("inside"), "kept\n").unwrap();
+
+    let mut buffer = Buffer::new();
+    buffer.insert_str(0, 0, "text");
+    let error = buffer
+        .snapshot_to(path.to_str().unwrap().to_string())
+        .write_back(|_| ())
+        .err()
+        .unwrap();
+
+    assert!(matches!(error, Error::Io(..)), "{:?}", error);
+    assert_eq!(fs::read_to_string(path.join("inside")).unwrap(), "kept\n");
+    let left = fs::read_dir(&directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name())
+        .collect::<Vec<_>>();
+    assert_eq!(left, vec!["file"]);
+}
@@ -0,0 +1,66 @@
+//! Checks what is written to a terminal to turn one frame into the next.
+
+extern crate nep;
+extern crate termion;
+
+use nep::backend::{Grid, Style};
+use termion::{cursor, style};
+
+fn diff(old: &Grid, new: &Grid) -> String {
+    let mut output = vec![];
+    new.diff(old, &mut output).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn changed_cells_are_written_in_a_run() {
+    let old = Grid::new(8, 2);
+    let mut new = old.clone();
+    new.put(1, 1, "ab", Style::PLAIN);
+
+    assert_eq!(
+        diff(&old, &new),
+        format!("{}{}ab", cursor::Goto(2, 2), style::Reset)
+    );
+}
+
+#[test]
+fn the_cursor_is_moved_after_characters_of_other_widths() {
+    let old = Grid::new(8, 1);
+    let mut new = old.clone();
+    new.put(0, 0, "a漢b", Style::PLAIN);
+    new.put(4, 0, "e\u{301}f", Style::PLAIN);
+
+    assert_eq!(
+        diff(&old, &new),
+        format!(
+            "{}{}a漢{}be\u{301}{}f",
+            cursor::Goto(1, 1),
+            style::Reset,
+            cursor::Goto(4, 1),
+            cursor::Goto(7, 1),
+        )
+    );
+}
+
+#[test]
+fn wide_characters_cover_the_next_cell() {
+    let mut old = Grid::new(4, 1);
+    old.put(0, 0, "漢字", Style::PLAIN);
+    let mut new = old.clone();
+    assert_eq!(new.put(0, 0, "ab字", Style::PLAIN), 4);
+    assert_eq!(
+        diff(&old, &new),
+        format!("{}{}ab", cursor::Goto(1, 1), style::Reset)
+    );
+
+    // Setting half of a wide character blanks the other half, and one cut
+    // off by the edge is left out.
+    let mut new = old.clone();
+    new.set(1, 0, 'x', Style::PLAIN);
+    new.set(3, 0, '漢', Style::PLAIN);
+    assert_eq!(
+        diff(&old, &new),
+        format!("{}{} x  ", cursor::Goto(1, 1), style::Reset)
+    );
+}
@@ -0,0 +1,127 @@
+//! Drives the editor through a script of keys on a screen kept in memory,
+//! then checks the file it leaves behind and compares what it shows with a
+//! snapshot kept in `tests/snapshots`.
+//!
+//! Run the tests with `UPDATE_SNAPSHOTS=1` to write every snapshot afresh
+//! from what is shown, then look over the difference before committing it.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use nep::backend::Headless;
+use nep::buffer::Buffer;
+use nep::interface::Interface;
+use termion::event::Key;
+
+const WIDTH: u16 = 60;
+const HEIGHT: u16 = 8;
+
+/// Reads a script of keys, written as they would be typed, with the keys
+/// that are not characters named in angle brackets: `<Esc>`, `<CR>`,
+/// `<Tab>`, `<BS>`, `<Del>`, `<Left>`, `<Right>`, `<Up>`, `<Down>`,
+/// `<Home>`, `<End>`, `<PageUp>`, `<PageDown>`, `<C-x>` for control, and
+/// `<M-x>` or `<A-x>` for alt. A `<` itself is written `<lt>`.
+pub fn keys(script: &str) -> Vec<Key> {
+    let mut keys = vec![];
+    let mut rest = script;
+    while let Some(c) = rest.chars().next() {
+        if c != '<' {
+            keys.push(Key::Char(c));
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+
+        let end = rest
+            .find('>')
+            .unwrap_or_else(|| panic!("unclosed < in {:?}; write <lt> for <", script));
+        let name = &rest[1..end];
+        rest = &rest[end + 1..];
+        keys.push(match name {
+            "Esc" => Key::Esc,
+            "CR" | "Enter" => Key::Char('\n'),
+            "Tab" => Key::Char('\t'),
+            "BS" => Key::Backspace,
+            "Del" => Key::Delete,
+            "Left" => Key::Left,
+            "Right" => Key::Right,
+            "Up" => Key::Up,
+            "Down" => Key::Down,
+            "Home" => Key::Home,
+            "End" => Key::End,
+            "PageUp" => Key::PageUp,
+            "PageDown" => Key::PageDown,
+            "lt" => Key::Char('<'),
+            _ => {
+                let mut chars = name.chars();
+                match (chars.next(), chars.next(), chars.next(), chars.next()) {
+                    (Some('C'), Some('-'), Some(c), None) => Key::Ctrl(c),
+                    (Some('M'), Some('-'), Some(c), None)
+                    | (Some('A'), Some('-'), Some(c), None) => Key::Alt(c),
+                    _ => panic!("no key <{}> in {:?}", name, script),
+                }
+            }
+        });
+    }
+    keys
+}
+
+/// Opens a file holding `start`, types `script` into the editor until the
+/// keys run out, and checks that the file then holds `expected` and that
+/// the screen matches the snapshot called `name`.
+pub fn check(name: &str, start: &str, script: &str, expected: &str) {
+    // A relative path keeps the status line the same wherever the crate is.
+    let dir = Path::new("target").join("keys");
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join(name);
+    fs::write(&path, start).unwrap();
+
+    let mut backend = Headless::new(WIDTH, HEIGHT);
+    for key in keys(script) {
+        backend.press(key);
+    }
+    env::set_var("XDG_CONFIG_HOME", "/nonexistent");
+    let buffer = Buffer::from(path.to_str().unwrap().to_string()).unwrap();
+    let backend = Interface::from_buffers(backend, vec![buffer])
+        .start()
+        .unwrap();
+
+    assert_eq!(
+        fs::read_to_string(&path).unwrap(),
+        expected,
+        "the file after {:?}",
+        script
+    );
+
+    let (x, y) = backend.cursor();
+    let shown = format!("{}cursor at column {}, row {}\n", backend.screen(), x, y);
+    snapshot(name, &shown);
+}
+
+/// Compares `shown` with the snapshot called `name`, or writes it as that
+/// snapshot if `UPDATE_SNAPSHOTS` is set.
+fn snapshot(name: &str, shown: &str) {
+    let path = Path::new("tests")
+        .join("snapshots")
+        .join(format!("{}.snap", name));
+    if env::var_os("UPDATE_SNAPSHOTS").is_some() {
+        fs::write(&path, shown).unwrap();
+        return;
+    }
+
+    match fs::read_to_string(&path) {
+        Ok(ref expected) if expected == shown => (),
+        Ok(expected) => panic!(
+            "the screen differs from {}; run with UPDATE_SNAPSHOTS=1 if it should\n\
+             expected:\n{}\nshown:\n{}",
+            path.display(),
+            expected,
+            shown
+        ),
+        Err(_) => panic!(
+            "there is no {}; run with UPDATE_SNAPSHOTS=1 to write it\nshown:\n{}",
+            path.display(),
+            shown
+        ),
+    }
+}
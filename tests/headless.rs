@@ -0,0 +1,162 @@
+//! Runs the editor on a screen kept in memory, checking what it shows.
+
+extern crate nep;
+extern crate termion;
+
+use std::io;
+
+use nep::backend::{Backend, Event, Grid, Headless};
+use nep::buffer::Buffer;
+use nep::interface::Interface;
+use termion::event::Key;
+
+/// Runs the editor on an empty buffer until `backend` runs out of events,
+/// away from any configuration.
+fn run(backend: Headless) -> Headless {
+    std::env::set_var("XDG_CONFIG_HOME", "/nonexistent");
+    Interface::from_buffers(backend, vec![Buffer::new()])
+        .start()
+        .unwrap()
+}
+
+#[test]
+fn typed_text_is_shown() {
+    let mut backend = Headless::new(40, 5);
+    backend.type_text("ihello\nworld");
+    backend.press(Key::Esc);
+    let backend = run(backend);
+
+    assert_eq!(
+        backend.screen(),
+        " 1 hello\n 2 world\n~\n~\n NORMAL [no name]* [1/1]            nep\n"
+    );
+    assert_eq!(backend.cursor(), (7, 1));
+}
+
+#[test]
+fn resizing_redraws_to_fit() {
+    let mut backend = Headless::new(40, 5);
+    backend.type_text("ia");
+    backend.resize(20, 3);
+    let backend = run(backend);
+
+    assert_eq!(backend.screen(), " 1 a\n~\n INSERT [no namenep\n");
+    assert_eq!(backend.cursor(), (4, 0));
+}
+
+#[test]
+fn the_cursor_follows_motions() {
+    let mut backend = Headless::new(40, 6);
+    backend.type_text("ione two\nthree");
+    backend.press(Key::Esc);
+    backend.type_text("k0w");
+    let backend = run(backend);
+
+    assert_eq!(backend.cursor(), (7, 0));
+}
+
+/// A screen which stops working after its first frame.
+struct Broken {
+    frames: usize,
+}
+
+impl Backend for Broken {
+    fn size(&mut self) -> io::Result<(u16, u16)> {
+        Ok((40, 5))
+    }
+
+    fn event(&mut self) -> Option<io::Result<Event>> {
+        Some(Ok(Event::Key(Key::Char('i'))))
+    }
+
+    fn present(&mut self, _: &Grid, _: (u16, u16)) -> io::Result<()> {
+        self.frames += 1;
+        if self.frames > 1 {
+            return Err(io::Error::other("gone"));
+        }
+        Ok(())
+    }
+}
+
+#[test]
+fn backend_errors_end_the_editor() {
+    std::env::set_var("XDG_CONFIG_HOME", "/nonexistent");
+    let error = Interface::from_buffers(Broken { frames: 0 }, vec![Buffer::new()])
+        .start()
+        .err()
+        .unwrap();
+    assert_eq!(error.to_string(), "gone");
+}
+
+#[test]
+fn splits_which_leave_no_room_are_refused() {
+    let mut backend = Headless::new(30, 1);
+    backend.press(Key::Ctrl('w'));
+    backend.press(Key::Char('s'));
+    backend.resize(2, 4);
+    backend.press(Key::Ctrl('w'));
+    backend.press(Key::Char('v'));
+    backend.resize(0, 0);
+    backend.press(Key::Ctrl('w'));
+    backend.press(Key::Char('s'));
+    backend.type_text("ia");
+    backend.resize(60, 4);
+    let backend = run(backend);
+
+    assert_eq!(
+        backend.screen(),
+        " 1 a\n~\n~\n INSERT [no name]* [1/1]  no room to split the window   nep\n"
+    );
+}
+
+#[test]
+fn windows_squeezed_to_nothing_are_not_drawn() {
+    let mut backend = Headless::new(40, 6);
+    backend.press(Key::Ctrl('w'));
+    backend.press(Key::Char('s'));
+    backend.press(Key::Ctrl('w'));
+    backend.press(Key::Char('v'));
+    backend.resize(1, 1);
+    backend.type_text("ia");
+    backend.resize(0, 0);
+    backend.type_text("b");
+    let backend = run(backend);
+
+    assert_eq!(backend.screen(), "");
+    assert_eq!(backend.cursor(), (0, 0));
+}
+
+#[test]
+fn search_matches_are_highlighted() {
+    let mut backend = Headless::new(40, 5);
+    backend.type_text("iab ab a");
+    backend.press(Key::Esc);
+    backend.type_text("/ab");
+    let backend = run(backend);
+
+    let highlighted = (0..40)
+        .filter(|&x| backend.frame().cell(x, 0).style.inverted)
+        .collect::<Vec<_>>();
+    assert_eq!(highlighted, vec![3, 4, 6, 7]);
+    assert_eq!(backend.cursor(), (3, 0));
+}
+
+#[test]
+fn saving_an_unnamed_buffer_asks_for_a_name() {
+    let path = "target/headless/unnamed";
+    std::fs::create_dir_all("target/headless").unwrap();
+    let _ = std::fs::remove_file(path);
+
+    let mut backend = Headless::new(60, 5);
+    backend.type_text("ihello");
+    backend.press(Key::Esc);
+    backend.press(Key::Ctrl('s'));
+    backend.type_text(path);
+    backend.press(Key::Char('\n'));
+    let backend = run(backend);
+
+    assert_eq!(std::fs::read_to_string(path).unwrap(), "hello\n");
+    assert!(backend
+        .screen()
+        .contains(" NORMAL target/headless/unnamed [1/1]"));
+}
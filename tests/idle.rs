@@ -0,0 +1,102 @@
+//! Measures how much processor time the editor uses while left alone in a
+//! terminal, which should be next to none: it only wakes for keys and for
+//! the terminal being resized.
+
+#![cfg(target_os = "linux")]
+
+extern crate libc;
+
+use std::ffi::CStr;
+use std::fs::{self, File};
+use std::io::{Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::process::{Command, Stdio};
+use std::thread;
+use std::time::Duration;
+
+/// Opens a pseudo-terminal of the given size, giving its two ends.
+fn pty(rows: u16, columns: u16) -> (File, File) {
+    unsafe {
+        let master = libc::posix_openpt(libc::O_RDWR | libc::O_NOCTTY);
+        assert!(master >= 0, "could not open a pseudo-terminal");
+        assert_eq!(libc::grantpt(master), 0);
+        assert_eq!(libc::unlockpt(master), 0);
+        let name = CStr::from_ptr(libc::ptsname(master)).to_owned();
+        let slave = libc::open(name.as_ptr(), libc::O_RDWR | libc::O_NOCTTY);
+        assert!(slave >= 0, "could not open {:?}", name);
+
+        let size = libc::winsize {
+            ws_row: rows,
+            ws_col: columns,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        assert_eq!(libc::ioctl(master, libc::TIOCSWINSZ, &size), 0);
+        (File::from_raw_fd(master), File::from_raw_fd(slave))
+    }
+}
+
+/// The processor time used by `pid` so far, in clock ticks.
+fn ticks(pid: u32) -> u64 {
+    let stat = fs::read_to_string(format!("/proc/{}/stat", pid)).unwrap();
+    // The name, in brackets, may hold spaces, so count from after it.
+    let fields: Vec<&str> = stat[stat.rfind(')').unwrap() + 2..].split(' ').collect();
+    let user: u64 = fields[11].parse().unwrap();
+    let system: u64 = fields[12].parse().unwrap();
+    user + system
+}
+
+#[test]
+fn idle_editor_uses_no_processor_time() {
+    let (mut master, slave) = pty(24, 80);
+    let mut nep = Command::new(env!("CARGO_BIN_EXE_nep"))
+        .arg("Cargo.toml")
+        .env("XDG_CONFIG_HOME", "/nonexistent")
+        .stdin(Stdio::from(slave.try_clone().unwrap()))
+        .stdout(Stdio::from(slave))
+        .stderr(Stdio::null())
+        .spawn()
+        .unwrap();
+
+    // Keep reading the screen, so drawing never waits on a full terminal.
+    let mut screen = master.try_clone().unwrap();
+    thread::spawn(move || {
+        let mut bytes = [0; 4096];
+        while let Ok(count) = screen.read(&mut bytes) {
+            if count == 0 {
+                break;
+            }
+        }
+    });
+
+    thread::sleep(Duration::from_millis(500));
+    let before = ticks(nep.id());
+    thread::sleep(Duration::from_secs(2));
+    let idle = ticks(nep.id()) - before;
+
+    // Resizing wakes the editor to redraw, and it should then settle again.
+    unsafe {
+        libc::kill(nep.id() as libc::pid_t, libc::SIGWINCH);
+    }
+    thread::sleep(Duration::from_millis(300));
+    let before = ticks(nep.id());
+    thread::sleep(Duration::from_secs(1));
+    let after_resize = ticks(nep.id()) - before;
+
+    master.write_all(b"\x11").unwrap();
+    let status = nep.wait().unwrap();
+    assert!(status.success());
+
+    let per_second = unsafe { libc::sysconf(libc::_SC_CLK_TCK) } as u64;
+    println!(
+        "idle for 2s: {} ticks, for 1s after resizing: {} ticks, of {} a second",
+        idle, after_resize, per_second
+    );
+    // A busy loop would use about `per_second` ticks every second.
+    assert!(idle * 20 <= per_second, "used {} ticks idle", idle);
+    assert!(
+        after_resize * 20 <= per_second,
+        "used {} ticks after resizing",
+        after_resize
+    );
+}
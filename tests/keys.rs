@@ -0,0 +1,512 @@
+//! Scripts of keys typed into the editor, each checked for the file it
+//! leaves behind and the screen it ends on.
+
+extern crate nep;
+extern crate termion;
+
+mod harness;
+
+use std::fs;
+
+use harness::check;
+
+#[test]
+fn insert_and_save() {
+    check(
+        "insert_and_save",
+        "",
+        "ihello<CR>world<Esc><C-s>",
+        "hello\nworld\n",
+    );
+}
+
+#[test]
+fn unsaved_edits_leave_the_file_alone() {
+    check("unsaved", "one\n", "Atwo<Esc>", "one\n");
+}
+
+#[test]
+fn delete_and_change_words() {
+    check(
+        "words",
+        "one two three\nfour five\n",
+        "dwwcwTHREE<Esc>j0x<C-s>",
+        "two THREE\nour five\n",
+    );
+}
+
+#[test]
+fn delete_and_put_lines() {
+    check("lines", "a\nb\nc\n", "ddpggyyGP<C-s>", "b\na\nb\nc\n");
+}
+
+#[test]
+fn undo_and_redo() {
+    check(
+        "undo",
+        "one\n",
+        "otwo<Esc>othree<Esc>uu<C-r><C-s>",
+        "one\ntwo\n",
+    );
+}
+
+#[test]
+fn delete_a_selection() {
+    check("visual", "abcdef\nghi\n", "lvlld<C-s>", "aef\nghi\n");
+}
+
+#[test]
+fn type_with_several_cursors() {
+    check(
+        "cursors",
+        "a\nb\nc\n",
+        "<M-j><M-j>I- <Esc><Esc><C-s>",
+        "- a\n- b\n- c\n",
+    );
+}
+
+#[test]
+fn huge_cursor_counts_stop_at_the_first_line() {
+    check(
+        "cursors_huge",
+        "a\nb\nc\nd\n",
+        "jj99999<M-k>I- <Esc><Esc><C-s>",
+        "- a\n- b\n- c\nd\n",
+    );
+}
+
+#[test]
+fn typing_at_several_cursors_is_undone_at_once() {
+    check(
+        "cursors_undo",
+        "a\nb\n",
+        "<M-j>Ihello<BS><BS><Esc>uu<C-s>",
+        "a\nb\n",
+    );
+}
+
+#[test]
+fn open_lines_above_and_below() {
+    check(
+        "open",
+        "middle\n",
+        "Otop<Esc>jobottom<BS><BS><Esc><C-s>",
+        "top\nmiddle\nbott\n",
+    );
+}
+
+#[test]
+fn long_lines_wrap() {
+    check(
+        "wrap",
+        "",
+        "i0123456789012345678901234567890123456789012345678901234567890123456789<CR>end<Esc>kk$<C-s>",
+        "0123456789012345678901234567890123456789012345678901234567890123456789\nend\n",
+    );
+}
+
+#[test]
+fn split_windows() {
+    check(
+        "split",
+        "shared\n",
+        "<C-w>sAline<Esc><C-w>w<C-s>",
+        "sharedline\n",
+    );
+}
+
+#[test]
+fn the_lt_key_is_typed() {
+    check("lt", "", "i<lt>tag><Esc><C-s>", "<tag>\n");
+}
+
+#[test]
+fn ctrl_q_will_not_quit_with_unsaved_changes() {
+    check("ctrl_q", "a\n", "x<C-q>ib<Esc>", "a\n");
+}
+
+#[test]
+fn huge_counts_stop_at_the_end_of_the_buffer() {
+    check(
+        "huge_counts",
+        "a\nb\nc\nd\n",
+        "j99999999999999999999999ddk99999999999999999999jx<C-s>",
+        "\n",
+    );
+}
+
+#[test]
+fn huge_pastes_are_refused() {
+    let line = format!("{}\n", "x".repeat(1000));
+    check("huge_paste", &line, "yy99999p", &line);
+}
+
+#[test]
+fn wide_characters_take_two_columns() {
+    check("wide", "漢字ab\n", "$hx<C-s>", "漢字b\n");
+}
+
+#[test]
+fn search_then_change_the_match() {
+    check(
+        "search",
+        "one two\nthree two\n",
+        "/two<CR>ncwfour<Esc><C-s>",
+        "one two\nthree four\n",
+    );
+}
+
+#[test]
+fn search_backward_wraps_round() {
+    check(
+        "search_backward",
+        "a x\nb x\nc\n",
+        "j?x<CR>nx<C-s>",
+        "a x\nb \nc\n",
+    );
+}
+
+#[test]
+fn cancelled_search_goes_back() {
+    check(
+        "search_cancelled",
+        "first\nsecond\n",
+        "l/sec<Esc>x<C-s>",
+        "frst\nsecond\n",
+    );
+}
+
+#[test]
+fn capitals_make_searches_match_case() {
+    check(
+        "search_case",
+        "Word word\n",
+        "/word<CR>x/Word<CR>x<C-s>",
+        "ord ord\n",
+    );
+}
+
+#[test]
+fn substitute_swaps_groups() {
+    check(
+        "substitute",
+        "ab ab\nab\n",
+        "&/(a)(b)/$2$1/g<CR><C-s>",
+        "ba ba\nba\n",
+    );
+}
+
+#[test]
+fn substitute_without_g_replaces_the_first_on_each_line() {
+    check(
+        "substitute_first",
+        "a a\na a\n",
+        "&/a/x/<CR><C-s>",
+        "x a\nx a\n",
+    );
+}
+
+#[test]
+fn substitute_matches_across_lines() {
+    check(
+        "substitute_lines",
+        "one,\ntwo,\nthree\n",
+        "&/,\\n/, /g<CR><C-s>",
+        "one, two, three\n",
+    );
+}
+
+#[test]
+fn substitute_asks_about_each() {
+    check(
+        "substitute_confirm",
+        "x x x x\n",
+        "&/x/y/gc<CR>ynyq<C-s>",
+        "y x y x\n",
+    );
+}
+
+#[test]
+fn substitute_is_undone_at_once() {
+    check(
+        "substitute_undo",
+        "a\na\na\n",
+        "&/a/b/<CR>u<C-s>",
+        "a\na\na\n",
+    );
+}
+
+#[test]
+fn substitute_over_a_selection() {
+    check(
+        "substitute_visual",
+        "a\na\na\na\n",
+        "jvj&/a/b/<CR><C-s>",
+        "a\nb\nb\na\n",
+    );
+}
+
+#[test]
+fn empty_substitute_repeats_the_last() {
+    check(
+        "substitute_repeat",
+        "a a a\n",
+        "&/a/b/<CR>&<CR><C-s>",
+        "b b a\n",
+    );
+}
+
+#[test]
+fn substitute_says_how_many_it_replaced() {
+    check("substitute_count", "a a\na\n", "&/a/b/g<CR>", "a a\na\n");
+}
+
+#[test]
+fn substitute_shows_what_it_asks_about() {
+    check("substitute_asking", "x x\n", "&/x/<lt>&>/gc<CR>y", "x x\n");
+}
+
+#[test]
+fn command_line_writes() {
+    check("command_write", "a\n", "xihello<Esc>:w<CR>", "hello\n");
+}
+
+#[test]
+fn command_line_goes_to_a_line() {
+    check("command_line", "a\nb\nc\n", ":3<CR>x:w<CR>", "a\nb\n\n");
+}
+
+#[test]
+fn command_line_substitutes() {
+    check(
+        "command_substitute",
+        "a a\na a\n",
+        "j:s/a/b/g<CR>:%s/a/c/<CR>:w<CR>",
+        "c a\nb b\n",
+    );
+}
+
+#[test]
+fn command_line_will_not_quit_with_unsaved_changes() {
+    check("command_quit", "a\n", "x:q<CR>", "a\n");
+}
+
+#[test]
+fn command_line_quits_anyway() {
+    check("command_quit_anyway", "a\n", "x:q!<CR>ib", "a\n");
+}
+
+#[test]
+fn command_line_changes_settings() {
+    check(
+        "command_set",
+        &format!("{}\n", "x".repeat(70)),
+        ":set nowrap<CR>:set wrap?<CR>",
+        &format!("{}\n", "x".repeat(70)),
+    );
+}
+
+#[test]
+fn command_line_is_edited() {
+    check(
+        "command_edited",
+        "a\n",
+        ":sett nowrap<Home><Right><Right><Right><Del><CR>:set wrap?<CR>",
+        "a\n",
+    );
+}
+
+#[test]
+fn command_line_recalls_history() {
+    check(
+        "command_history",
+        "a\nb\nc\nd\n",
+        ":2<CR>:4<CR>gg:<Up><Up><CR>x:w<CR>",
+        "a\n\nc\nd\n",
+    );
+}
+
+#[test]
+fn command_line_completes_names() {
+    check(
+        "command_complete",
+        "a\n",
+        ":subs<Tab>/a/b/<CR>:w<CR>",
+        "b\n",
+    );
+}
+
+#[test]
+fn command_line_runs_actions() {
+    check(
+        "command_action",
+        "a\nb\n",
+        ":move_down<CR>x:frob<CR>",
+        "a\nb\n",
+    );
+}
+
+#[test]
+fn command_line_shows_its_cursor() {
+    check("command_typing", "a\n", ":set wr<Left>", "a\n");
+}
+
+#[test]
+fn command_line_runs_actions_given_arguments() {
+    check(
+        "command_action_arguments",
+        "a\na\n",
+        ":substitute_every_line /a/c/<CR>:substitute_line /c/d/<CR><C-s>",
+        "d\nc\n",
+    );
+}
+
+#[test]
+fn next_buffer_counts_wrap_around_the_list() {
+    check(
+        "next_buffer_wrap",
+        "a\n",
+        ":e target/keys/buffer_b<CR>:e target/keys/buffer_c<CR>2]b",
+        "a\n",
+    );
+}
+
+#[test]
+fn previous_buffer_counts_wrap_around_the_list() {
+    check(
+        "previous_buffer_wrap",
+        "a\n",
+        ":e target/keys/buffer_b<CR>:e target/keys/buffer_c<CR>5[b",
+        "a\n",
+    );
+}
+
+#[test]
+fn closing_a_changed_buffer_can_be_refused() {
+    check("close_refused", "one\n", "x:close<CR>n", "one\n");
+}
+
+#[test]
+fn closing_a_changed_buffer_can_be_confirmed() {
+    check("close_confirmed", "one\n", "x:close<CR>y", "one\n");
+}
+
+#[test]
+fn save_as_asks_before_overwriting() {
+    let other = "target/keys/save_as_other";
+    fs::write(other, "other\n").unwrap();
+    check(
+        "save_as",
+        "a\n",
+        &format!("xib<Esc>:saveas {0}<CR>n<C-s>:saveas {0}<CR>y", other),
+        "b\n",
+    );
+    assert_eq!(fs::read_to_string(other).unwrap(), "b\n");
+}
+
+#[test]
+fn write_a_copy() {
+    let copy = "target/keys/write_copy_other";
+    fs::write(copy, "other\n").unwrap();
+    check(
+        "write_copy",
+        "a\n",
+        &format!("xib<Esc>:w {}<CR>y", copy),
+        "a\n",
+    );
+    assert_eq!(fs::read_to_string(copy).unwrap(), "b\n");
+}
+
+#[test]
+fn save_as_makes_directories_when_asked() {
+    let directory = "target/keys/made";
+    let _ = fs::remove_dir_all(directory);
+    check(
+        "save_as_directory",
+        "a\n",
+        &format!(
+            ":saveas {0}/new/file<CR>n:saveas {0}/new/file<CR>y",
+            directory
+        ),
+        "a\n",
+    );
+    assert_eq!(
+        fs::read_to_string(format!("{}/new/file", directory)).unwrap(),
+        "a\n"
+    );
+}
+
+#[test]
+fn crlf_files_keep_their_line_endings() {
+    check(
+        "crlf",
+        "\u{feff}a\r\nb\r\n",
+        "xic<Esc><C-s>",
+        "\u{feff}c\r\nb\r\n",
+    );
+}
+
+#[test]
+fn files_without_a_final_newline_keep_it_that_way() {
+    check("no_final_newline", "a\nb", "xic<Esc><C-s>", "c\nb");
+}
+
+#[test]
+fn mixed_line_endings_show_the_odd_carriage_return() {
+    check(
+        "mixed_line_endings",
+        "a\r\nb\n",
+        ":line_endings<CR>",
+        "a\r\nb\n",
+    );
+}
+
+#[test]
+fn convert_line_endings() {
+    check(
+        "convert_line_endings",
+        "a\nb\n",
+        ":line_endings crlf<CR><C-s>",
+        "a\r\nb\r\n",
+    );
+}
+
+#[test]
+fn a_lone_newline_is_kept() {
+    check("lone_newline", "\n", "<C-s>", "\n");
+}
+
+#[test]
+fn an_empty_file_stays_empty() {
+    check("empty_file", "", "<C-s>", "");
+}
+
+#[test]
+fn converting_mixed_line_endings_to_crlf() {
+    check(
+        "convert_mixed_to_crlf",
+        "a\r\nb\n",
+        ":line_endings crlf<CR><C-s>",
+        "a\r\nb\r\n",
+    );
+}
+
+#[test]
+fn converting_mixed_line_endings_to_lf() {
+    check(
+        "convert_mixed_to_lf",
+        "a\r\nb\n",
+        ":line_endings lf<CR><C-s>",
+        "a\nb\n",
+    );
+}
+
+#[test]
+fn converting_line_endings_is_undone_at_once() {
+    check(
+        "convert_undo",
+        "a\r\nb\r\nc\n",
+        ":line_endings lf<CR>u<C-s>",
+        "a\r\nb\r\nc\n",
+    );
+}
@@ -0,0 +1,521 @@
+//! A model of a buffer as a plain `String`, with a cursor kept in it, to
+//! run sequences of edits and motions against alongside the real `Buffer`
+//! and `Cursor`, checking after each step that the two agree.
+//!
+//! Shared by the property tests and the fuzz target, which reads its
+//! operations from raw bytes with `decode`.
+
+use std::cmp::min;
+use std::sync::{Arc, Mutex};
+
+use nep::buffer::Buffer;
+use nep::cursor::Cursor;
+use nep::mode::Motion;
+
+/// Characters to type, chosen to mix single and multibyte characters and
+/// the classes word motions tell apart.
+pub const CHARACTERS: &[char] = &['a', 'b', '_', ' ', '\t', '.', 'é', '日'];
+
+#[derive(Clone, Debug)]
+pub enum Op {
+    Insert((usize, usize), char),
+    Delete((usize, usize)),
+    Split((usize, usize)),
+    Merge(usize),
+    InsertStr((usize, usize), String),
+    DeleteRange((usize, usize), (usize, usize)),
+    Undo,
+    Redo,
+    Checkpoint,
+    /// Opens a transaction.
+    Begin,
+    /// Closes the innermost transaction, if one is open.
+    Commit,
+    /// Closes the innermost transaction, if one is open, taking back its
+    /// edits.
+    Abort,
+    Goto((usize, usize)),
+    Move(Motion),
+    PastEnd(bool),
+    NextMatch,
+}
+
+impl Op {
+    /// Makes this operation on `buffer` if it is an edit, ignoring what it
+    /// gives back. Transactions are left to `check`, which knows whether
+    /// one is open.
+    pub fn edit(&self, buffer: &mut Buffer) {
+        match *self {
+            Op::Insert((line, column), c) => buffer.insert(line, column, c),
+            Op::Delete((line, column)) => buffer.delete(line, column),
+            Op::Split((line, column)) => buffer.split_line(line, column),
+            Op::Merge(line) => drop(buffer.merge_line(line)),
+            Op::InsertStr((line, column), ref text) => drop(buffer.insert_str(line, column, text)),
+            Op::DeleteRange(start, end) => drop(buffer.delete_range(start, end)),
+            Op::Undo => drop(buffer.undo()),
+            Op::Redo => drop(buffer.redo()),
+            Op::Checkpoint => buffer.checkpoint(),
+            _ => (),
+        }
+    }
+}
+
+/// The text as one string with a newline between lines, and the undo and
+/// redo stacks as whole texts.
+struct Model {
+    text: String,
+    undo: Vec<String>,
+    redo: Vec<String>,
+    /// The run of typing or deleting the last undo step holds, while one
+    /// more edit of the same kind could still join it, with the offset it
+    /// left off at.
+    run: Option<(Run, usize)>,
+    /// For each open transaction, outermost first, the text when it began
+    /// and whether anything had been edited in the ones around it.
+    transactions: Vec<(String, bool)>,
+    /// Whether anything has been edited since the outermost transaction
+    /// began.
+    edited: bool,
+}
+
+/// The kinds of edit which are undone a run at a time rather than one by
+/// one: typing, where each edit adds text just after the last, and
+/// deleting, where each removes text just before it.
+#[derive(Clone, Copy, PartialEq)]
+enum Run {
+    Typing,
+    Deleting,
+}
+
+impl Model {
+    fn lines(&self) -> Vec<&str> {
+        self.text.split('\n').collect()
+    }
+
+    fn len(&self, line: usize) -> usize {
+        self.lines()[line].chars().count()
+    }
+
+    /// The nearest position in the text, as `Buffer` takes positions past
+    /// the end to mean the end.
+    fn clamp(&self, (line, column): (usize, usize)) -> (usize, usize) {
+        let line = min(line, self.lines().len() - 1);
+        (line, min(column, self.len(line)))
+    }
+
+    /// The byte offset of a position in the text.
+    fn offset(&self, position: (usize, usize)) -> usize {
+        let (line, column) = self.clamp(position);
+        let lines = self.lines();
+        let start = lines[..line]
+            .iter()
+            .map(|line| line.len() + 1)
+            .sum::<usize>();
+        start
+            + lines[line]
+                .char_indices()
+                .nth(column)
+                .map_or(lines[line].len(), |(offset, _)| offset)
+    }
+
+    /// The position of a byte offset in the text.
+    fn position(&self, offset: usize) -> (usize, usize) {
+        let before = &self.text[..offset];
+        let line = before.matches('\n').count();
+        let start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        (line, before[start..].chars().count())
+    }
+
+    /// Replaces the text between two offsets, if that changes anything.
+    /// An edit of a kind which runs, as `run` says, joins the last undo step
+    /// if that holds a run of the same kind which it carries on from; any
+    /// other edit makes a new step. Inside a transaction, edits wait for it
+    /// to commit.
+    fn replace(&mut self, start: usize, end: usize, with: &str, run: Option<Run>) {
+        let mut text = self.text.clone();
+        text.replace_range(start..end, with);
+        if text == self.text {
+            return;
+        }
+        if !self.transactions.is_empty() {
+            self.text = text;
+            self.edited = true;
+            return;
+        }
+
+        let continues = match (self.run, run) {
+            (Some((Run::Typing, at)), Some(Run::Typing)) => start == at,
+            (Some((Run::Deleting, at)), Some(Run::Deleting)) => end == at,
+            _ => false,
+        };
+        let before = std::mem::replace(&mut self.text, text);
+        if !continues {
+            self.undo.push(before);
+        }
+        self.redo.clear();
+        self.run = run.map(|run| match run {
+            Run::Typing => (run, start + with.len()),
+            Run::Deleting => (run, start),
+        });
+    }
+
+    fn begin(&mut self) {
+        self.transactions.push((self.text.clone(), self.edited));
+        if self.transactions.len() == 1 {
+            self.edited = false;
+        }
+    }
+
+    /// Closes the innermost transaction. Closing the outermost makes what
+    /// was edited in it a single undo step, if anything was.
+    fn commit(&mut self) {
+        let (before, _) = self.transactions.pop().unwrap();
+        if self.transactions.is_empty() && self.edited {
+            self.undo.push(before);
+            self.redo.clear();
+            self.run = None;
+        }
+    }
+
+    /// Closes the innermost transaction, putting the text back as it was
+    /// when it began.
+    fn abort(&mut self) {
+        let (before, edited) = self.transactions.pop().unwrap();
+        self.text = before;
+        self.edited = edited;
+    }
+}
+
+/// The model's cursor: where it was put, which is only brought within the
+/// text when read, as with `Cursor`.
+struct Place {
+    line: usize,
+    column: usize,
+    past_end: bool,
+}
+
+impl Place {
+    fn read(&self, model: &Model) -> (usize, usize) {
+        let line = min(self.line, model.lines().len() - 1);
+        (line, min(self.column, self.last(model, line)))
+    }
+
+    /// The furthest column the cursor may be at on `line`.
+    fn last(&self, model: &Model, line: usize) -> usize {
+        let len = model.len(line);
+        if self.past_end {
+            len
+        } else {
+            len.saturating_sub(1)
+        }
+    }
+
+    /// Makes a motion.
+    fn apply(&mut self, model: &Model, motion: Motion) {
+        let (line, column) = self.read(model);
+        let first_non_blank = |line: usize| {
+            let text = model.lines()[line];
+            text.chars()
+                .position(|c| !c.is_whitespace())
+                .unwrap_or_else(|| text.chars().count())
+        };
+        match motion {
+            Motion::Left => self.column = column.saturating_sub(1),
+            Motion::Right => self.column = min(column + 1, self.last(model, line)),
+            Motion::Up => self.line = line.saturating_sub(1),
+            Motion::Down => self.line = min(line + 1, model.lines().len() - 1),
+            Motion::LineStart => {
+                *self = Place {
+                    column: 0,
+                    line,
+                    ..*self
+                }
+            }
+            Motion::LineEnd => self.column = usize::MAX,
+            Motion::FirstNonBlank => {
+                self.line = line;
+                self.column = min(first_non_blank(line), self.last(model, line));
+            }
+            Motion::Line(number) => {
+                let last = model.lines().len() - 1;
+                self.line = min(number.map_or(last, |number| number.max(1) - 1), last);
+                self.column = min(first_non_blank(self.line), self.last(model, self.line));
+            }
+            Motion::WordForward | Motion::WordBackward | Motion::WordEnd => {
+                let slots = slots(model);
+                let at = slots
+                    .iter()
+                    .position(|&(position, _)| position == (line, column))
+                    .unwrap();
+                let last = slots.len() - 1;
+                let class = |i: usize| class(slots[i].1);
+                // A word starts at a character with none of its class
+                // before it, and ends at one with none after it. The end
+                // of a line is never part of a word, so neither needs to
+                // look past the ends of the text.
+                let starts = |i: usize| class(i) != 0 && (i == 0 || class(i - 1) != class(i));
+                let ends = |i: usize| class(i) != 0 && class(i + 1) != class(i);
+                let blank = |i: usize| model.len(slots[i].0 .0) == 0;
+
+                let target = match motion {
+                    Motion::WordForward => {
+                        (at + 1..=last).find(|&i| starts(i) || blank(i) || i == last)
+                    }
+                    Motion::WordBackward => {
+                        (0..at).rev().find(|&i| starts(i) || blank(i) || i == 0)
+                    }
+                    _ => (at + 1..=last).find(|&i| ends(i) || i == last),
+                };
+                let (line, column) = slots[target.unwrap_or(at)].0;
+                self.line = line;
+                self.column = min(column, self.last(model, line));
+            }
+        }
+    }
+}
+
+/// Every place a word motion can stop, in order: each character of each
+/// line and the end of each line, with the character there if there is one.
+fn slots(model: &Model) -> Vec<((usize, usize), Option<char>)> {
+    let mut slots = vec![];
+    for (line, text) in model.lines().into_iter().enumerate() {
+        for (column, c) in text.chars().enumerate() {
+            slots.push(((line, column), Some(c)));
+        }
+        slots.push(((line, text.chars().count()), None));
+    }
+    slots
+}
+
+/// What word motions tell characters apart by: 0 for blanks and the ends
+/// of lines, 1 for word characters and 2 for anything else.
+fn class(c: Option<char>) -> u8 {
+    match c {
+        Some(c) if c.is_alphanumeric() || c == '_' => 1,
+        Some(c) if !c.is_whitespace() => 2,
+        _ => 0,
+    }
+}
+
+fn text(buffer: &Buffer) -> String {
+    (0..buffer.len())
+        .map(|line| buffer.borrow_line(line).as_str())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Runs `ops` on a new buffer with a cursor in it, and on the model,
+/// checking after each that they agree and that the cursor is in the
+/// buffer.
+pub fn check(ops: &[Op]) {
+    let shared = Arc::new(Mutex::new(Buffer::new()));
+    let mut cursor = Cursor::new(&shared);
+    let mut model = Model {
+        text: String::new(),
+        undo: vec![],
+        redo: vec![],
+        run: None,
+        transactions: vec![],
+        edited: false,
+    };
+    let mut place = Place {
+        line: 0,
+        column: 0,
+        past_end: false,
+    };
+
+    for op in ops {
+        let mut buffer = shared.lock().unwrap();
+        match *op {
+            Op::Insert(position, c) => {
+                let offset = model.offset(position);
+                model.replace(offset, offset, &c.to_string(), Some(Run::Typing));
+                op.edit(&mut buffer);
+            }
+            Op::Delete(position) => {
+                let (line, column) = model.clamp(position);
+                if column < model.len(line) {
+                    let offset = model.offset((line, column));
+                    let width = model.text[offset..].chars().next().unwrap().len_utf8();
+                    model.replace(offset, offset + width, "", Some(Run::Deleting));
+                }
+                op.edit(&mut buffer);
+            }
+            Op::Split(position) => {
+                let offset = model.offset(position);
+                model.replace(offset, offset, "\n", Some(Run::Typing));
+                op.edit(&mut buffer);
+            }
+            Op::Merge(line) => {
+                let expected = if line == 0 || line >= model.lines().len() {
+                    None
+                } else {
+                    let offset = model.offset((line, 0));
+                    let column = model.len(line - 1);
+                    model.replace(offset - 1, offset, "", Some(Run::Deleting));
+                    Some(column)
+                };
+                assert_eq!(buffer.merge_line(line), expected, "{:?}", op);
+            }
+            Op::InsertStr((line, column), ref text) => {
+                let offset = model.offset((line, column));
+                model.replace(offset, offset, text, None);
+                let after = model.position(offset + text.len());
+                assert_eq!(buffer.insert_str(line, column, text), after, "{:?}", op);
+            }
+            Op::DeleteRange(start, end) => {
+                let (from, to) = (model.offset(start), model.offset(end));
+                let (from, to) = (min(from, to), from.max(to));
+                let removed = model.text[from..to].to_string();
+                model.replace(from, to, "", None);
+                assert_eq!(buffer.delete_range(start, end), removed, "{:?}", op);
+            }
+            // Nothing is undone or redone while a transaction is open.
+            Op::Undo if !model.transactions.is_empty() => {
+                assert_eq!(buffer.undo(), None, "{:?}", op);
+            }
+            Op::Redo if !model.transactions.is_empty() => {
+                assert_eq!(buffer.redo(), None, "{:?}", op);
+            }
+            Op::Undo => {
+                model.run = None;
+                let expected = model.undo.pop();
+                let to = buffer.undo();
+                assert_eq!(to.is_some(), expected.is_some(), "{:?}", op);
+                if let Some(text) = expected {
+                    model.redo.push(std::mem::replace(&mut model.text, text));
+                    let (line, column) = to.unwrap();
+                    assert!(
+                        model.clamp((line, column)) == (line, column),
+                        "undo to {:?}",
+                        to
+                    );
+                }
+            }
+            Op::Redo => {
+                model.run = None;
+                let expected = model.redo.pop();
+                let to = buffer.redo();
+                assert_eq!(to.is_some(), expected.is_some(), "{:?}", op);
+                if let Some(text) = expected {
+                    model.undo.push(std::mem::replace(&mut model.text, text));
+                    let (line, column) = to.unwrap();
+                    assert!(
+                        model.clamp((line, column)) == (line, column),
+                        "redo to {:?}",
+                        to
+                    );
+                }
+            }
+            Op::Checkpoint => {
+                model.run = None;
+                buffer.checkpoint();
+            }
+            Op::Begin => {
+                model.begin();
+                buffer.begin();
+            }
+            Op::Commit if !model.transactions.is_empty() => {
+                model.commit();
+                buffer.commit();
+            }
+            Op::Abort if !model.transactions.is_empty() => {
+                model.abort();
+                buffer.abort();
+            }
+            _ => (),
+        }
+
+        assert_eq!(text(&buffer), model.text, "after {:?}", op);
+        for line in 0..buffer.len() {
+            let line = buffer.borrow_line(line);
+            assert_eq!(line.len(), line.chars().count(), "after {:?}", op);
+        }
+        drop(buffer);
+
+        match *op {
+            Op::Goto((line, column)) => {
+                cursor.goto(line, column);
+                place.line = line;
+                place.column = column;
+            }
+            Op::PastEnd(past_end) => {
+                cursor.set_past_end(past_end);
+                place.past_end = past_end;
+            }
+            Op::Move(motion) => {
+                cursor.apply(motion);
+                place.apply(&model, motion);
+            }
+            Op::NextMatch => {
+                if let Some(found) = cursor.next_match() {
+                    let (line, column) = found;
+                    assert!(line < model.lines().len() && column < model.len(line));
+                }
+            }
+            _ => (),
+        }
+        assert_eq!(
+            (cursor.line(), cursor.column()),
+            place.read(&model),
+            "after {:?}",
+            op
+        );
+    }
+}
+
+/// Reads operations from raw bytes, as a fuzzer gives them: one byte picks
+/// each operation, and those after it give its arguments.
+pub fn decode(bytes: &[u8]) -> Vec<Op> {
+    let mut bytes = bytes.iter().map(|&byte| byte as usize);
+    let mut ops = vec![];
+    while let Some(kind) = bytes.next() {
+        let mut next = || bytes.next().unwrap_or(0);
+        let op = match kind % 16 {
+            0 => Op::Insert(
+                (next() % 8, next() % 12),
+                CHARACTERS[next() % CHARACTERS.len()],
+            ),
+            1 => Op::Delete((next() % 8, next() % 12)),
+            2 => Op::Split((next() % 8, next() % 12)),
+            3 => Op::Merge(next() % 8),
+            4 => {
+                let position = (next() % 8, next() % 12);
+                let length = next() % 8;
+                let text = (0..length)
+                    .map(|_| match next() % (CHARACTERS.len() + 1) {
+                        0 => '\n',
+                        i => CHARACTERS[i - 1],
+                    })
+                    .collect();
+                Op::InsertStr(position, text)
+            }
+            5 => Op::DeleteRange((next() % 8, next() % 12), (next() % 8, next() % 12)),
+            6 => Op::Undo,
+            7 => Op::Redo,
+            8 => Op::Checkpoint,
+            9 => Op::Goto((next() % 10, next() % 14)),
+            10 => Op::Move(match next() % 12 {
+                0 => Motion::Left,
+                1 => Motion::Right,
+                2 => Motion::Up,
+                3 => Motion::Down,
+                4 => Motion::WordForward,
+                5 => Motion::WordBackward,
+                6 => Motion::WordEnd,
+                7 => Motion::LineStart,
+                8 => Motion::FirstNonBlank,
+                9 => Motion::LineEnd,
+                10 => Motion::Line(None),
+                _ => Motion::Line(Some(next() % 10)),
+            }),
+            11 => Op::PastEnd(next() % 2 == 0),
+            12 => Op::Begin,
+            13 => Op::Commit,
+            14 => Op::Abort,
+            _ => Op::NextMatch,
+        };
+        ops.push(op);
+    }
+    ops
+}
@@ -0,0 +1,148 @@
+//! Runs the editor with small shell scripts as plugins, checking how it
+//! talks to them and what it does when they misbehave.
+
+extern crate nep;
+extern crate termion;
+
+use std::fs;
+use std::os::unix::fs::PermissionsExt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use nep::backend::Headless;
+use nep::buffer::Buffer;
+use nep::interface::Interface;
+use termion::event::Key;
+
+/// Held while the editor runs, as each test points the configuration
+/// directory at its own plugin.
+static CONFIG: Mutex<()> = Mutex::new(());
+
+/// Reads the `initialize` call and answers it with `provides`, leaving
+/// the id of each later call in `$id`.
+const PRELUDE: &str = r#"
+id() { printf '%s\n' "$1" | sed 's/^.*"id":\([0-9]*\).*$/\1/'; }
+answer() { printf '{"jsonrpc":"2.0","id":%s,"result":%s}\n' "$1" "$2"; }
+read -r line
+answer "$(id "$line")" "$PROVIDES"
+"#;
+
+/// Runs the editor on a buffer holding `text`, with `script` as its only
+/// plugin, until `backend` runs out of events.
+fn run(name: &str, provides: &str, script: &str, text: &str, backend: Headless) -> Headless {
+    let _config = CONFIG.lock().unwrap_or_else(|error| error.into_inner());
+    let directory = format!("target/plugin/{}", name);
+    let plugins = format!("{}/nep/plugins", directory);
+    fs::create_dir_all(&plugins).unwrap();
+    let path = format!("{}/{}", plugins, name);
+    let source = format!("#!/bin/sh\nPROVIDES='{}'\n{}{}", provides, PRELUDE, script);
+    fs::write(&path, source).unwrap();
+    fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+    std::env::set_var("XDG_CONFIG_HOME", &directory);
+
+    let mut buffer = Buffer::new();
+    buffer.insert_str(0, 0, text);
+    Interface::from_buffers(backend, vec![buffer])
+        .start()
+        .unwrap()
+}
+
+/// The message in the status line.
+fn message(backend: &Headless) -> String {
+    let status = backend.screen().lines().last().unwrap().to_string();
+    status.trim_end_matches("nep").trim().to_string()
+}
+
+#[test]
+fn commands_are_answered_and_can_ask_the_editor() {
+    let count = r#"
+while read -r line; do
+    id=$(id "$line")
+    printf '{"jsonrpc":"2.0","id":"count","method":"buffer/lineCount"}\n'
+    read -r reply
+    lines=$(printf '%s\n' "$reply" | sed 's/^.*"result":\([0-9]*\).*$/\1/')
+    printf '{"jsonrpc":"2.0","method":"editor/message","params":{"text":"%s lines"}}\n' "$lines"
+    answer "$id" null
+done
+"#;
+    let mut backend = Headless::new(60, 5);
+    backend.type_text(":count\n");
+    let backend = run(
+        "count",
+        r#"{"commands":["count"]}"#,
+        count,
+        "one\ntwo\nthree",
+        backend,
+    );
+
+    assert_eq!(message(&backend), "NORMAL [no name]* [1/1]  3 lines");
+}
+
+#[test]
+fn plugins_which_stop_answering_are_shut_down() {
+    let hang = "
+read -r line
+exec sleep 10
+";
+    let mut backend = Headless::new(60, 5);
+    backend.type_text(":hang\n:hang\n");
+    let start = Instant::now();
+    let backend = run("hang", r#"{"commands":["hang"]}"#, hang, "", backend);
+
+    // Only the first call is waited for, as the plugin is gone by the
+    // second.
+    let waited = start.elapsed();
+    assert!(waited >= Duration::from_secs(2), "{:?}", waited);
+    assert!(waited < Duration::from_secs(4), "{:?}", waited);
+    assert_eq!(message(&backend), "NORMAL [no name] [1/1]  no command hang");
+}
+
+#[test]
+fn plugins_which_stop_reading_are_shut_down() {
+    // Answers every call ahead of time without reading any of them, so the
+    // editor fills the pipe to the plugin and then its queue.
+    let deaf = r#"
+i=2
+while [ $i -le 5000 ]; do
+    answer $i null
+    i=$((i + 1))
+done
+exec sleep 60
+"#;
+    let mut backend = Headless::new(80, 5);
+    backend.press(Key::Char('i'));
+    for _ in 0..100 {
+        backend.type_text("some text to fill the pipe with\n");
+    }
+    backend.press(Key::Esc);
+    let start = Instant::now();
+    let backend = run("deaf", r#"{"hooks":["edit"]}"#, deaf, "", backend);
+
+    // It was never waited on.
+    assert!(start.elapsed() < Duration::from_secs(2));
+    assert_eq!(
+        message(&backend),
+        "NORMAL [no name]* [1/1]  plugin deaf stopped reading its input, stopping it"
+    );
+}
+
+#[test]
+fn plugins_which_crash_are_shut_down() {
+    let crash = "
+read -r line
+exit 1
+";
+    let mut backend = Headless::new(70, 5);
+    backend.type_text(":crash\n");
+    let crashed = run("crash", r#"{"commands":["crash"]}"#, crash, "", backend);
+    assert_eq!(
+        message(&crashed),
+        "NORMAL [no name] [1/1]  plugin crash exited, stopping it"
+    );
+
+    // Its commands go with it.
+    let mut backend = Headless::new(70, 5);
+    backend.type_text(":crash\n:crash\n");
+    let again = run("crash", r#"{"commands":["crash"]}"#, crash, "", backend);
+    assert_eq!(message(&again), "NORMAL [no name] [1/1]  no command crash");
+}
@@ -0,0 +1,147 @@
+//! Random sequences of edits and motions, run against `Buffer` and `Cursor`
+//! and checked against a model of the text as a plain string.
+
+extern crate nep;
+extern crate proptest;
+
+mod model;
+
+use std::fs;
+
+use nep::buffer::{Buffer, LineEnding};
+use nep::mode::Motion;
+use proptest::prelude::*;
+use proptest::sample::select;
+
+use model::{Op, CHARACTERS};
+
+/// Positions which are sometimes past the end of the buffer or line.
+fn position() -> impl Strategy<Value = (usize, usize)> {
+    (0..8usize, 0..12usize)
+}
+
+fn text() -> impl Strategy<Value = String> {
+    let mut characters = CHARACTERS.to_vec();
+    characters.push('\n');
+    prop::collection::vec(select(characters), 0..8).prop_map(|text| text.into_iter().collect())
+}
+
+/// The contents of a file, mixing line endings, sometimes without a newline
+/// at the end and sometimes starting with a byte order mark.
+fn file() -> impl Strategy<Value = String> {
+    let pieces = select(vec!["a", "é", "\n", "\r\n", "\r", "\u{feff}"]);
+    (any::<bool>(), prop::collection::vec(pieces, 0..12)).prop_map(|(bom, pieces)| {
+        let start = if bom { "\u{feff}" } else { "" };
+        start.to_string() + &pieces.concat()
+    })
+}
+
+fn motion() -> impl Strategy<Value = Motion> {
+    prop_oneof![
+        Just(Motion::Left),
+        Just(Motion::Right),
+        Just(Motion::Up),
+        Just(Motion::Down),
+        Just(Motion::WordForward),
+        Just(Motion::WordBackward),
+        Just(Motion::WordEnd),
+        Just(Motion::LineStart),
+        Just(Motion::FirstNonBlank),
+        Just(Motion::LineEnd),
+        prop::option::of(0..10usize).prop_map(Motion::Line),
+    ]
+}
+
+fn edit() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        4 => (position(), select(CHARACTERS)).prop_map(|(at, c)| Op::Insert(at, c)),
+        2 => position().prop_map(Op::Delete),
+        2 => position().prop_map(Op::Split),
+        2 => (0..8usize).prop_map(Op::Merge),
+        2 => (position(), text()).prop_map(|(at, text)| Op::InsertStr(at, text)),
+        2 => (position(), position()).prop_map(|(start, end)| Op::DeleteRange(start, end)),
+        1 => Just(Op::Checkpoint),
+    ]
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    prop_oneof![
+        8 => edit(),
+        2 => Just(Op::Undo),
+        1 => Just(Op::Redo),
+        2 => (0..10usize, 0..14usize).prop_map(Op::Goto),
+        6 => motion().prop_map(Op::Move),
+        1 => any::<bool>().prop_map(Op::PastEnd),
+        1 => Just(Op::NextMatch),
+        1 => Just(Op::Begin),
+        1 => Just(Op::Commit),
+        1 => Just(Op::Abort),
+    ]
+}
+
+fn contents(buffer: &Buffer) -> Vec<String> {
+    (0..buffer.len())
+        .map(|line| buffer.borrow_line(line).as_str().to_string())
+        .collect()
+}
+
+proptest! {
+    #[test]
+    fn edits_and_motions_match_the_model(ops in prop::collection::vec(op(), 0..64)) {
+        model::check(&ops);
+    }
+
+    /// However edits were grouped into undo steps, undoing them all gives
+    /// back the empty buffer and redoing them all gives back the edited one.
+    #[test]
+    fn undo_and_redo_go_all_the_way(ops in prop::collection::vec(edit(), 0..64)) {
+        let mut buffer = Buffer::new();
+        for op in &ops {
+            op.edit(&mut buffer);
+        }
+        let edited = contents(&buffer);
+
+        while buffer.undo().is_some() {}
+        prop_assert_eq!(contents(&buffer), vec![String::new()]);
+        while buffer.redo().is_some() {}
+        prop_assert_eq!(contents(&buffer), edited);
+    }
+
+    /// Opening a file and saving it straight away leaves it as it was.
+    #[test]
+    fn files_are_written_back_unchanged(contents in file()) {
+        fs::create_dir_all("target/properties").unwrap();
+        let path = "target/properties/written_back";
+        fs::write(path, &contents).unwrap();
+
+        let buffer = Buffer::from(path.to_string()).unwrap();
+        buffer.snapshot().unwrap().write_back(|_| ()).unwrap();
+        prop_assert_eq!(fs::read_to_string(path).unwrap(), contents);
+    }
+
+    /// Converting a file's line endings changes each line break to the new
+    /// one, taking at most the one `\r` before it along, and leaves every
+    /// other `\r` alone.
+    #[test]
+    fn line_endings_are_converted(contents in file(), crlf in any::<bool>()) {
+        fs::create_dir_all("target/properties").unwrap();
+        let path = "target/properties/converted";
+        fs::write(path, &contents).unwrap();
+
+        let line_ending = if crlf { LineEnding::CrLf } else { LineEnding::Lf };
+        let mut buffer = Buffer::from(path.to_string()).unwrap();
+        buffer.set_line_ending(line_ending);
+        buffer.snapshot().unwrap().write_back(|_| ()).unwrap();
+
+        let ending = if crlf { "\r\n" } else { "\n" };
+        let expected = contents.replace("\r\n", "\n").replace('\n', ending);
+        prop_assert_eq!(fs::read_to_string(path).unwrap(), expected);
+    }
+
+    /// Whatever bytes the fuzz target is given are turned into operations
+    /// which the buffer and cursor cope with.
+    #[test]
+    fn fuzz_input_is_handled(bytes in prop::collection::vec(any::<u8>(), 0..256)) {
+        model::check(&model::decode(&bytes));
+    }
+}
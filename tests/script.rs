@@ -0,0 +1,140 @@
+//! Runs the editor with an `init.nep`, checking what the scripting language
+//! does with it.
+
+extern crate nep;
+extern crate termion;
+
+use std::fs;
+use std::sync::Mutex;
+
+use nep::backend::Headless;
+use nep::buffer::Buffer;
+use nep::interface::Interface;
+use termion::event::Key;
+
+/// Held while the editor runs, as each test points the configuration
+/// directory at its own `init.nep`.
+static CONFIG: Mutex<()> = Mutex::new(());
+
+/// Runs the editor on an empty buffer with `init` as its `init.nep`, until
+/// `backend` runs out of events.
+fn run(name: &str, init: &str, backend: Headless) -> Headless {
+    let _config = CONFIG.lock().unwrap_or_else(|error| error.into_inner());
+    let directory = format!("target/script/{}", name);
+    fs::create_dir_all(format!("{}/nep", directory)).unwrap();
+    fs::write(format!("{}/nep/init.nep", directory), init).unwrap();
+    std::env::set_var("XDG_CONFIG_HOME", &directory);
+    Interface::from_buffers(backend, vec![Buffer::new()])
+        .start()
+        .unwrap()
+}
+
+/// The message in the status line after running `init` on its own.
+fn message(name: &str, init: &str) -> String {
+    let backend = run(name, init, Headless::new(100, 3));
+    let status = backend.screen().lines().last().unwrap().to_string();
+    status.trim_end_matches("nep").trim().to_string()
+}
+
+#[test]
+fn tokenizer_errors_give_their_line() {
+    assert_eq!(
+        message("unterminated", "let a = 1\nlet b = \"text"),
+        "NORMAL [no name] [1/1]  init.nep line 2: unterminated string"
+    );
+    assert_eq!(
+        message("unexpected", "let a = 1\n\nlet b = a @ 2"),
+        "NORMAL [no name] [1/1]  init.nep line 3: unexpected @"
+    );
+}
+
+#[test]
+fn parser_errors_give_their_line() {
+    assert_eq!(
+        message("parser", "fn f() {\n    let = 2\n}"),
+        "NORMAL [no name] [1/1]  init.nep line 2: expected a name"
+    );
+}
+
+#[test]
+fn runaway_loops_are_stopped() {
+    assert_eq!(
+        message("runaway", "let n = 0\nwhile true {\n    n = n + 1\n}"),
+        "NORMAL [no name] [1/1]  init.nep line 3: took too long, stopping it"
+    );
+}
+
+const UPCASE: &str = "
+fn upcase() {
+    let line = cursor_line()
+    let text = get_line(line)
+    delete(line, 0, line, len(text))
+    insert(line, 0, upper(text))
+}
+
+command(\"upcase\", upcase)
+bind(\"C-u\", \"upcase\")
+";
+
+#[test]
+fn commands_and_bindings_are_defined() {
+    let mut backend = Headless::new(40, 4);
+    backend.type_text("iab\ncd");
+    backend.press(Key::Esc);
+    backend.press(Key::Ctrl('u'));
+    backend.type_text("k:upcase\n");
+    let backend = run("upcase", UPCASE, backend);
+
+    assert_eq!(
+        backend.screen(),
+        " 1 AB\n 2 CD\n~\n NORMAL [no name]* [1/1]            nep\n"
+    );
+}
+
+#[test]
+fn failed_scripts_take_back_their_edits() {
+    let init = "
+insert(0, 0, \"kept?\")
+fn broken() {
+    insert(0, 0, \"no\")
+    missing()
+}
+command(\"broken\", broken)
+missing()
+";
+    let mut backend = Headless::new(70, 4);
+    backend.type_text("iab");
+    backend.press(Key::Esc);
+    backend.type_text(":broken\n");
+    let broken = run("broken", init, backend);
+    // Neither what `init.nep` inserted before failing nor what the command
+    // did is left in the buffer.
+    assert_eq!(
+        broken.screen(),
+        " 1 ab\n~\n~\n NORMAL [no name]* [1/1]  broken: line 5: no variable missing     nep\n"
+    );
+
+    // Nothing the command did is left to undo, so undoing takes back the
+    // typing before it.
+    let mut backend = Headless::new(70, 4);
+    backend.type_text("iab");
+    backend.press(Key::Esc);
+    backend.type_text(":broken\nu");
+    let undone = run("broken", init, backend);
+    assert_eq!(undone.screen().lines().next(), Some(" 1"));
+}
+
+#[test]
+fn inserted_newlines_split_the_line() {
+    let init = "
+action(\"insert\")
+action(\"insert_char a\")
+action(\"insert_char \\n\")
+action(\"insert_char b\")
+";
+    let backend = run("newline", init, Headless::new(40, 4));
+    assert_eq!(
+        backend.screen(),
+        " 1 a\n 2 b\n~\n INSERT [no name]* [1/1]            nep\n"
+    );
+}